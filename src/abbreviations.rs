@@ -0,0 +1,153 @@
+use crate::editor_state::{EditorState, ModeKind};
+use std::collections::BTreeMap;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum AbbreviationError {
+    #[error("No such abbreviation: {0}")]
+    NoSuchAbbreviationError(String),
+}
+
+/// The abbreviations of insert mode (`:iabbrev`) and of the command line (`:cabbrev`), the editor
+/// has one set and every buffer has its own (`:iabbrev <buffer>`), which goes first
+#[derive(Clone, Default)]
+pub(crate) struct Abbreviations {
+    insert: BTreeMap<String, String>,
+    command: BTreeMap<String, String>,
+}
+
+impl Abbreviations {
+    /// The abbreviations of `mode`, every mode but command mode uses the insert mode ones
+    pub(crate) fn get(&self, mode: ModeKind) -> &BTreeMap<String, String> {
+        match mode {
+            ModeKind::Command => &self.command,
+            _ => &self.insert,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, mode: ModeKind) -> &mut BTreeMap<String, String> {
+        match mode {
+            ModeKind::Command => &mut self.command,
+            _ => &mut self.insert,
+        }
+    }
+}
+
+fn is_keyword(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether typing `atom` expands an abbreviation in front of the cursor: any character that can
+/// not be part of a word does, and so do Enter and Tab
+pub(crate) fn triggers(atom: crate::motion_interpreter::MotionAtom) -> bool {
+    use crossterm::event::{KeyCode, KeyModifiers};
+    match atom.code {
+        KeyCode::Char(c) => !is_keyword(c) && (atom.modifiers - KeyModifiers::SHIFT).is_empty(),
+        KeyCode::Enter | KeyCode::Tab => atom.modifiers.is_empty(),
+        _ => false,
+    }
+}
+
+/// The abbreviation `text` (what is in front of the cursor) ends with, as the number of characters
+/// to replace and their replacement. An abbreviation has to start a word, so `teh` is not expanded
+/// in `uteh`.
+fn expansion<'a>(
+    abbreviations: [&'a Abbreviations; 2],
+    mode: ModeKind,
+    text: &[char],
+) -> Option<(usize, &'a str)> {
+    abbreviations.into_iter().find_map(|abbreviations| {
+        abbreviations.get(mode).iter().find_map(|(lhs, rhs)| {
+            let lhs = lhs.chars().collect::<Vec<_>>();
+            if !text.ends_with(&lhs) {
+                return None;
+            }
+            let starts_word = match text[..text.len() - lhs.len()].last() {
+                Some(&before) => {
+                    before.is_whitespace()
+                        || (lhs.first().is_some_and(|&c| is_keyword(c)) && !is_keyword(before))
+                }
+                None => true,
+            };
+            starts_word.then_some((lhs.len(), rhs.as_str()))
+        })
+    })
+}
+
+/// The edit that expands the insert mode abbreviation in front of `head`, if there is one
+pub(crate) fn insert_edit(
+    buffer: &crate::editor_buffer::Buffer,
+    editor_globals: &crate::editor_state::EditorGlobals,
+    head: usize,
+) -> Option<crate::editor_buffer::CursorEdit> {
+    let text = buffer.content.slice(buffer.line_start(head)..head);
+    let abbreviations = [&buffer.abbreviations, &editor_globals.abbreviations];
+    let (len, rhs) = expansion(abbreviations, ModeKind::Insert, &text)?;
+    Some(crate::editor_buffer::CursorEdit::before(
+        head,
+        len,
+        rhs.chars().collect(),
+    ))
+}
+
+/// Expands the command line abbreviation at the end of the command line input
+pub(crate) fn expand_command_line(ed_state: &mut EditorState) {
+    let input = ed_state.command_line.input.chars().collect::<Vec<_>>();
+    let abbreviations = [
+        &ed_state.get_buffer().abbreviations,
+        &ed_state.editor_globals.abbreviations,
+    ];
+    if let Some((len, rhs)) = expansion(abbreviations, ModeKind::Command, &input) {
+        let rhs = rhs.to_string();
+        let mut input = input[..input.len() - len].iter().collect::<String>();
+        input.push_str(&rhs);
+        ed_state.command_line.input = input;
+    }
+}
+
+fn abbreviations_mut(ed_state: &mut EditorState, buffer_local: bool) -> &mut Abbreviations {
+    if buffer_local {
+        &mut ed_state.get_buffer_mut().abbreviations
+    } else {
+        &mut ed_state.editor_globals.abbreviations
+    }
+}
+
+pub(crate) fn define(
+    ed_state: &mut EditorState,
+    modes: &[ModeKind],
+    buffer_local: bool,
+    lhs: &str,
+    rhs: &str,
+) {
+    let abbreviations = abbreviations_mut(ed_state, buffer_local);
+    for &mode in modes {
+        abbreviations
+            .get_mut(mode)
+            .insert(lhs.to_string(), rhs.to_string());
+    }
+}
+
+/// Removes the abbreviation `lhs` of `modes`, it is an error if none of them has it
+pub(crate) fn remove(
+    ed_state: &mut EditorState,
+    modes: &[ModeKind],
+    buffer_local: bool,
+    lhs: &str,
+) -> Result<(), AbbreviationError> {
+    let abbreviations = abbreviations_mut(ed_state, buffer_local);
+    let mut removed = false;
+    for &mode in modes {
+        removed |= abbreviations.get_mut(mode).remove(lhs).is_some();
+    }
+    match removed {
+        true => Ok(()),
+        false => Err(AbbreviationError::NoSuchAbbreviationError(lhs.to_string())),
+    }
+}
+
+pub(crate) fn clear(ed_state: &mut EditorState, modes: &[ModeKind], buffer_local: bool) {
+    let abbreviations = abbreviations_mut(ed_state, buffer_local);
+    for &mode in modes {
+        abbreviations.get_mut(mode).clear();
+    }
+}
@@ -0,0 +1,73 @@
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct AnchorId(usize);
+
+/// Positions in a buffer that move along with the text around them when the buffer is edited, so
+/// things like jumplist entries keep pointing at the same text
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Anchors {
+    offsets: Vec<Option<usize>>,
+    free: Vec<usize>,
+}
+
+impl Anchors {
+    pub(crate) fn insert(&mut self, offset: usize) -> AnchorId {
+        match self.free.pop() {
+            Some(idx) => {
+                self.offsets[idx] = Some(offset);
+                AnchorId(idx)
+            }
+            None => {
+                self.offsets.push(Some(offset));
+                AnchorId(self.offsets.len() - 1)
+            }
+        }
+    }
+
+    pub(crate) fn get(&self, id: AnchorId) -> Option<usize> {
+        self.offsets.get(id.0).copied().flatten()
+    }
+
+    pub(crate) fn set(&mut self, id: AnchorId, offset: usize) {
+        if let Some(anchor @ Some(_)) = self.offsets.get_mut(id.0) {
+            *anchor = Some(offset);
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: AnchorId) {
+        if let Some(anchor) = self.offsets.get_mut(id.0) {
+            if anchor.take().is_some() {
+                self.free.push(id.0);
+            }
+        }
+    }
+
+    /// Moves the anchors after `range` was replaced with `inserted` characters
+    pub(crate) fn shift(&mut self, range: &Range<usize>, inserted: usize) {
+        for offset in self.offsets.iter_mut().flatten() {
+            *offset = crate::transaction::shift_offset(*offset, range, inserted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchors_move_with_text() {
+        let mut anchors = Anchors::default();
+        let before = anchors.insert(2);
+        let inside = anchors.insert(6);
+        let after = anchors.insert(10);
+        anchors.shift(&(4..8), 1);
+        assert_eq!(anchors.get(before), Some(2));
+        assert_eq!(anchors.get(inside), Some(5));
+        assert_eq!(anchors.get(after), Some(7));
+        anchors.remove(inside);
+        assert_eq!(anchors.get(inside), None);
+        // removed slots are reused
+        assert_eq!(anchors.insert(0), inside);
+    }
+}
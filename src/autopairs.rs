@@ -0,0 +1,117 @@
+use crate::editor_buffer::{Buffer, CursorEdit};
+use crate::editor_state::EditorGlobals;
+
+/// The (opener, closer) pairs of `buffer`: the ones the `pairs` option sets for its filetype, or
+/// the defaults of the filetype
+fn pairs(buffer: &Buffer, editor_globals: &EditorGlobals) -> Vec<(char, char)> {
+    let filetype = crate::filetype::detect(buffer);
+    let pairs = editor_globals
+        .pairs
+        .iter()
+        .find(|(name, _)| name == filetype)
+        .map_or_else(
+            || crate::filetype::pairs(filetype),
+            |(_, pairs)| pairs.as_str(),
+        );
+    let chars = pairs.chars().collect::<Vec<_>>();
+    chars
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect()
+}
+
+fn is_word(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// The pair whose opener is in front of `head` and whose closer is behind it
+fn surrounding_pair(
+    buffer: &Buffer,
+    editor_globals: &EditorGlobals,
+    head: usize,
+) -> Option<(char, char)> {
+    let before = buffer.char_at(head.checked_sub(1)?)?;
+    let after = buffer.char_at(head)?;
+    pairs(buffer, editor_globals)
+        .into_iter()
+        .find(|&pair| pair == (before, after))
+}
+
+/// Typing `c` skips over the next character if it is the same closer, an opener gets its closer
+/// inserted behind the cursor. Quotes in words (like in `don't`) and openers right in front of a
+/// word are typed as usual, as is everything else (`None`).
+pub(crate) fn char_edit(
+    buffer: &Buffer,
+    editor_globals: &EditorGlobals,
+    head: usize,
+    c: char,
+) -> Option<CursorEdit> {
+    if !editor_globals.auto_pairs {
+        return None;
+    }
+    let pairs = pairs(buffer, editor_globals);
+    let next = buffer.char_at(head);
+    if next == Some(c) && pairs.iter().any(|&(_, closer)| closer == c) {
+        return Some(CursorEdit {
+            range: head..head + 1,
+            text: vec![c],
+            cursor: 1,
+        });
+    }
+    let &(opener, closer) = pairs.iter().find(|&&(opener, _)| opener == c)?;
+    let previous = head
+        .checked_sub(1)
+        .and_then(|offset| buffer.char_at(offset));
+    if is_word(next) || (opener == closer && is_word(previous)) {
+        return None;
+    }
+    Some(CursorEdit {
+        range: head..head,
+        text: vec![opener, closer],
+        cursor: 1,
+    })
+}
+
+/// Backspace between an empty pair deletes both of them
+pub(crate) fn backspace_edit(
+    buffer: &Buffer,
+    editor_globals: &EditorGlobals,
+    head: usize,
+) -> Option<CursorEdit> {
+    if !editor_globals.auto_pairs {
+        return None;
+    }
+    surrounding_pair(buffer, editor_globals, head)?;
+    Some(CursorEdit {
+        range: head - 1..head + 1,
+        text: Vec::new(),
+        cursor: 0,
+    })
+}
+
+/// Enter between brackets opens an indented block, with the closer on a line of its own below
+pub(crate) fn newline_edit(
+    buffer: &Buffer,
+    editor_globals: &EditorGlobals,
+    head: usize,
+) -> Option<CursorEdit> {
+    if !editor_globals.auto_pairs {
+        return None;
+    }
+    let (opener, closer) = surrounding_pair(buffer, editor_globals, head)?;
+    if opener == closer {
+        return None;
+    }
+    let width = crate::indent::indent_width(buffer, head, editor_globals.tab_size);
+    let inner = width + crate::indent::shift_width(editor_globals);
+    let mut text = vec!['\n'];
+    text.extend(crate::indent::whitespace(editor_globals, 0, inner));
+    let cursor = text.len();
+    text.push('\n');
+    text.extend(crate::indent::whitespace(editor_globals, 0, width));
+    Some(CursorEdit {
+        range: head..head,
+        text,
+        cursor,
+    })
+}
@@ -0,0 +1,320 @@
+use crate::editor_state::{EditorGlobals, EditorState, EditorStateUpdate};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The registers that go to the system clipboard: `"+` is the clipboard, `"*` the primary
+/// selection (the same thing for providers that only know one clipboard)
+pub(crate) const CLIPBOARD_REGISTERS: &str = "+*";
+
+/// Ways to reach the system clipboard, the `clipboard` option lists them in the order they are
+/// tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Provider {
+    /// the `clipcopy` and `clippaste` commands
+    Custom,
+    /// wl-copy and wl-paste
+    Wayland,
+    Xclip,
+    Xsel,
+    /// the OSC 52 escape sequence, which makes the terminal set the clipboard (also over SSH). It
+    /// can only copy.
+    Osc52,
+}
+
+const PROVIDER_NAMES: [(&str, Provider); 5] = [
+    ("custom", Provider::Custom),
+    ("wayland", Provider::Wayland),
+    ("xclip", Provider::Xclip),
+    ("xsel", Provider::Xsel),
+    ("osc52", Provider::Osc52),
+];
+
+pub(crate) fn default_providers() -> Vec<Provider> {
+    PROVIDER_NAMES.iter().map(|&(_, provider)| provider).collect()
+}
+
+/// Parses a comma separated list of provider names, `None` if one of them is unknown
+pub(crate) fn parse_providers(value: &str) -> Option<Vec<Provider>> {
+    value
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            PROVIDER_NAMES
+                .iter()
+                .find(|&&(provider_name, _)| provider_name == name)
+                .map(|&(_, provider)| provider)
+        })
+        .collect()
+}
+
+pub(crate) fn format_providers(providers: &[Provider]) -> String {
+    providers
+        .iter()
+        .filter_map(|provider| {
+            PROVIDER_NAMES
+                .iter()
+                .find(|(_, named)| named == provider)
+                .map(|(name, _)| *name)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn in_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|path| {
+        std::env::split_paths(&path).any(|dir| Path::new(&dir).join(program).is_file())
+    })
+}
+
+/// The program and arguments that copy to (or paste from, if `paste`) the clipboard of `register`
+fn command(
+    provider: Provider,
+    register: char,
+    paste: bool,
+    editor_globals: &EditorGlobals,
+) -> Option<(String, Vec<String>)> {
+    let primary = register == '*';
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    let (program, args) = match provider {
+        Provider::Custom => {
+            let command = if paste {
+                &editor_globals.clip_paste
+            } else {
+                &editor_globals.clip_copy
+            };
+            if command.is_empty() {
+                return None;
+            }
+            ("sh", vec!["-c".to_string(), command.clone()])
+        }
+        Provider::Wayland => {
+            std::env::var_os("WAYLAND_DISPLAY")?;
+            let mut wl_args = if paste {
+                args(&["--no-newline"])
+            } else {
+                Vec::new()
+            };
+            if primary {
+                wl_args.push("--primary".to_string());
+            }
+            (if paste { "wl-paste" } else { "wl-copy" }, wl_args)
+        }
+        Provider::Xclip => {
+            std::env::var_os("DISPLAY")?;
+            let selection = if primary { "primary" } else { "clipboard" };
+            let mut xclip_args = args(&["-selection", selection]);
+            if paste {
+                xclip_args.push("-o".to_string());
+            }
+            ("xclip", xclip_args)
+        }
+        Provider::Xsel => {
+            std::env::var_os("DISPLAY")?;
+            let selection = if primary { "--primary" } else { "--clipboard" };
+            let direction = if paste { "--output" } else { "--input" };
+            ("xsel", args(&[selection, direction]))
+        }
+        Provider::Osc52 => return None,
+    };
+    in_path(program).then(|| (program.to_string(), args))
+}
+
+fn run_copy(program: &str, args: &[String], text: &str) -> bool {
+    let Ok(mut child) = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    else {
+        return false;
+    };
+    let written = child
+        .stdin
+        .take()
+        .is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+    // the tools fork to keep serving the clipboard, so this does not wait for long
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+fn run_paste(program: &str, args: &[String]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// The escape sequence that sets the clipboard (`c`) or primary selection (`p`) to `text`
+pub(crate) fn osc52(register: char, text: &str) -> String {
+    let selection = if register == '*' { 'p' } else { 'c' };
+    format!("\x1b]52;{};{}\x07", selection, base64(text.as_bytes()))
+}
+
+/// What a clipboard task reports back to the editor once its tools are done
+pub(crate) enum ClipboardUpdate {
+    /// none of the copy tools worked, the text goes out with OSC 52 if `osc52` is listed after
+    /// them
+    CopyFailed {
+        register: char,
+        text: String,
+        osc52: bool,
+    },
+    /// the clipboard of `register` was read (`None` if no tool could), `then` runs with the text
+    /// in the register
+    Pasted {
+        register: char,
+        text: Option<String>,
+        then: crate::motion_interpreter::MotionFunction,
+    },
+}
+
+/// Writes the OSC 52 sequence with the next frame, or tells that nothing could copy the text
+fn copy_fallback(ed_state: &mut EditorState, register: char, text: &str, osc52_listed: bool) {
+    if osc52_listed {
+        ed_state.terminal_output.push(osc52(register, text));
+        return;
+    }
+    writeln!(ed_state.command_line, "No clipboard provider could copy the text")
+        .expect("Fatal: Could not write to command line buffer");
+}
+
+/// Copies `text` that was yanked into `register` with the first provider that works. The tools run
+/// in the background, so a slow one does not hold up the editor.
+pub(crate) fn copy(ed_state: &mut EditorState, register: char, text: &str) {
+    let providers = &ed_state.editor_globals.clipboard_providers;
+    // the providers after OSC 52 are never needed, it always works
+    let osc52_listed = providers.contains(&Provider::Osc52);
+    let commands = providers
+        .iter()
+        .take_while(|&&provider| provider != Provider::Osc52)
+        .filter_map(|&provider| command(provider, register, false, &ed_state.editor_globals))
+        .collect::<Vec<_>>();
+    if commands.is_empty() {
+        return copy_fallback(ed_state, register, text, osc52_listed);
+    }
+    let text = text.to_string();
+    ed_state.scheduler.spawn(Box::pin(async move {
+        let copy_text = text.clone();
+        let copied = crate::scheduler::spawn_blocking(move || {
+            commands
+                .iter()
+                .any(|(program, args)| run_copy(program, args, &copy_text))
+        })
+        .await
+        .unwrap_or(false);
+        if copied {
+            return EditorStateUpdate::None;
+        }
+        EditorStateUpdate::Clipboard(ClipboardUpdate::CopyFailed {
+            register,
+            text,
+            osc52: osc52_listed,
+        })
+    }));
+}
+
+/// Reads the clipboard into `register` (if it is `"+` or `"*` and a provider can paste) in the
+/// background before running `then`, which reads the register. Without a provider the text yanked
+/// into the register last is used.
+pub(crate) fn paste(
+    ed_state: &EditorState,
+    register: char,
+    then: impl Fn(&EditorState) -> EditorStateUpdate + 'static,
+) -> EditorStateUpdate {
+    let editor_globals = &ed_state.editor_globals;
+    let commands = editor_globals
+        .clipboard_providers
+        .iter()
+        .filter(|_| CLIPBOARD_REGISTERS.contains(register))
+        .filter_map(|&provider| command(provider, register, true, editor_globals))
+        .collect::<Vec<_>>();
+    if commands.is_empty() {
+        return then(ed_state);
+    }
+    EditorStateUpdate::Spawn(Box::pin(async move {
+        let text = crate::scheduler::spawn_blocking(move || {
+            commands
+                .iter()
+                .find_map(|(program, args)| run_paste(program, args))
+        })
+        .await
+        .ok()
+        .flatten();
+        EditorStateUpdate::Clipboard(ClipboardUpdate::Pasted {
+            register,
+            text,
+            then: crate::motion_interpreter::MotionFunction(std::rc::Rc::new(then)),
+        })
+    }))
+}
+
+/// Applies what a clipboard task reported
+pub(crate) fn apply(ed_state: &mut EditorState, update: ClipboardUpdate) {
+    match update {
+        ClipboardUpdate::CopyFailed {
+            register,
+            text,
+            osc52,
+        } => copy_fallback(ed_state, register, &text, osc52),
+        ClipboardUpdate::Pasted {
+            register,
+            text,
+            then,
+        } => {
+            if let Some(text) = text {
+                ed_state.registers.set_clipboard(register, text);
+            }
+            let update = then.0(ed_state);
+            ed_state.apply(update);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(base64(b"miv"), "bWl2");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+        assert_eq!(osc52('+', "hi\n"), "\x1b]52;c;aGkK\x07");
+        assert_eq!(osc52('*', ""), "\x1b]52;p;\x07");
+    }
+    #[test]
+    fn test_provider_order() {
+        let providers = parse_providers("osc52,xclip").unwrap();
+        assert_eq!(providers, vec![Provider::Osc52, Provider::Xclip]);
+        assert_eq!(format_providers(&providers), "osc52,xclip");
+        assert_eq!(parse_providers("pbcopy"), None);
+    }
+}
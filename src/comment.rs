@@ -0,0 +1,124 @@
+use crate::editor_buffer::Buffer;
+use crate::editor_state::{EditorState, EditorStateUpdate};
+use crate::filetype::CommentString;
+use crate::transaction::Transaction;
+
+/// The range of the comment markers of the line starting at `start` (without the space between
+/// them and the text), if the line is commented out
+fn markers(
+    buffer: &Buffer,
+    start: usize,
+    comment: &CommentString,
+) -> Option<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+    let text_start = crate::text_motions::first_non_blank(buffer, start);
+    let mut text_end = buffer.line_end(start);
+    while text_end > text_start
+        && buffer
+            .char_at(text_end - 1)
+            .is_some_and(char::is_whitespace)
+    {
+        text_end -= 1;
+    }
+    let text = buffer.content.slice(text_start..text_end);
+    let opener = comment.start.chars().collect::<Vec<_>>();
+    let closer = comment.end.chars().collect::<Vec<_>>();
+    if !text.starts_with(&opener) || text.len() < opener.len() + closer.len() {
+        return None;
+    }
+    if !text[opener.len()..].ends_with(&closer) {
+        return None;
+    }
+    let mut opener_end = text_start + opener.len();
+    if opener_end < text_end - closer.len() && buffer.char_at(opener_end) == Some(' ') {
+        opener_end += 1;
+    }
+    let mut closer_start = text_end - closer.len();
+    if !closer.is_empty()
+        && closer_start > opener_end
+        && buffer.char_at(closer_start - 1) == Some(' ')
+    {
+        closer_start -= 1;
+    }
+    Some((text_start..opener_end, closer_start..text_end))
+}
+
+/// `gc`: comments out the lines of `spans` with the comment string of the filetype, or uncomments
+/// them if all of them are commented out already. The markers go after the indentation (the
+/// smallest one of the lines), blank lines are left alone.
+pub(crate) fn toggle(ed_state: &EditorState, spans: &[(usize, usize)]) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let comment = crate::filetype::comment_string(crate::filetype::detect(buffer));
+    let lines = spans
+        .iter()
+        .flat_map(|&(first, last)| first..=last)
+        .map(|line| buffer.line_offset(line))
+        .filter(|&start| {
+            crate::text_motions::first_non_blank(buffer, start) < buffer.line_end(start)
+        })
+        .collect::<Vec<_>>();
+    let commented = lines
+        .iter()
+        .map(|&start| markers(buffer, start, &comment))
+        .collect::<Option<Vec<_>>>();
+    let mut transaction = Transaction::new();
+    match commented {
+        Some(markers) if !markers.is_empty() => {
+            for (opener, closer) in markers.into_iter().rev() {
+                if !closer.is_empty() {
+                    transaction.delete(ed_state.buffer_idx, closer);
+                }
+                transaction.delete(ed_state.buffer_idx, opener);
+            }
+        }
+        _ => {
+            let indent = lines
+                .iter()
+                .map(|&start| crate::text_motions::first_non_blank(buffer, start) - start)
+                .min()
+                .unwrap_or(0);
+            for &start in lines.iter().rev() {
+                if !comment.end.is_empty() {
+                    let end = buffer.line_end(start);
+                    transaction.insert(ed_state.buffer_idx, end, &format!(" {}", comment.end));
+                }
+                let opener = format!("{} ", comment.start);
+                transaction.insert(ed_state.buffer_idx, start + indent, &opener);
+            }
+        }
+    }
+    crate::indent::finish(ed_state, spans, transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    fn run(text: &str, start: usize, filetype: &str) -> Option<(Range<usize>, Range<usize>)> {
+        let comment = crate::filetype::comment_string(filetype);
+        markers(&Buffer::from_text(text), start, &comment)
+    }
+
+    #[test]
+    fn test_block_comment_markers() {
+        assert_eq!(run("  /* a */", 0, "css"), Some((2..5, 6..9)));
+        assert_eq!(run("/*a*/", 0, "css"), Some((0..2, 3..5)));
+        // whitespace behind the closer is not part of it
+        assert_eq!(run("/* a */  ", 0, "css"), Some((0..3, 4..7)));
+        assert_eq!(run("/**/", 0, "css"), Some((0..2, 2..4)));
+        assert_eq!(run("x\n<!-- y -->", 2, "html"), Some((2..7, 8..12)));
+    }
+    #[test]
+    fn test_lines_that_are_not_commented_out() {
+        assert_eq!(run("/* a", 0, "css"), None);
+        assert_eq!(run("a */", 0, "css"), None);
+        // the opener and the closer can not share characters
+        assert_eq!(run("/*/", 0, "css"), None);
+        assert_eq!(run("// a", 0, "python"), None);
+    }
+    #[test]
+    fn test_line_comment_markers() {
+        assert_eq!(run("  # a", 0, "python"), Some((2..4, 5..5)));
+        assert_eq!(run("#a", 0, "python"), Some((0..1, 2..2)));
+    }
+}
@@ -0,0 +1,373 @@
+use crate::editor_buffer::{Buffer, CursorEdit};
+use crate::editor_state::EditorState;
+use std::fmt::Write as _;
+
+/// Where completion candidates come from, the `complete` option lists the ones <C-n> and <C-p>
+/// use (in order), <C-x> followed by <C-n>, <C-l>, <C-f> or <C-k> completes from a single one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Source {
+    /// words of all buffers, the ones closest to the cursor first
+    Keyword,
+    /// whole lines of all buffers, starting like the text in front of the cursor
+    Line,
+    /// file paths, relative to the working directory
+    File,
+    /// words of the files in the `dictionary` option
+    Dictionary,
+}
+
+const SOURCE_NAMES: [(&str, Source); 4] = [
+    ("keyword", Source::Keyword),
+    ("line", Source::Line),
+    ("file", Source::File),
+    ("dictionary", Source::Dictionary),
+];
+
+pub(crate) fn default_sources() -> Vec<Source> {
+    vec![Source::Keyword]
+}
+
+/// Parses a comma separated list of source names, `None` if one of them is unknown
+pub(crate) fn parse_sources(value: &str) -> Option<Vec<Source>> {
+    value
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            SOURCE_NAMES
+                .iter()
+                .find(|&&(source_name, _)| source_name == name)
+                .map(|&(_, source)| source)
+        })
+        .collect()
+}
+
+pub(crate) fn format_sources(sources: &[Source]) -> String {
+    sources
+        .iter()
+        .filter_map(|source| {
+            SOURCE_NAMES
+                .iter()
+                .find(|(_, named)| named == source)
+                .map(|(name, _)| *name)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Candidate {
+    pub(crate) text: String,
+    /// how many characters in front of the cursor the candidate takes the place of
+    pub(crate) replaces: usize,
+}
+
+/// An open completion popup
+#[derive(Debug, Clone)]
+pub(crate) struct Completion {
+    pub(crate) sources: Vec<Source>,
+    pub(crate) candidates: Vec<Candidate>,
+    /// the candidate in the buffer, `None` while the typed text is
+    pub(crate) selected: Option<usize>,
+    /// the typed text in front of the cursor that candidates replace
+    original: Vec<char>,
+    /// where the replaced text starts, the popup is drawn below it
+    pub(crate) anchor: usize,
+    // the popup closes once anything but completion changes the buffer or moves the cursor
+    buffer_idx: usize,
+    change_tick: usize,
+    cursor: usize,
+}
+
+impl Completion {
+    fn shown(&self) -> (usize, Vec<char>) {
+        match self.selected {
+            Some(i) => {
+                let candidate = &self.candidates[i];
+                (candidate.replaces, candidate.text.chars().collect())
+            }
+            None => (0, Vec::new()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CompletionCommand {
+    /// <C-n>/<C-p> go to the next or previous candidate, opening the popup with the candidates of
+    /// `source` (or the `complete` option) first if it is not open for them
+    Select {
+        forward: bool,
+        source: Option<Source>,
+    },
+    /// <C-y> keeps the selected candidate
+    Accept,
+    /// <C-e> goes back to the typed text
+    Cancel,
+}
+
+/// The completion popup if it is still open
+pub(crate) fn active(ed_state: &EditorState) -> Option<&Completion> {
+    let completion = ed_state.completion.as_ref()?;
+    let buffer = ed_state.get_buffer();
+    let open = ed_state.mode.is_typing()
+        && completion.buffer_idx == ed_state.buffer_idx
+        && completion.change_tick == buffer.change_tick
+        && completion.cursor == buffer.cursor();
+    open.then_some(completion)
+}
+
+fn is_keyword(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Where the run of characters matching `matches` in front of `head` starts
+fn run_start(buffer: &Buffer, head: usize, matches: impl Fn(char) -> bool) -> usize {
+    let line_start = buffer.line_start(head);
+    let mut start = head;
+    while start > line_start && buffer.char_at(start - 1).is_some_and(&matches) {
+        start -= 1;
+    }
+    start
+}
+
+/// The buffers to gather candidates from, the current one first
+fn buffers_by_closeness(ed_state: &EditorState) -> impl Iterator<Item = (usize, &Buffer)> {
+    std::iter::once(ed_state.buffer_idx)
+        .chain((0..ed_state.buffers.len()).filter(move |&i| i != ed_state.buffer_idx))
+        .map(|i| (i, &ed_state.buffers[i]))
+}
+
+/// Ranks `found` (distance from the cursor and text) by distance and drops duplicates
+fn ranked(mut found: Vec<(usize, String)>, replaces: usize) -> Vec<Candidate> {
+    found.sort_by_key(|&(distance, _)| distance);
+    let mut seen = std::collections::HashSet::new();
+    found
+        .into_iter()
+        .filter(|(_, text)| seen.insert(text.clone()))
+        .map(|(_, text)| Candidate { text, replaces })
+        .collect()
+}
+
+fn keywords(ed_state: &EditorState) -> Vec<Candidate> {
+    let buffer = ed_state.get_buffer();
+    let head = buffer.cursor();
+    let prefix_start = run_start(buffer, head, is_keyword);
+    let prefix = buffer
+        .content
+        .slice(prefix_start..head)
+        .iter()
+        .collect::<String>();
+    let mut found = Vec::new();
+    for (rank, (buffer_idx, other)) in buffers_by_closeness(ed_state).enumerate() {
+        let mut word = String::new();
+        let mut start = 0;
+        for (offset, &c) in other
+            .content
+            .iter()
+            .chain(std::iter::once(&'\n'))
+            .enumerate()
+        {
+            if is_keyword(c) {
+                if word.is_empty() {
+                    start = offset;
+                }
+                word.push(c);
+                continue;
+            }
+            let typed = buffer_idx == ed_state.buffer_idx && start == prefix_start;
+            if !typed && word.len() > prefix.len() && word.starts_with(&prefix) {
+                // words of other buffers come after the ones of the current buffer
+                let distance = match rank {
+                    0 => start.abs_diff(head),
+                    rank => usize::MAX / 2 + rank,
+                };
+                found.push((distance, std::mem::take(&mut word)));
+            }
+            word.clear();
+        }
+    }
+    ranked(found, prefix.chars().count())
+}
+
+fn lines(ed_state: &EditorState) -> Vec<Candidate> {
+    let buffer = ed_state.get_buffer();
+    let head = buffer.cursor();
+    let start = crate::text_motions::first_non_blank(buffer, head).min(head);
+    let prefix = buffer.content.slice(start..head).iter().collect::<String>();
+    let current_line = buffer.position(head).0;
+    let mut found = Vec::new();
+    for (rank, (buffer_idx, other)) in buffers_by_closeness(ed_state).enumerate() {
+        for line in 0..other.line_count() {
+            if buffer_idx == ed_state.buffer_idx && line == current_line {
+                continue;
+            }
+            let line_start = other.line_offset(line);
+            let text_start = crate::text_motions::first_non_blank(other, line_start);
+            let text = other
+                .content
+                .slice(text_start..other.line_end(line_start))
+                .iter()
+                .collect::<String>();
+            if text.len() > prefix.len() && text.starts_with(&prefix) {
+                let distance = match rank {
+                    0 => line.abs_diff(current_line),
+                    rank => usize::MAX / 2 + rank,
+                };
+                found.push((distance, text));
+            }
+        }
+    }
+    ranked(found, prefix.chars().count())
+}
+
+fn files(ed_state: &EditorState) -> Vec<Candidate> {
+    let buffer = ed_state.get_buffer();
+    let head = buffer.cursor();
+    let is_path = |c: char| !c.is_whitespace() && !"\"'`()[]{}<>,;".contains(c);
+    let start = run_start(buffer, head, is_path);
+    let prefix = buffer.content.slice(start..head).iter().collect::<String>();
+    let (dir, name) = match prefix.rsplit_once('/') {
+        Some((dir, name)) => (format!("{dir}/"), name),
+        None => (String::new(), prefix.as_str()),
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { &dir }) else {
+        return Vec::new();
+    };
+    let mut found = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            // hidden files only when their dot is typed
+            if !file_name.starts_with(name) || (file_name.starts_with('.') && name.is_empty()) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{dir}{file_name}{slash}"))
+        })
+        .collect::<Vec<_>>();
+    found.sort();
+    let replaces = prefix.chars().count();
+    found
+        .into_iter()
+        .map(|text| Candidate { text, replaces })
+        .collect()
+}
+
+fn dictionary(ed_state: &EditorState) -> Vec<Candidate> {
+    let buffer = ed_state.get_buffer();
+    let head = buffer.cursor();
+    let prefix_start = run_start(buffer, head, is_keyword);
+    let prefix = buffer
+        .content
+        .slice(prefix_start..head)
+        .iter()
+        .collect::<String>();
+    let mut found = Vec::new();
+    let paths = ed_state.editor_globals.dictionary.split(',');
+    // unreadable dictionaries are skipped like in vim
+    for text in paths.filter_map(|path| std::fs::read_to_string(path).ok()) {
+        found.extend(
+            text.split_whitespace()
+                .filter(|word| word.len() > prefix.len() && word.starts_with(&prefix))
+                .map(|word| (0, word.to_string())),
+        );
+    }
+    ranked(found, prefix.chars().count())
+}
+
+pub(crate) fn candidates(ed_state: &EditorState, source: Source) -> Vec<Candidate> {
+    match source {
+        Source::Keyword => keywords(ed_state),
+        Source::Line => lines(ed_state),
+        Source::File => files(ed_state),
+        Source::Dictionary => dictionary(ed_state),
+    }
+}
+
+/// Puts candidate `selected` (or the typed text) in the place of the one in the buffer at every
+/// cursor
+fn show(ed_state: &mut EditorState, selected: Option<usize>) {
+    let Some(completion) = ed_state.completion.as_mut() else {
+        return;
+    };
+    let (old_replaces, old_text) = completion.shown();
+    completion.selected = selected;
+    let (new_replaces, new_text) = completion.shown();
+    // typed text the old candidate took the place of comes back if the new one replaces less
+    let replaced = old_replaces.max(new_replaces);
+    let count = old_text.len() + replaced - old_replaces;
+    let original_len = completion.original.len();
+    let mut text =
+        completion.original[original_len - replaced..original_len - new_replaces].to_vec();
+    text.extend(new_text);
+    let buffer = &mut ed_state.buffers[ed_state.buffer_idx];
+    buffer.edit_at_cursors(|_, head| CursorEdit::before(head, count, text.clone()));
+    completion.change_tick = buffer.change_tick;
+    completion.cursor = buffer.cursor();
+    ed_state.registers.insert_deleted(count);
+    ed_state
+        .registers
+        .inserted(&text.iter().collect::<String>());
+}
+
+/// Opens the popup with the candidates of `sources` at the primary cursor
+fn start(ed_state: &mut EditorState, sources: Vec<Source>) -> bool {
+    let candidates = sources
+        .iter()
+        .flat_map(|&source| candidates(ed_state, source))
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        writeln!(ed_state.command_line, "No matches")
+            .expect("Fatal: Could not write to command line buffer");
+        ed_state.completion = None;
+        return false;
+    }
+    let buffer = ed_state.get_buffer();
+    let head = buffer.cursor();
+    let longest = candidates.iter().map(|c| c.replaces).max().unwrap_or(0);
+    ed_state.completion = Some(Completion {
+        sources,
+        original: buffer.content.slice(head - longest..head),
+        anchor: head - candidates[0].replaces,
+        candidates,
+        selected: None,
+        buffer_idx: ed_state.buffer_idx,
+        change_tick: buffer.change_tick,
+        cursor: head,
+    });
+    true
+}
+
+pub(crate) fn run(ed_state: &mut EditorState, command: CompletionCommand) {
+    match command {
+        CompletionCommand::Select { forward, source } => {
+            // <C-n>/<C-p> go on with whatever the popup shows
+            let open = active(ed_state).is_some_and(|completion| {
+                source.is_none_or(|source| completion.sources == [source])
+            });
+            let sources =
+                source.map_or_else(|| ed_state.editor_globals.complete.clone(), |s| vec![s]);
+            if !open && !start(ed_state, sources) {
+                return;
+            }
+            let Some(completion) = &ed_state.completion else {
+                return;
+            };
+            // the typed text comes after the last candidate (and before the first)
+            let len = completion.candidates.len();
+            let position = completion.selected.unwrap_or(len);
+            let position = if forward {
+                (position + 1) % (len + 1)
+            } else {
+                (position + len) % (len + 1)
+            };
+            show(ed_state, (position < len).then_some(position));
+        }
+        CompletionCommand::Accept => ed_state.completion = None,
+        CompletionCommand::Cancel => {
+            if active(ed_state).is_some() {
+                show(ed_state, None);
+            }
+            ed_state.completion = None;
+        }
+    }
+}
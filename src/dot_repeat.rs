@@ -0,0 +1,142 @@
+use crate::motion_interpreter::MotionAtom;
+
+/// A change that `.` can replay: the keys of the command that made it (after mappings were
+/// expanded, so they are replayed without remapping) including the keys typed in the insert
+/// session it started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Change {
+    pub(crate) count: Option<usize>,
+    pub(crate) keys: Vec<MotionAtom>,
+    /// for changes made in visual mode, the kind of visual mode and how far the selection reached
+    /// from its start (lines, columns), so the replay can select a region of the same size at the
+    /// cursor
+    pub(crate) visual_extent: Option<(crate::visual::VisualKind, isize, isize)>,
+}
+
+/// Records the keys of the command that is currently being executed and keeps the last one that
+/// changed a buffer
+#[derive(Debug, Default)]
+pub(crate) struct DotRepeat {
+    recording: Vec<MotionAtom>,
+    visual_extent: Option<(crate::visual::VisualKind, isize, isize)>,
+    count: Option<usize>,
+    in_insert_session: bool,
+    pub(crate) last_change: Option<Change>,
+}
+
+impl DotRepeat {
+    /// Records the keys of a motion that was resolved by the interpreter
+    pub(crate) fn record_keys(&mut self, keys: &[MotionAtom]) {
+        self.recording.extend_from_slice(keys);
+    }
+
+    pub(crate) fn in_insert_session(&self) -> bool {
+        self.in_insert_session
+    }
+
+    /// Called after the motion function of a command ran. `changed` tells whether it edited the
+    /// buffer, `started_insert` whether it went from normal or visual mode to insert mode (the
+    /// change then continues until insert mode is left again).
+    pub(crate) fn command_done(
+        &mut self,
+        count: Option<usize>,
+        visual_extent: Option<(crate::visual::VisualKind, isize, isize)>,
+        changed: bool,
+        started_insert: bool,
+        in_insert: bool,
+    ) {
+        if self.in_insert_session {
+            if !in_insert {
+                self.finish();
+            }
+            return;
+        }
+        if started_insert {
+            self.count = count;
+            self.visual_extent = visual_extent;
+            self.in_insert_session = true;
+        } else if changed {
+            self.count = count;
+            self.visual_extent = visual_extent;
+            self.finish();
+        } else {
+            self.recording.clear();
+        }
+    }
+
+    /// Drops the keys of a command that failed (keys typed in an insert session are kept, they
+    /// are self-inserted)
+    pub(crate) fn command_failed(&mut self) {
+        if !self.in_insert_session {
+            self.recording.clear();
+        }
+    }
+
+    fn finish(&mut self) {
+        self.in_insert_session = false;
+        self.last_change = Some(Change {
+            count: self.count.take(),
+            keys: std::mem::take(&mut self.recording),
+            visual_extent: self.visual_extent.take(),
+        });
+    }
+
+    /// The keys to feed back into the interpreter to repeat the last change. A new `count`
+    /// replaces the one the change was made with.
+    pub(crate) fn replay_keys(&self, count: Option<usize>) -> Option<Vec<MotionAtom>> {
+        let change = self.last_change.as_ref()?;
+        let mut keys = match count.or(change.count) {
+            Some(count) => count
+                .to_string()
+                .chars()
+                .map(|c| {
+                    MotionAtom::new(
+                        crossterm::event::KeyCode::Char(c),
+                        crossterm::event::KeyModifiers::NONE,
+                    )
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        keys.extend_from_slice(&change.keys);
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(notation: &str) -> Vec<MotionAtom> {
+        crate::key_notation::parse(notation).unwrap()
+    }
+
+    #[test]
+    fn test_non_changes_are_not_recorded() {
+        let mut dot_repeat = DotRepeat::default();
+        dot_repeat.record_keys(&keys("x"));
+        dot_repeat.command_done(None, None, true, false, false);
+        dot_repeat.record_keys(&keys("j"));
+        dot_repeat.command_done(None, None, false, false, false);
+        assert_eq!(dot_repeat.replay_keys(None), Some(keys("x")));
+    }
+    #[test]
+    fn test_insert_session_is_part_of_the_change() {
+        let mut dot_repeat = DotRepeat::default();
+        dot_repeat.record_keys(&keys("i"));
+        dot_repeat.command_done(Some(2), None, false, true, true);
+        dot_repeat.record_keys(&keys("ab"));
+        dot_repeat.command_failed();
+        assert!(dot_repeat.last_change.is_none());
+        dot_repeat.record_keys(&keys("<Esc>"));
+        dot_repeat.command_done(None, None, false, false, false);
+        assert_eq!(dot_repeat.replay_keys(None), Some(keys("2iab<Esc>")));
+    }
+    #[test]
+    fn test_new_count_replaces_old_one() {
+        let mut dot_repeat = DotRepeat::default();
+        dot_repeat.record_keys(&keys("x"));
+        dot_repeat.command_done(Some(3), None, true, false, false);
+        assert_eq!(dot_repeat.replay_keys(Some(12)), Some(keys("12x")));
+    }
+}
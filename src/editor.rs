@@ -1,153 +1,1585 @@
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind},
     terminal,
 };
 use std::fmt::Write;
 use std::rc::Rc;
 
-pub(crate) fn process(
-    event: event::Event,
-    ed_state: &mut crate::editor_state::EditorState,
-) -> Result<
-    Option<crate::motion_interpreter::MotionFunction>,
-    crate::motion_interpreter::MotionInterpreterError,
-> {
+/// How often the event loop checks on running tasks while waiting for input
+const TASK_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(16);
+
+pub(crate) fn process(event: event::Event, ed_state: &mut crate::editor_state::EditorState) {
     match event {
-        Event::FocusGained => Ok(None),
-        Event::FocusLost => Ok(None),
+        Event::FocusGained => {}
+        Event::FocusLost => {}
         Event::Key(key_event) => {
-            let KeyEvent {
-                code, modifiers, ..
-            } = key_event;
-            let motion_atom = crate::motion_interpreter::MotionAtom { code, modifiers };
-            let ed_state_updated_or_error = ed_state
-                .motion_interpreter_state
-                .clone() // this might hit performance hard because of heap allocations in vec
-                // (TODO: remove vec component and replace with slice?)
-                .update(ed_state.active_motion_tree(), motion_atom);
-            match ed_state_updated_or_error {
-                Ok(crate::motion_interpreter::MotionInterpreterState::Pending(
-                    motion_component_buffer,
-                )) => {
-                    ed_state.motion_interpreter_state =
-                        crate::motion_interpreter::MotionInterpreterState::Pending(
-                            motion_component_buffer,
-                        );
-                    Ok(None)
-                }
-                Ok(crate::motion_interpreter::MotionInterpreterState::Done(motion_function)) => {
-                    ed_state.motion_interpreter_state =
-                        crate::motion_interpreter::MotionInterpreterState::new();
-                    Ok(Some(crate::motion_interpreter::MotionFunction(Rc::clone(
-                        &motion_function.0,
-                    ))))
-                }
-                Err(error) => {
-                    ed_state.motion_interpreter_state =
-                        crate::motion_interpreter::MotionInterpreterState::new();
-                    Err(error)
-                }
+            if key_event.kind == KeyEventKind::Release {
+                return;
+            }
+            let atom = crate::motion_interpreter::MotionAtom::from_key_event(key_event);
+            if let Some(recording) = &mut ed_state.macro_recording {
+                recording.keys.push(atom);
+            }
+            ed_state
+                .input_queue
+                .push_back(crate::editor_state::QueuedInput::Key(
+                    crate::editor_state::PendingKey {
+                        atom,
+                        remap: true,
+                        depth: 0,
+                        from_macro: false,
+                    },
+                ));
+        }
+        Event::Mouse(mouse_event) => {
+            // clicking moves the cursor there, alt-click adds another cursor
+            if mouse_event.kind != MouseEventKind::Down(MouseButton::Left) {
+                return;
+            }
+            let Some(offset) =
+                crate::render::offset_at(ed_state, mouse_event.column, mouse_event.row)
+            else {
+                return;
+            };
+            let insert = ed_state.mode.is_typing();
+            let buffer = ed_state.get_buffer_mut();
+            let offset = if insert {
+                offset
+            } else {
+                crate::text_motions::clamp_to_line(buffer, offset)
+            };
+            let cursor = crate::selections::Selection::cursor(offset, buffer.position(offset).1);
+            if mouse_event.modifiers.contains(KeyModifiers::ALT) {
+                buffer.selections.push(cursor);
+            } else {
+                buffer.selections = crate::selections::Selections::single(cursor);
             }
         }
-        Event::Mouse(_mouse_event) => Ok(None), // TODO (maybe we dont need no mouse xD!!)
         Event::Paste(string) => {
             let buffer = ed_state.get_buffer_mut();
             let char_slice = string.chars().collect::<Vec<char>>();
-            buffer.content.insert(&char_slice);
-            Ok(None)
+            buffer.insert_at_cursors(&char_slice);
+            if ed_state.mode.is_typing() {
+                ed_state.registers.inserted(&string);
+            }
         }
         Event::Resize(rows, cols) => {
             ed_state.term_info.rows = rows;
             ed_state.term_info.cols = cols;
+        }
+    }
+}
+
+/// Feeds one key into the motion interpreter. Keys of mappings are pushed back onto the front of
+/// the input queue, so they are processed before anything the user typed after the mapping.
+pub(crate) fn process_key(
+    key: crate::editor_state::PendingKey,
+    ed_state: &mut crate::editor_state::EditorState,
+) -> Result<
+    Option<crate::motion_interpreter::MotionFunction>,
+    crate::motion_interpreter::MotionInterpreterError,
+> {
+    ed_state.current_key = Some(key);
+    let crate::editor_state::PendingKey {
+        atom,
+        remap,
+        depth,
+        from_macro,
+    } = key;
+    // while recording, `q` stops the recording instead of waiting for a register name
+    if ed_state.macro_recording.is_some()
+        && ed_state.motion_interpreter_state.is_idle()
+        && ed_state.mode.kind() == crate::editor_state::ModeKind::Normal
+        && atom == crate::motion_interpreter::MotionAtom::new(KeyCode::Char('q'), KeyModifiers::NONE)
+    {
+        ed_state.pending_count = None;
+        crate::macros::stop_recording(ed_state);
+        return Ok(None);
+    }
+    // counts are typed in front of a motion in normal and visual mode (a leading 0 is a motion of
+    // its own though)
+    if ed_state.motion_interpreter_state.is_idle()
+        && matches!(
+            ed_state.mode.kind(),
+            crate::editor_state::ModeKind::Normal | crate::editor_state::ModeKind::Visual
+        )
+        && atom.modifiers.is_empty()
+    {
+        if let KeyCode::Char(c @ '0'..='9') = atom.code {
+            if c != '0' || ed_state.pending_count.is_some() {
+                let digit = c as usize - '0' as usize;
+                ed_state.pending_count = Some(
+                    ed_state
+                        .pending_count
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(digit),
+                );
+                return Ok(None);
+            }
+        }
+    }
+    let mut motion_keys = match &ed_state.motion_interpreter_state {
+        crate::motion_interpreter::MotionInterpreterState::Pending(motion_component_buffer) => {
+            motion_component_buffer.0.clone()
+        }
+        _ => Vec::new(),
+    };
+    motion_keys.push(atom);
+    let ed_state_updated_or_error = ed_state
+        .motion_interpreter_state
+        .clone() // this might hit performance hard because of heap allocations in vec
+        // (TODO: remove vec component and replace with slice?)
+        .update(ed_state.active_motion_tree(), atom, remap);
+    match ed_state_updated_or_error {
+        Ok(crate::motion_interpreter::MotionInterpreterState::Pending(motion_component_buffer)) => {
+            ed_state.motion_interpreter_state =
+                crate::motion_interpreter::MotionInterpreterState::Pending(
+                    motion_component_buffer,
+                );
             Ok(None)
         }
+        Ok(crate::motion_interpreter::MotionInterpreterState::Done(motion_function)) => {
+            ed_state.motion_interpreter_state =
+                crate::motion_interpreter::MotionInterpreterState::new();
+            ed_state.dot_repeat.record_keys(&motion_keys);
+            ed_state.count = ed_state.pending_count.take();
+            Ok(Some(crate::motion_interpreter::MotionFunction(Rc::clone(
+                &motion_function.0,
+            ))))
+        }
+        Ok(crate::motion_interpreter::MotionInterpreterState::Remapped(mapping)) => {
+            ed_state.motion_interpreter_state =
+                crate::motion_interpreter::MotionInterpreterState::new();
+            if depth >= ed_state.editor_globals.max_map_depth {
+                // drop everything that is still queued, it was most likely produced by the
+                // same runaway mapping
+                ed_state.input_queue.clear();
+                return Err(
+                    crate::motion_interpreter::MotionInterpreterError::RecursiveMappingError(
+                        crate::motion_interpreter::MotionComponentBuffer(mapping.keys),
+                    ),
+                );
+            }
+            for &atom in mapping.keys.iter().rev() {
+                ed_state
+                    .input_queue
+                    .push_front(crate::editor_state::QueuedInput::Key(
+                        crate::editor_state::PendingKey {
+                            atom,
+                            remap: mapping.recursive,
+                            depth: depth + 1,
+                            from_macro,
+                        },
+                    ));
+            }
+            Ok(None)
+        }
+        Err(error) => {
+            ed_state.motion_interpreter_state =
+                crate::motion_interpreter::MotionInterpreterState::new();
+            if let crate::motion_interpreter::MotionInterpreterError::UnknownMotionError(keys) =
+                &error
+            {
+                // the character after `r` is not bound to anything
+                if let Some(motion_function) =
+                    crate::replace::replacement_motion(&ed_state.mode, &keys.0)
+                {
+                    ed_state.dot_repeat.record_keys(&keys.0);
+                    ed_state.count = ed_state.pending_count.take();
+                    return Ok(Some(motion_function));
+                }
+            }
+            ed_state.pending_count = None;
+            Err(error)
+        }
     }
 }
 
-pub(crate) fn run() -> std::io::Result<()> {
-    // NOTE: enabling and disabling raw mode is handled by main (caller)
-    // let stdout = io::stdout();
-    let (cols, rows) = terminal::size()?;
-    let term_info = crate::editor_state::TermInfo { rows, cols };
+/// Inserts a key typed in insert mode that is not bound to any motion into the current buffer
+fn self_insert(
+    atom: crate::motion_interpreter::MotionAtom,
+    ed_state: &mut crate::editor_state::EditorState,
+) {
+    if !matches!(
+        atom.code,
+        KeyCode::Char(_) | KeyCode::Enter | KeyCode::Tab | KeyCode::Backspace
+    ) {
+        return;
+    }
+    let editor_globals = &ed_state.editor_globals;
+    let edit = |buffer: &crate::editor_buffer::Buffer, head: usize| match atom.code {
+        KeyCode::Char(c) => crate::autopairs::char_edit(buffer, editor_globals, head, c)
+            .unwrap_or_else(|| crate::indent::char_edit(buffer, editor_globals, head, c)),
+        KeyCode::Enter => crate::autopairs::newline_edit(buffer, editor_globals, head)
+            .unwrap_or_else(|| crate::indent::newline_edit(buffer, editor_globals, head)),
+        KeyCode::Tab => crate::indent::tab_edit(buffer, editor_globals, head),
+        _ => crate::autopairs::backspace_edit(buffer, editor_globals, head)
+            .unwrap_or_else(|| crate::indent::backspace_edit(buffer, editor_globals, head)),
+    };
+    let current_buffer = &mut ed_state.buffers[ed_state.buffer_idx];
+    // a key that ends a word expands the abbreviation in front of it first (if any cursor has
+    // one, the buffer is not touched otherwise)
+    let expands = |buffer: &crate::editor_buffer::Buffer| {
+        buffer.selections.iter().any(|selection| {
+            crate::abbreviations::insert_edit(buffer, editor_globals, selection.head).is_some()
+        })
+    };
+    if crate::abbreviations::triggers(atom) && expands(current_buffer) {
+        edit_at_cursors(current_buffer, &mut ed_state.registers, |buffer, head| {
+            crate::abbreviations::insert_edit(buffer, editor_globals, head)
+                .unwrap_or_else(|| crate::editor_buffer::CursorEdit::before(head, 0, Vec::new()))
+        });
+    }
+    edit_at_cursors(current_buffer, &mut ed_state.registers, edit);
+}
+
+/// Makes an insert mode edit at every cursor, the primary cursor's edit is what the `.` register
+/// sees (up to where the cursor ends up)
+fn edit_at_cursors(
+    buffer: &mut crate::editor_buffer::Buffer,
+    registers: &mut crate::registers::Registers,
+    edit: impl Fn(&crate::editor_buffer::Buffer, usize) -> crate::editor_buffer::CursorEdit,
+) {
+    let head = buffer.cursor();
+    let primary = edit(buffer, head);
+    buffer.edit_at_cursors(edit);
+    registers.insert_deleted(head.saturating_sub(primary.range.start));
+    let inserted = &primary.text[..primary.cursor.min(primary.text.len())];
+    registers.inserted(&inserted.iter().collect::<String>());
+}
+
+/// Edits the command line input with a key typed in command mode that is not bound to any motion
+fn command_line_input(
+    atom: crate::motion_interpreter::MotionAtom,
+    ed_state: &mut crate::editor_state::EditorState,
+) {
+    match atom.code {
+        KeyCode::Char(c) => ed_state.command_line.input.push(c),
+        // backspace on an empty command line leaves command mode
+        KeyCode::Backspace if ed_state.command_line.input.pop().is_none() => {
+            ed_state.apply(crate::editor_state::EditorStateUpdate::Mode(
+                crate::editor_state::EditorMode::Normal,
+            ));
+        }
+        KeyCode::Enter => {
+            let line = std::mem::take(&mut ed_state.command_line.input);
+            ed_state.apply(crate::editor_state::EditorStateUpdate::Mode(
+                crate::editor_state::EditorMode::Normal,
+            ));
+            if let Err(error) = crate::ex_commands::execute(ed_state, &line) {
+                writeln!(&mut ed_state.command_line, "{}", error)
+                    .expect("Fatal: Could not write to command line buffer");
+            }
+            if !line.is_empty() {
+                ed_state.registers.set_read_only(':', line);
+            }
+        }
+        KeyCode::Esc => {
+            ed_state.command_line.input.clear();
+            ed_state.apply(crate::editor_state::EditorStateUpdate::Mode(
+                crate::editor_state::EditorMode::Normal,
+            ));
+        }
+        _ => {}
+    }
+}
+
+fn handle_motion_error(
+    error: crate::motion_interpreter::MotionInterpreterError,
+    ed_state: &mut crate::editor_state::EditorState,
+) {
+    // panic only in debug mode, ignore in release mode
+    // if cfg!(debug_assertions) {
+    // panic!("Error processing event: {:?}", e);
+    // eprintln!("Error processing event: {:?}", error);
+    match (&ed_state.mode, error) {
+        (
+            crate::editor_state::EditorMode::Insert,
+            crate::motion_interpreter::MotionInterpreterError::UnknownMotionError(
+                motion_component_buffer,
+            ),
+        ) => {
+            // In insert mode, this is just an insert
+            // The user is just trying to type something
+            // This is not actually an error, it was false alarm
+            // We should write what the user typed into the current buffer (including the keys
+            // of a mapping prefix that turned out not to be a mapping)
+            if ed_state.dot_repeat.in_insert_session() {
+                ed_state.dot_repeat.record_keys(&motion_component_buffer.0);
+            }
+            for atom in motion_component_buffer.0 {
+                self_insert(atom, ed_state);
+            }
+        }
+        (
+            crate::editor_state::EditorMode::Replace { .. },
+            crate::motion_interpreter::MotionInterpreterError::UnknownMotionError(
+                motion_component_buffer,
+            ),
+        ) => {
+            // like in insert mode, but the keys overwrite the text
+            if ed_state.dot_repeat.in_insert_session() {
+                ed_state.dot_repeat.record_keys(&motion_component_buffer.0);
+            }
+            for atom in motion_component_buffer.0 {
+                crate::replace::type_key(atom, ed_state);
+            }
+        }
+        (
+            crate::editor_state::EditorMode::Command,
+            crate::motion_interpreter::MotionInterpreterError::UnknownMotionError(
+                motion_component_buffer,
+            ),
+        ) => {
+            // same as in insert mode, but the keys go into the command line
+            ed_state.dot_repeat.command_failed();
+            for atom in motion_component_buffer.0 {
+                if crate::abbreviations::triggers(atom) {
+                    crate::abbreviations::expand_command_line(ed_state);
+                }
+                command_line_input(atom, ed_state);
+            }
+            // a command line opened with <C-o> is done when it is run or cancelled
+            if crate::insert_keys::resume_insert(ed_state) {
+                ed_state.get_buffer_mut().undo.start_group(false);
+                ed_state.registers.insert_started();
+            }
+        }
+        (_, error) => {
+            ed_state.dot_repeat.command_failed();
+            ed_state.selected_register = None;
+            crate::macros::abort(ed_state);
+            // In normal and visual mode (and for errors other than unknown motions), this is
+            // considered an error
+            writeln!(&mut ed_state.command_line, "{:?}", error)
+                .expect("Fatal: Could not write to command line buffer");
+        }
+    }
+}
+
+/// Runs every queued key (and the keys of the mappings they expand to) through the interpreter
+pub(crate) fn process_input_queue(ed_state: &mut crate::editor_state::EditorState) {
+    while let Some(input) = ed_state.input_queue.pop_front() {
+        let key = match input {
+            crate::editor_state::QueuedInput::Key(key) => key,
+            crate::editor_state::QueuedInput::MacroRuns { keys, count, depth } => {
+                crate::macros::queue_runs(ed_state, keys, count, depth);
+                continue;
+            }
+        };
+        // the key after <C-v> skips mappings and motions
+        if ed_state.literal_input.is_some() && ed_state.mode.is_typing() {
+            ed_state.current_key = Some(key);
+            crate::insert_keys::literal_key(key, ed_state);
+            continue;
+        }
+        match process_key(key, ed_state) {
+            Ok(Some(motion_function)) => {
+                let was_inserting = ed_state.mode.is_typing();
+                let visual_extent = ed_state.visual_extent();
+                let change_tick = ed_state.change_tick();
+                let update = motion_function.0(ed_state);
+                let is_undo = matches!(update, crate::editor_state::EditorStateUpdate::Undo(_));
+                let selects_register = matches!(
+                    update,
+                    crate::editor_state::EditorStateUpdate::SelectRegister(_)
+                );
+                let visual_selection = if selects_register {
+                    None
+                } else {
+                    crate::marks::track_visual_selection(ed_state)
+                };
+                ed_state.apply(update);
+                if selects_register {
+                    // `"{name}` is a prefix, the count typed in front of it is for the command
+                    // after it and `.` repeats both
+                    ed_state.pending_count = ed_state.count.take();
+                    continue;
+                }
+                ed_state.selected_register = None;
+                // the command after <C-o> is done, back to insert mode
+                if !was_inserting {
+                    crate::insert_keys::resume_insert(ed_state);
+                }
+                crate::marks::update_special_marks(ed_state, visual_selection, was_inserting);
+                let inserting = ed_state.mode.is_typing();
+                let changed = ed_state.change_tick() != change_tick;
+                // a whole insert session (and the change that started it) is one undo step
+                if !was_inserting && inserting {
+                    ed_state.get_buffer_mut().undo.start_group(changed);
+                    ed_state.registers.insert_started();
+                } else if was_inserting && !inserting {
+                    let buffer = ed_state.get_buffer_mut();
+                    buffer.undo.end_group(&buffer.selections);
+                    ed_state.registers.insert_finished();
+                }
+                if is_undo {
+                    // undoing is not a change that `.` repeats
+                    ed_state.count = None;
+                    ed_state.dot_repeat.command_failed();
+                    continue;
+                }
+                ed_state.dot_repeat.command_done(
+                    ed_state.count.take(),
+                    visual_extent,
+                    changed,
+                    !was_inserting && inserting,
+                    inserting,
+                );
+            }
+            Ok(None) => {}
+            Err(error) => handle_motion_error(error, ed_state),
+        }
+    }
+}
+
+/// Creates the editor state with all builtin motions and ex commands set up
+pub(crate) fn setup_editor_state(
+    term_info: crate::editor_state::TermInfo,
+) -> crate::editor_state::EditorState {
     let editor_globals = crate::editor_state::EditorGlobals::default();
     let mut ed_state = crate::editor_state::EditorState::new_normal(term_info, editor_globals);
     match (
         &mut ed_state.editor_globals.normal_mode_motion_tree,
         &mut ed_state.editor_globals.insert_mode_motion_tree,
         &mut ed_state.editor_globals.visual_mode_motion_tree,
+        &mut ed_state.editor_globals.replace_mode_motion_tree,
     ) {
         (
             crate::motion_interpreter::MotionTree::Tree(ref mut normal_motion_tree),
             crate::motion_interpreter::MotionTree::Tree(ref mut insert_motion_tree),
             crate::motion_interpreter::MotionTree::Tree(ref mut visual_motion_tree),
+            crate::motion_interpreter::MotionTree::Tree(ref mut replace_motion_tree),
         ) => crate::setup_motions::setup_motions(
             normal_motion_tree,
             insert_motion_tree,
             visual_motion_tree,
+            replace_motion_tree,
         ),
         _ => unreachable!(),
     }
+    crate::ex_commands::setup_ex_commands(&mut ed_state.editor_globals.ex_commands);
+    ed_state
+}
+
+pub(crate) fn run() -> std::io::Result<()> {
+    // NOTE: enabling and disabling raw mode is handled by main (caller)
+    // let stdout = io::stdout();
+    let (cols, rows) = terminal::size()?;
+    let term_info = crate::editor_state::TermInfo { rows, cols };
+    let mut ed_state = setup_editor_state(term_info);
+    crate::render::render(&ed_state)?;
     loop {
-        while event::poll(std::time::Duration::ZERO)? {
+        // with tasks in flight, wake up regularly to drive them, otherwise just wait for input
+        let has_input = ed_state.scheduler.is_idle() || event::poll(TASK_POLL_INTERVAL)?;
+        if has_input {
             let evnt = event::read()?;
-            let process_result = process(evnt.clone(), &mut ed_state);
-            match process_result {
-                Ok(Some(motion_function)) => {
-                    let update = motion_function.0(&ed_state);
-                    ed_state.apply(update);
-                }
-                Ok(None) => {}
-                Err(error) => {
-                    // panic only in debug mode, ignore in release mode
-                    // if cfg!(debug_assertions) {
-                    // panic!("Error processing event: {:?}", e);
-                    // eprintln!("Error processing event: {:?}", error);
-                    match ed_state.mode {
-                        crate::editor_state::EditorMode::Normal => {
-                            // In normal mode, this is considered an error
-                            writeln!(
-                                &mut ed_state.command_line,
-                                "{:?}",
-                                error
-                            )
-                            .expect("Fatal: Could not write to command line buffer");
-                        }
-                        crate::editor_state::EditorMode::Insert => {
-                            if let Event::Key(KeyEvent { code, .. }) = evnt {
-                                // In insert mode, this is just an insert
-                                // The user is just trying to type something
-                                // This is not actually an error, it was false alarm
-                                // We should write what the user typed into the current buffer
-                                let tab_size = ed_state.editor_globals.tab_size;
-                                let current_buffer = ed_state.get_buffer_mut();
-                                if let KeyCode::Backspace = code {
-                                    current_buffer.content.delete(1);
-                                } else {
-                                    let keys = match code {
-                                        KeyCode::Char(c) => c.to_string(),
-                                        KeyCode::Enter => "\n".to_string(),
-                                        KeyCode::Tab => str::repeat(" ", tab_size),
-                                        _ => "".to_string(),
-                                    };
-                                    current_buffer
-                                        .content
-                                        .insert(&keys.chars().collect::<Vec<_>>());
-                                }
-                            }
-                        }
-                        crate::editor_state::EditorMode::Visual { .. } => {
-                            // In visual mode, this is considered an error
-                            writeln!(
-                                &mut ed_state.command_line,
-                                "{:?}",
-                                error
-                            )
-                            .expect("Fatal: Could not write to command line buffer");
-                        }
-                    }
-                    crate::render::render(&ed_state)?;
-                }
+            process(evnt, &mut ed_state);
+            process_input_queue(&mut ed_state);
+        }
+        let updates = ed_state.scheduler.poll();
+        let tasks_finished = !updates.is_empty();
+        for update in updates {
+            ed_state.apply(update);
+        }
+        if has_input || tasks_finished {
+            crate::render::write_terminal_output(&mut ed_state, &mut std::io::stdout())?;
+            crate::render::render(&ed_state)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_ed_state() -> crate::editor_state::EditorState {
+        setup_editor_state(crate::editor_state::TermInfo { rows: 24, cols: 80 })
+    }
+
+    fn feed(ed_state: &mut crate::editor_state::EditorState, keys: &str) {
+        for atom in crate::key_notation::parse(keys).unwrap() {
+            let key_event = crossterm::event::KeyEvent::new(atom.code, atom.modifiers);
+            process(Event::Key(key_event), ed_state);
+            process_input_queue(ed_state);
+        }
+    }
+
+    fn buffer_text(ed_state: &crate::editor_state::EditorState) -> String {
+        ed_state.get_buffer().content.iter().collect()
+    }
+
+    /// A buffer with `text` that belongs to the file `name` (for the filetype), it is not read
+    fn buffer_with_file(text: &str, name: &str) -> crate::editor_buffer::Buffer {
+        let mut buffer = crate::editor_buffer::Buffer::from_text(text);
+        buffer.location = Some(std::path::PathBuf::from(name));
+        buffer
+    }
+
+    /// Waits for the tasks in the background and applies their updates, like the event loop
+    fn finish_tasks(ed_state: &mut crate::editor_state::EditorState) {
+        while !ed_state.scheduler.is_idle() {
+            for update in ed_state.scheduler.poll() {
+                ed_state.apply(update);
             }
         }
     }
+
+    #[test]
+    fn test_imap_expands_to_keys() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":imap teh the<CR>ia teh x");
+        assert_eq!(buffer_text(&ed_state), "a the x");
+    }
+    #[test]
+    fn test_mapping_prefix_is_inserted_when_not_completed() {
+        let mut ed_state = new_ed_state();
+        // `<lt>` types a literal `<`, so the command line receives `<Esc>` as text
+        feed(&mut ed_state, ":inoremap jk <lt>Esc><CR>ijxjk");
+        assert_eq!(buffer_text(&ed_state), "jx");
+        assert!(matches!(
+            ed_state.mode,
+            crate::editor_state::EditorMode::Normal
+        ));
+    }
+    #[test]
+    fn test_noremap_uses_shadowed_builtin() {
+        let mut ed_state = new_ed_state();
+        // `i` is remapped to itself recursively and non-recursively, only the latter terminates
+        feed(&mut ed_state, ":nnoremap i i<CR>ix");
+        assert_eq!(buffer_text(&ed_state), "x");
+    }
+    #[test]
+    fn test_recursive_mapping_is_stopped() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":nmap a b<CR>:nmap b a<CR>a");
+        assert!(ed_state.command_line.buffer.contains("RecursiveMappingError"));
+        assert!(ed_state.input_queue.is_empty());
+        // keys typed afterwards work as usual
+        feed(&mut ed_state, "ix");
+        assert_eq!(buffer_text(&ed_state), "x");
+    }
+    #[test]
+    fn test_spawned_task_applies_update_later() {
+        let mut ed_state = new_ed_state();
+        ed_state
+            .editor_globals
+            .normal_mode_motion_tree
+            .insert(
+                crate::motion_interpreter::MotionComponentBuffer(
+                    crate::key_notation::parse("<F5>").unwrap(),
+                ),
+                crate::motion_interpreter::MotionFunction(Rc::new(|_| {
+                    crate::editor_state::EditorStateUpdate::Spawn(Box::pin(async {
+                        let mode = crate::scheduler::spawn_blocking(|| {
+                            crate::editor_state::EditorMode::Insert
+                        })
+                        .await
+                        .unwrap_or(crate::editor_state::EditorMode::Normal);
+                        crate::editor_state::EditorStateUpdate::Mode(mode)
+                    }))
+                })),
+            )
+            .unwrap();
+        feed(&mut ed_state, "<F5>");
+        assert!(!ed_state.scheduler.is_idle());
+        // input keeps working while the task runs
+        feed(&mut ed_state, ":nmap x y<CR>");
+        finish_tasks(&mut ed_state);
+        assert!(matches!(
+            ed_state.mode,
+            crate::editor_state::EditorMode::Insert
+        ));
+    }
+    #[test]
+    fn test_dot_repeats_insert_session() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, "iab<BS>c<Esc>:set tsz=2<CR>.");
+        assert_eq!(buffer_text(&ed_state), "acac");
+        assert_eq!(ed_state.dot_repeat.replay_keys(None).unwrap().len(), 6);
+    }
+    #[test]
+    fn test_dot_repeat_through_mapping() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":nmap Q ix<lt>Esc><CR>Q");
+        // the mapping itself is not recorded, only the keys it expanded to
+        feed(&mut ed_state, ":nunmap Q<CR>..");
+        assert_eq!(buffer_text(&ed_state), "xxx");
+    }
+    #[test]
+    fn test_count_is_recorded_with_change() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, "3ia<Esc>");
+        let change = ed_state.dot_repeat.last_change.clone().unwrap();
+        assert_eq!(change.count, Some(3));
+        feed(&mut ed_state, "5.");
+        assert_eq!(ed_state.dot_repeat.last_change.unwrap().count, Some(5));
+    }
+    #[test]
+    fn test_macro_record_and_play() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, "qaiab<Esc>q");
+        assert_eq!(ed_state.registers.get('a').unwrap().text, "iab<Esc>");
+        feed(&mut ed_state, "@a2@@");
+        assert_eq!(buffer_text(&ed_state), "abababab");
+        // appending to the macro with the uppercase register
+        feed(&mut ed_state, "qAic<Esc>q@a");
+        assert_eq!(buffer_text(&ed_state), "ababababcabc");
+    }
+    #[test]
+    fn test_macro_is_editable_text() {
+        let mut ed_state = new_ed_state();
+        ed_state.registers.set('b', "ihi<Esc>".to_string());
+        feed(&mut ed_state, "@b");
+        assert_eq!(buffer_text(&ed_state), "hi");
+    }
+    #[test]
+    fn test_macro_stops_when_a_motion_fails() {
+        let mut ed_state = new_ed_state();
+        ed_state.registers.set('c', "ix<Esc>Zix<Esc>".to_string());
+        feed(&mut ed_state, "3@c");
+        assert_eq!(buffer_text(&ed_state), "x");
+        assert!(ed_state.input_queue.is_empty());
+    }
+    #[test]
+    fn test_macro_with_a_huge_count_is_queued_one_run_at_a_time() {
+        let mut ed_state = new_ed_state();
+        ed_state.registers.set('c', "ix<Esc>Z".to_string());
+        feed(&mut ed_state, "99999999@c");
+        assert_eq!(buffer_text(&ed_state), "x");
+        assert!(ed_state.input_queue.is_empty());
+        ed_state.registers.set('d', "ix<Esc>".to_string());
+        feed(&mut ed_state, "3@d");
+        assert_eq!(buffer_text(&ed_state), "xxxx");
+    }
+    #[test]
+    fn test_recursive_macro_is_stopped() {
+        let mut ed_state = new_ed_state();
+        ed_state.registers.set('d', "@d".to_string());
+        feed(&mut ed_state, "@d");
+        assert!(ed_state.command_line.buffer.contains("Recursive macro"));
+    }
+    #[test]
+    fn test_cursor_motions_keep_column_and_move_insert_point() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("abcdef\nab\nabcdef");
+        feed(&mut ed_state, "4lj");
+        assert_eq!(ed_state.get_buffer().cursor_position(), (1, 1));
+        feed(&mut ed_state, "j");
+        assert_eq!(ed_state.get_buffer().cursor_position(), (2, 4));
+        feed(&mut ed_state, "ix<Esc>");
+        assert_eq!(buffer_text(&ed_state), "abcdef\nab\nabcdxef");
+        feed(&mut ed_state, "gg$");
+        assert_eq!(ed_state.get_buffer().cursor_position(), (0, 5));
+        feed(&mut ed_state, "vjj");
+        assert_eq!(ed_state.get_buffer().cursor_position(), (2, 6));
+    }
+    #[test]
+    fn test_typing_moves_cursor() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, "iab<CR>cd<BS>");
+        assert_eq!(buffer_text(&ed_state), "ab\nc");
+        assert_eq!(ed_state.get_buffer().cursor_position(), (1, 1));
+        feed(&mut ed_state, "<Esc>0ix");
+        assert_eq!(buffer_text(&ed_state), "ab\nxc");
+    }
+    #[test]
+    fn test_buffers_keep_their_cursor() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo");
+        feed(&mut ed_state, "jl:enew<CR>iabc<Esc>");
+        assert_eq!(ed_state.buffer_idx, 1);
+        assert_eq!(ed_state.get_buffer().cursor(), 3);
+        feed(&mut ed_state, ":bn<CR>");
+        assert_eq!(ed_state.get_buffer().cursor_position(), (1, 1));
+        feed(&mut ed_state, ":b 2<CR>");
+        assert_eq!(ed_state.get_buffer().cursor(), 3);
+        feed(&mut ed_state, ":b 3<CR>");
+        assert!(ed_state.command_line.buffer.contains("Buffer 3 does not exist"));
+    }
+    fn cursors(ed_state: &crate::editor_state::EditorState) -> Vec<usize> {
+        let selections = &ed_state.get_buffer().selections;
+        selections.iter().map(|selection| selection.head).collect()
+    }
+    #[test]
+    fn test_cursors_at_word_matches() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("foo bar\nfoo foobar foo");
+        feed(&mut ed_state, "<C-n><C-n>");
+        assert_eq!(cursors(&ed_state), vec![0, 8, 19]);
+        feed(&mut ed_state, "<C-n>");
+        assert_eq!(ed_state.command_line.buffer, "No more matches\n");
+        feed(&mut ed_state, "wiX<Esc>");
+        assert_eq!(buffer_text(&ed_state), "foo Xbar\nfoo Xfoobar foXo");
+        feed(&mut ed_state, "<Esc>");
+        assert_eq!(ed_state.get_buffer().selections.len(), 1);
+    }
+    #[test]
+    fn test_cursor_on_every_selected_line() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\nt\nthree");
+        feed(&mut ed_state, "lvjj<A-s>");
+        assert!(matches!(ed_state.mode, crate::editor_state::EditorMode::Normal));
+        assert_eq!(cursors(&ed_state), vec![1, 4, 7]);
+        feed(&mut ed_state, "lix<Esc>");
+        assert_eq!(buffer_text(&ed_state), "onxe\nxt\nthxree");
+    }
+    #[test]
+    fn test_mouse_clicks_place_cursors() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo");
+        let click = |column, row, modifiers| {
+            Event::Mouse(crossterm::event::MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column,
+                row,
+                modifiers,
+            })
+        };
+        // the line of the cursor is drawn in the middle of the screen
+        process(click(2, 13, KeyModifiers::NONE), &mut ed_state);
+        assert_eq!(cursors(&ed_state), vec![6]);
+        process(click(0, 12, KeyModifiers::ALT), &mut ed_state);
+        assert_eq!(cursors(&ed_state), vec![4, 6]);
+    }
+    #[test]
+    fn test_jumplist_follows_edits() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("a\nb\nc\nd");
+        feed(&mut ed_state, "jGggiX<CR><Esc>");
+        assert_eq!(buffer_text(&ed_state), "X\na\nb\nc\nd");
+        feed(&mut ed_state, "<C-o>");
+        assert_eq!(ed_state.get_buffer().cursor(), 8);
+        feed(&mut ed_state, "<C-o>");
+        assert_eq!(ed_state.get_buffer().cursor(), 4);
+        feed(&mut ed_state, "<C-o>");
+        assert_eq!(ed_state.command_line.buffer, "At start of jumplist\n");
+        feed(&mut ed_state, "2<Tab>");
+        assert_eq!(ed_state.get_buffer().cursor(), 2);
+    }
+    #[test]
+    fn test_jumplist_spans_buffers() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("a\nb");
+        feed(&mut ed_state, "j:enew<CR>");
+        assert_eq!(ed_state.buffer_idx, 1);
+        feed(&mut ed_state, "<C-o>");
+        assert_eq!(ed_state.buffer_idx, 0);
+        assert_eq!(ed_state.get_buffer().cursor(), 2);
+        feed(&mut ed_state, "<C-i>");
+        assert_eq!(ed_state.buffer_idx, 1);
+    }
+    #[test]
+    fn test_changelist() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo\nthree");
+        feed(&mut ed_state, "g;");
+        assert_eq!(ed_state.command_line.buffer, "Changelist is empty\n");
+        feed(&mut ed_state, "ix<Esc>jjiy<Esc>gg");
+        feed(&mut ed_state, "g;");
+        assert_eq!(ed_state.get_buffer().cursor(), 11);
+        feed(&mut ed_state, "g;");
+        assert_eq!(ed_state.get_buffer().cursor(), 1);
+        feed(&mut ed_state, "g;");
+        assert_eq!(ed_state.command_line.buffer, "At start of changelist\n");
+        feed(&mut ed_state, "g,");
+        assert_eq!(ed_state.get_buffer().cursor(), 11);
+        feed(&mut ed_state, "g,");
+        assert_eq!(ed_state.command_line.buffer, "At end of changelist\n");
+    }
+    #[test]
+    fn test_local_and_special_marks() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\n  two\nthree");
+        feed(&mut ed_state, "jlllmaggiX<Esc>");
+        feed(&mut ed_state, "'a");
+        assert_eq!(ed_state.get_buffer().cursor(), 7);
+        feed(&mut ed_state, "`a");
+        assert_eq!(ed_state.get_buffer().cursor(), 8);
+        feed(&mut ed_state, "`^");
+        assert_eq!(ed_state.get_buffer().cursor(), 1);
+        // '' goes back to where the last jump started
+        feed(&mut ed_state, "``");
+        assert_eq!(ed_state.get_buffer().cursor(), 8);
+        feed(&mut ed_state, "vj<Esc>gg`>");
+        assert_eq!(ed_state.get_buffer().cursor(), 14);
+        feed(&mut ed_state, ":marks a.<CR>");
+        assert_eq!(
+            ed_state.command_line.buffer,
+            "mark line  col file/text\n a      2    3   two\n .      1    1 Xone\n"
+        );
+        // an invalid mark keeps the others
+        feed(&mut ed_state, ":delmarks a1<CR>gg'a");
+        assert_eq!(ed_state.get_buffer().cursor(), 7);
+        feed(&mut ed_state, ":delmarks a-c<CR>'a");
+        assert_eq!(ed_state.command_line.buffer, "Mark not set: a\n");
+    }
+    #[test]
+    fn test_global_marks_switch_and_reopen_files() {
+        let path = std::env::temp_dir().join(format!("miv-marks-{}.txt", std::process::id()));
+        std::fs::write(&path, "first\nsecond\n").unwrap();
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, &format!(":e {}<CR>jlmA:enew<CR>", path.display()));
+        assert_eq!(ed_state.buffer_idx, 2);
+        feed(&mut ed_state, "`A");
+        assert_eq!(ed_state.buffer_idx, 1);
+        assert_eq!(ed_state.get_buffer().cursor(), 7);
+        // the buffer does not hold the file anymore, so it is opened again
+        ed_state.buffers[1].location = None;
+        feed(&mut ed_state, "gg`A");
+        assert_eq!(ed_state.buffer_idx, 3);
+        assert_eq!(ed_state.get_buffer().cursor_position(), (1, 1));
+        std::fs::remove_file(&path).unwrap();
+        // moving the mark keeps the anchors of the buffer that took the place of its old one
+        ed_state.buffers[1] = crate::editor_buffer::Buffer::from_text("xyz");
+        let anchors = [0, 1, 2].map(|offset| ed_state.buffers[1].anchors.insert(offset));
+        feed(&mut ed_state, "mA");
+        let offsets = anchors.map(|anchor| ed_state.buffers[1].anchors.get(anchor));
+        assert_eq!(offsets, [Some(0), Some(1), Some(2)]);
+    }
+    #[test]
+    fn test_map_listing() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":nnoremap Y y$<CR>:vmap Y y<CR>:map<CR>");
+        assert_eq!(
+            ed_state.command_line.buffer,
+            "n  Y            * y$\nv  Y              y\n"
+        );
+        feed(&mut ed_state, ":imap<CR>");
+        assert_eq!(ed_state.command_line.buffer, "No mapping found\n");
+    }
+    #[test]
+    fn test_mapping_runs_its_keys() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one two");
+        feed(&mut ed_state, ":nnoremap Y y$<CR>wYP");
+        assert_eq!(buffer_text(&ed_state), "one twotwo");
+    }
+    #[test]
+    fn test_mapping_can_not_start_with_mapped_keys() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":nmap jk <lt>Esc><CR>");
+        assert!(ed_state
+            .command_line
+            .buffer
+            .contains("mappings can not start with keys that are mapped already"));
+    }
+    #[test]
+    fn test_unmap_restores_builtin() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":nmap i v<CR>:nunmap i<CR>ix");
+        assert_eq!(buffer_text(&ed_state), "x");
+    }
+    #[test]
+    fn test_mapped_modified_keys_fire() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("abc");
+        feed(&mut ed_state, ":nmap <lt>C-A> x<CR>:nmap <lt>S-y> x<CR>:nmap <lt>S-Tab> x<CR>");
+        // the keys the way the terminal reports them
+        for (code, modifiers) in [
+            (KeyCode::Char('a'), KeyModifiers::CONTROL),
+            (KeyCode::Char('Y'), KeyModifiers::SHIFT),
+            (KeyCode::BackTab, KeyModifiers::SHIFT),
+        ] {
+            let text = buffer_text(&ed_state);
+            process(Event::Key(crossterm::event::KeyEvent::new(code, modifiers)), &mut ed_state);
+            process_input_queue(&mut ed_state);
+            assert_eq!(buffer_text(&ed_state), text[1..]);
+        }
+    }
+    #[test]
+    fn test_map_changes_nothing_on_error() {
+        let mut ed_state = new_ed_state();
+        // `=` is a prefix in normal mode but a motion of its own in visual mode
+        feed(&mut ed_state, ":map =x y<CR>");
+        let keys = crate::key_notation::parse("=x").unwrap();
+        let normal = ed_state.editor_globals.motion_tree(crate::editor_state::ModeKind::Normal);
+        assert!(normal.get(&keys).is_none());
+    }
+    #[test]
+    fn test_set_changes_nothing_on_error() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":set sw?<CR>");
+        let shift_width = ed_state.command_line.buffer.clone();
+        feed(&mut ed_state, ":set sw=3 ts=x<CR>:set sw?<CR>");
+        assert_eq!(ed_state.command_line.buffer, shift_width);
+    }
+    #[test]
+    fn test_undo_insert_sessions() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("ab\nab");
+        // every insert session is one step, also with several cursors (the new one is primary)
+        feed(&mut ed_state, "<C-n>i-<BS>+<Esc><Esc>ix<Esc>");
+        assert_eq!(buffer_text(&ed_state), "+ab\n+xab");
+        feed(&mut ed_state, "u");
+        assert_eq!(buffer_text(&ed_state), "+ab\n+ab");
+        assert_eq!(cursors(&ed_state), vec![5]);
+        feed(&mut ed_state, "u");
+        assert_eq!(buffer_text(&ed_state), "ab\nab");
+        assert_eq!(cursors(&ed_state), vec![0, 3]);
+        feed(&mut ed_state, "u");
+        assert_eq!(ed_state.command_line.buffer, "Already at oldest change\n");
+        feed(&mut ed_state, "2<C-r>");
+        assert_eq!(buffer_text(&ed_state), "+ab\n+xab");
+        // `.` repeats the last insert, not the undo
+        feed(&mut ed_state, "uu.");
+        assert_eq!(buffer_text(&ed_state), "xab\nxab");
+    }
+    #[test]
+    fn test_undo_branches_and_writes() {
+        let path = std::env::temp_dir().join(format!("miv-undo-{}.txt", std::process::id()));
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, "ione<Esc>");
+        feed(&mut ed_state, &format!(":w {}<CR>", path.display()));
+        assert_eq!(
+            ed_state.command_line.buffer,
+            format!("\"{}\" 1L, 3B written\n", path.display())
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one");
+        feed(&mut ed_state, "ggix<Esc>uiy<Esc>");
+        assert_eq!(buffer_text(&ed_state), "yone");
+        // g- goes back to the branch that was undone
+        feed(&mut ed_state, "g-");
+        assert_eq!(buffer_text(&ed_state), "xone");
+        feed(&mut ed_state, ":earlier 1f<CR>");
+        assert_eq!(buffer_text(&ed_state), "one");
+        feed(&mut ed_state, ":later 9<CR>");
+        assert_eq!(buffer_text(&ed_state), "yone");
+        feed(&mut ed_state, ":undolist<CR>");
+        let list = ed_state.command_line.buffer.lines().collect::<Vec<_>>();
+        assert_eq!(list[0], "number changes  when               saved");
+        assert!(list[1].starts_with("     2       2  "));
+        assert!(list[2].starts_with("     3       2  "));
+        std::fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn test_undo_history_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("miv-undodir-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file.txt");
+        let open = format!(":set undodir={}<CR>:e {}<CR>", dir.display(), path.display());
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, &format!("{}ione<Esc>ix<Esc>:w<CR>", open));
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, &open);
+        assert_eq!(buffer_text(&ed_state), "onex");
+        feed(&mut ed_state, "u");
+        assert_eq!(buffer_text(&ed_state), "one");
+        // changed by someone else, the old history does not apply anymore
+        std::fs::write(&path, "other").unwrap();
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, &format!("{}u", open));
+        assert_eq!(ed_state.command_line.buffer, "Already at oldest change\n");
+        feed(&mut ed_state, ":set maxundosize=0<CR>ix<Esc>:w<CR>");
+        assert!(ed_state
+            .command_line
+            .buffer
+            .ends_with("Undo history is too large to save (1 KiB)\n"));
+        // a broken undo file is reported when the file is opened
+        let undo_dir = dir.display().to_string();
+        let undo_path = crate::undo_file::undo_file_path(&path, &undo_dir).unwrap();
+        std::fs::write(undo_path, "miv-undo 1\nbroken").unwrap();
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, &open);
+        assert_eq!(ed_state.command_line.buffer, "Undo file is corrupt\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_yank_delete_and_put() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo\nthree");
+        feed(&mut ed_state, "yyjp");
+        assert_eq!(buffer_text(&ed_state), "one\ntwo\none\nthree");
+        assert_eq!(ed_state.get_buffer().cursor(), 8);
+        feed(&mut ed_state, "ggvlyG$p");
+        assert_eq!(buffer_text(&ed_state), "one\ntwo\none\nthreeon");
+        // line deletes go through the numbered registers, `"_` keeps them as they are
+        feed(&mut ed_state, "ggdddd\"_dd");
+        assert_eq!(buffer_text(&ed_state), "threeon");
+        assert_eq!(ed_state.registers.get('1').unwrap().text, "two\n");
+        assert_eq!(ed_state.registers.get('2').unwrap().text, "one\n");
+        feed(&mut ed_state, "\"2P");
+        assert_eq!(buffer_text(&ed_state), "one\nthreeon");
+        // a count in front of the register name counts the put
+        feed(&mut ed_state, "vl\"ay2\"ap");
+        assert_eq!(buffer_text(&ed_state), "oononne\nthreeon");
+        feed(&mut ed_state, "\".p");
+        assert_eq!(ed_state.command_line.buffer, "Nothing in register .\n");
+        feed(&mut ed_state, "vl\".y");
+        assert_eq!(ed_state.command_line.buffer, "Register . is read-only\n");
+    }
+    #[test]
+    fn test_small_deletes_go_into_the_minus_register() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one two three\nfour");
+        feed(&mut ed_state, "dw");
+        assert_eq!(buffer_text(&ed_state), "two three\nfour");
+        assert_eq!(ed_state.registers.get('-').unwrap().text, "one ");
+        // `dw` on the last word of a line keeps the line break
+        feed(&mut ed_state, "wdw");
+        assert_eq!(buffer_text(&ed_state), "two \nfour");
+        assert_eq!(ed_state.registers.get('-').unwrap().text, "three");
+        assert_eq!(ed_state.get_buffer().cursor(), 3);
+        feed(&mut ed_state, "x");
+        assert_eq!(buffer_text(&ed_state), "two\nfour");
+        assert_eq!(ed_state.registers.get('-').unwrap().text, " ");
+        feed(&mut ed_state, "jD");
+        assert_eq!(buffer_text(&ed_state), "two\nfo");
+        assert_eq!(ed_state.registers.get('-').unwrap().text, "ur");
+        assert_eq!(ed_state.registers.get('1'), None);
+    }
+    #[test]
+    fn test_x_and_p_swap_characters() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("ab");
+        feed(&mut ed_state, "xp");
+        assert_eq!(buffer_text(&ed_state), "ba");
+    }
+    #[test]
+    fn test_yank_motions() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one two\nthree");
+        feed(&mut ed_state, "wye");
+        assert_eq!(ed_state.registers.get('0').unwrap().text, "two");
+        assert_eq!(buffer_text(&ed_state), "one two\nthree");
+        feed(&mut ed_state, "jyk");
+        assert_eq!(ed_state.registers.get('0').unwrap().text, "one two\nthree\n");
+        assert_eq!(ed_state.get_buffer().cursor_position().0, 0);
+        feed(&mut ed_state, "\"ay$");
+        assert_eq!(ed_state.registers.get('a').unwrap().text, "two");
+    }
+    #[test]
+    fn test_line_deletes_shift_the_numbered_registers() {
+        let mut ed_state = new_ed_state();
+        let lines = ('a'..='k').map(String::from).collect::<Vec<_>>();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text(&lines.join("\n"));
+        feed(&mut ed_state, "djdddddddddddddddd");
+        assert_eq!(buffer_text(&ed_state), "k");
+        let numbered = ('1'..='9')
+            .map(|name| ed_state.registers.get(name).unwrap().text.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(numbered, ["j\n", "i\n", "h\n", "g\n", "f\n", "e\n", "d\n", "c\n", "a\nb\n"]);
+        assert_eq!(ed_state.registers.get('-'), None);
+    }
+    #[test]
+    fn test_read_only_registers_and_listing() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, "iab<BS>c<Esc>:set tabsize=2<CR>\".p\":P");
+        assert_eq!(buffer_text(&ed_state), "acaset tabsize=2c");
+        feed(&mut ed_state, "vx:reg\"-.:<CR>");
+        assert_eq!(
+            ed_state.command_line.buffer,
+            concat!(
+                "Type Name Content\n",
+                "  c  \"\"   2\n",
+                "  c  \"-   2\n",
+                "  c  \".   ac\n",
+                "  c  \":   set tabsize=2\n"
+            )
+        );
+    }
+    #[test]
+    fn test_put_block() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\nt");
+        let block = crate::registers::Register::new(
+            "ab\nc\nd".to_string(),
+            crate::registers::RegisterKind::Blockwise,
+        );
+        ed_state.registers.yank(Some('b'), block);
+        // short lines are padded and missing lines are added
+        feed(&mut ed_state, "ll2\"bp");
+        assert_eq!(buffer_text(&ed_state), "oneabab\nt  c c\n   d d");
+        assert_eq!(ed_state.get_buffer().cursor(), 3);
+    }
+    #[test]
+    fn test_clipboard_registers() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("hi there");
+        feed(&mut ed_state, ":set clipboard=osc52<CR>vl\"+y");
+        let mut output = Vec::new();
+        crate::render::write_terminal_output(&mut ed_state, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "\x1b]52;c;aGk=\x07");
+        assert!(ed_state.terminal_output.is_empty());
+        // OSC 52 can not paste, so the text that was copied last is put
+        feed(&mut ed_state, "$\"+p");
+        assert_eq!(buffer_text(&ed_state), "hi therehi");
+
+        let path = std::env::temp_dir().join(format!("miv-clipboard-{}", std::process::id()));
+        feed(
+            &mut ed_state,
+            &format!(
+                ":set clipboard=custom,osc52 clipcopy=cat\\ >{} clippaste=printf\\ pasted<CR>",
+                path.display()
+            ),
+        );
+        // the tools run in the background
+        feed(&mut ed_state, "0vl\"*d\"*P");
+        assert_eq!(buffer_text(&ed_state), " therehi");
+        finish_tasks(&mut ed_state);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi");
+        assert!(ed_state.terminal_output.is_empty());
+        assert_eq!(buffer_text(&ed_state), "pasted therehi");
+        std::fs::remove_file(&path).unwrap();
+        // listing the registers does not read the clipboard
+        feed(&mut ed_state, ":reg *<CR>");
+        assert_eq!(ed_state.command_line.buffer, "Type Name Content\n  c  \"*   pasted\n");
+        assert!(ed_state.scheduler.is_idle());
+    }
+    #[test]
+    fn test_visual_line_delete() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo\nthree");
+        feed(&mut ed_state, "jVd");
+        assert_eq!(buffer_text(&ed_state), "one\nthree");
+        assert_eq!(ed_state.registers.get('"').unwrap().text, "two\n");
+    }
+    #[test]
+    fn test_visual_block_delete_and_put() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo\nthree");
+        feed(&mut ed_state, "l<C-v>jlx");
+        assert_eq!(buffer_text(&ed_state), "o\nt\nthree");
+        assert_eq!(
+            ed_state.registers.get('"').unwrap().kind,
+            crate::registers::RegisterKind::Blockwise
+        );
+        feed(&mut ed_state, "p");
+        assert_eq!(buffer_text(&ed_state), "one\ntwo\nthree");
+    }
+    #[test]
+    fn test_visual_block_insert() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo\nthree");
+        // the text typed after `I` goes into every line of the block
+        feed(&mut ed_state, "<C-v>jjI- <Esc><Esc>");
+        assert_eq!(buffer_text(&ed_state), "- one\n- two\n- three");
+    }
+    #[test]
+    fn test_visual_block_append_after_line_ends() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo\nthree");
+        feed(&mut ed_state, "<C-v>jl$A;<Esc><Esc>");
+        assert_eq!(buffer_text(&ed_state), "one;\ntwo;\nthree");
+    }
+    #[test]
+    fn test_visual_block_change() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\ntwo\nthree");
+        feed(&mut ed_state, "l<C-v>jjcX<Esc><Esc>");
+        assert_eq!(buffer_text(&ed_state), "oXe\ntXo\ntXree");
+    }
+    #[test]
+    fn test_visual_uppercase() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\n  two\nthree");
+        feed(&mut ed_state, "vjllU");
+        assert_eq!(buffer_text(&ed_state), "ONE\n  Two\nthree");
+    }
+    #[test]
+    fn test_visual_toggle_case_of_lines() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\n  tWo\nthree");
+        feed(&mut ed_state, "jV~");
+        assert_eq!(buffer_text(&ed_state), "one\n  TwO\nthree");
+    }
+    #[test]
+    fn test_visual_reselect_and_swap_ends() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\n  two\nthree");
+        // `gv` selects the same characters again, `o` turns the selection around
+        feed(&mut ed_state, "vjllUgvohu");
+        assert_eq!(buffer_text(&ed_state), "one\n  two\nthree");
+    }
+    #[test]
+    fn test_visual_join() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\n  two\nthree");
+        feed(&mut ed_state, "VjJ");
+        assert_eq!(buffer_text(&ed_state), "one two\nthree");
+        assert_eq!(ed_state.get_buffer().cursor(), 3);
+    }
+    #[test]
+    fn test_visual_shift() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\nthree");
+        feed(&mut ed_state, ":set tabsize=2<CR>Vj>");
+        assert_eq!(buffer_text(&ed_state), "  one\n  three");
+        assert_eq!(ed_state.get_buffer().cursor(), 2);
+    }
+    #[test]
+    fn test_visual_replace_chars() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one two");
+        feed(&mut ed_state, "wvlrx");
+        assert_eq!(buffer_text(&ed_state), "one xxo");
+    }
+    #[test]
+    fn test_visual_highlighted_ranges() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one two\nthree");
+        feed(&mut ed_state, "w<C-v>jh");
+        assert_eq!(crate::visual::highlighted_ranges(&ed_state), vec![3..5, 11..13]);
+        feed(&mut ed_state, "V");
+        assert_eq!(crate::visual::highlighted_ranges(&ed_state), vec![0..13]);
+    }
+    #[test]
+    fn test_visual_marks_stay_on_edited_text() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("one\nthree");
+        // the lines get longer in front of the end of the selection
+        feed(&mut ed_state, ":set tabsize=2<CR>Vj>gv<lt>");
+        assert_eq!(buffer_text(&ed_state), "one\nthree");
+    }
+    #[test]
+    fn test_replace_mode_and_replace_chars() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("abc\ndef");
+        // backspace puts back what was overwritten
+        feed(&mut ed_state, "Rxy<BS><BS>z<Esc>");
+        assert_eq!(buffer_text(&ed_state), "zbc\ndef");
+        // at the end of the line the characters are inserted
+        feed(&mut ed_state, "$Rqrs<BS><Esc>");
+        assert_eq!(buffer_text(&ed_state), "zbqr\ndef");
+        assert_eq!(ed_state.registers.get('.').unwrap().text, "qr");
+        feed(&mut ed_state, "u");
+        assert_eq!(buffer_text(&ed_state), "zbc\ndef");
+        feed(&mut ed_state, "j02rX");
+        assert_eq!(buffer_text(&ed_state), "zbc\nXXf");
+        assert_eq!(ed_state.get_buffer().cursor(), 5);
+        feed(&mut ed_state, "04rY");
+        assert_eq!(buffer_text(&ed_state), "zbc\nXXf");
+        feed(&mut ed_state, "lr<CR>");
+        assert_eq!(buffer_text(&ed_state), "zbc\nX\nf");
+        assert_eq!(ed_state.get_buffer().cursor_position(), (2, 0));
+        // insert mode mappings work in replace mode too
+        feed(&mut ed_state, ":imap jj JJ<CR>ggRjj<Esc>");
+        assert_eq!(buffer_text(&ed_state), "JJc\nX\nf");
+        // `r` takes any character (in visual mode too), <Esc> cancels it
+        feed(&mut ed_state, "0rär<Esc>lvlré");
+        assert_eq!(buffer_text(&ed_state), "äéé\nX\nf");
+        // so do the insert mode editing keys
+        feed(&mut ed_state, "jRx<C-v>u00e8y<C-w>z<C-o>0");
+        assert_eq!(buffer_text(&ed_state), "äéé\nz\nf");
+        assert!(matches!(ed_state.mode, crate::editor_state::EditorMode::Replace { .. }));
+    }
+    #[test]
+    fn test_autoindent_smartindent_and_soft_tabs() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":set ai?<CR>");
+        assert_eq!(ed_state.command_line.buffer, "noautoindent\n");
+        feed(&mut ed_state, ":set si sw=2 noap<CR>iif x {<CR>y<CR>}<Esc>");
+        assert_eq!(buffer_text(&ed_state), "if x {\n  y\n}");
+        assert_eq!(ed_state.registers.get('.').unwrap().text, "if x {\n  y\n}");
+        // autoindent alone copies the indentation
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("  ab");
+        feed(&mut ed_state, ":set nosi ai<CR>$i<CR><Esc>");
+        assert_eq!(buffer_text(&ed_state), "  a\n  b");
+        // python blocks open after a colon
+        ed_state.buffers[0] = buffer_with_file("", "t.py");
+        feed(&mut ed_state, ":set si sw=4<CR>idef f():<CR>pass<Esc>");
+        assert_eq!(buffer_text(&ed_state), "def f():\n    pass");
+        // soft tabs are filled up with tabs without expandtab and backspace takes them back
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("");
+        feed(&mut ed_state, ":set noai nosi et! sts=4 ts=8<CR>i<Tab>");
+        assert_eq!(buffer_text(&ed_state), "    ");
+        feed(&mut ed_state, "<Tab>");
+        assert_eq!(buffer_text(&ed_state), "\t");
+        feed(&mut ed_state, "<BS>");
+        assert_eq!(buffer_text(&ed_state), "    ");
+        feed(&mut ed_state, "<BS><Esc>");
+        assert_eq!(buffer_text(&ed_state), "");
+        // with expandtab, backspace removes the spaces of a whole soft tab
+        feed(&mut ed_state, ":set et sts=2<CR>ia<Tab><Tab><BS>b<Esc>");
+        assert_eq!(buffer_text(&ed_state), "a b");
+    }
+    #[test]
+    fn test_autopairs() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, "if(a<Esc>");
+        assert_eq!(buffer_text(&ed_state), "f(a)");
+        // closers are skipped over, quotes in words are left alone
+        feed(&mut ed_state, "i)[\"don't<Esc>");
+        assert_eq!(buffer_text(&ed_state), "f(a)[\"don't\"]");
+        // backspace between an empty pair deletes both
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("");
+        feed(&mut ed_state, "ix = ({<BS><BS>y<Esc>");
+        assert_eq!(buffer_text(&ed_state), "x = y");
+        // enter between braces opens a block
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("");
+        feed(&mut ed_state, ":set sw=2<CR>i  fn f() {<CR>x<Esc>");
+        assert_eq!(buffer_text(&ed_state), "  fn f() {\n    x\n  }");
+        assert_eq!(ed_state.registers.get('.').unwrap().text, "  fn f() {\n    x");
+        // pairs are set per filetype
+        ed_state.buffers[0] = buffer_with_file("", "t.rs");
+        feed(&mut ed_state, ":set pairs=rust:<lt>><CR>i'<lt>(<Esc>");
+        assert_eq!(buffer_text(&ed_state), "'<(>");
+        feed(&mut ed_state, ":set noap<CR>0i[<Esc>");
+        assert_eq!(buffer_text(&ed_state), "['<(>");
+    }
+    #[test]
+    fn test_keyword_completion() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("\nfooqux foobar fooqux");
+        // the closest words come first, after the last one the typed text comes back
+        feed(&mut ed_state, "ifo<C-n>");
+        assert_eq!(buffer_text(&ed_state), "fooqux\nfooqux foobar fooqux");
+        let completion = crate::completion::active(&ed_state).unwrap();
+        assert_eq!(completion.candidates.len(), 2);
+        feed(&mut ed_state, "<C-n>");
+        assert_eq!(buffer_text(&ed_state), "foobar\nfooqux foobar fooqux");
+        feed(&mut ed_state, "<C-n>");
+        assert_eq!(buffer_text(&ed_state), "fo\nfooqux foobar fooqux");
+        feed(&mut ed_state, "<C-p><C-y>");
+        assert!(crate::completion::active(&ed_state).is_none());
+        feed(&mut ed_state, " fo<C-n><C-e>!");
+        assert_eq!(buffer_text(&ed_state), "foobar fo!\nfooqux foobar fooqux");
+        // other buffers are searched too, typing closes the popup
+        ed_state.buffers.push(crate::editor_buffer::Buffer::from_text("fabulous"));
+        feed(&mut ed_state, "<CR>fa<C-n>.<Esc>");
+        assert_eq!(buffer_text(&ed_state), "foobar fo!\nfabulous.\nfooqux foobar fooqux");
+        assert_eq!(ed_state.registers.get('.').unwrap().text, "foobar fo!\nfabulous.");
+        feed(&mut ed_state, "ixyz<C-n>");
+        assert_eq!(ed_state.command_line.buffer, "No matches\n");
+    }
+    #[test]
+    fn test_line_file_and_dictionary_completion() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("  let x = 1;\n");
+        feed(&mut ed_state, "Gi  le<C-x><C-l><C-y><Esc>");
+        assert_eq!(buffer_text(&ed_state), "  let x = 1;\n  let x = 1;");
+        let dir = std::env::temp_dir().join(format!("miv-completion-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("subdir")).unwrap();
+        std::fs::write(dir.join("words"), "apple\napricot banana\n").unwrap();
+        let path = dir.to_str().unwrap();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("");
+        feed(&mut ed_state, &format!("i{path}/s<C-x><C-f><Esc>"));
+        assert_eq!(buffer_text(&ed_state), format!("{path}/subdir/"));
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("");
+        feed(&mut ed_state, &format!(":set dict={path}/words cpt=dictionary<CR>iap<C-p><C-p>"));
+        assert_eq!(buffer_text(&ed_state), "apple");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_insert_mode_editing_keys() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, "i  foo.bar baz  <C-w><C-w>x<C-w><C-w>");
+        assert_eq!(buffer_text(&ed_state), "  foo");
+        feed(&mut ed_state, "<CR>qux<C-u>y<C-u>");
+        assert_eq!(buffer_text(&ed_state), "  foo\n");
+        // <C-w> at the start of a line joins it with the line above
+        feed(&mut ed_state, "<C-w><Esc>");
+        assert_eq!(buffer_text(&ed_state), "  foo");
+        // <C-r> inserts a register
+        feed(&mut ed_state, "yy0i<C-r>\"<C-r>z<Esc>");
+        assert_eq!(buffer_text(&ed_state), "  foo\n  foo");
+        // <C-t> and <C-d> indent to multiples of shiftwidth, keeping the cursor on its text
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("   x");
+        feed(&mut ed_state, ":set sw=2<CR>$i<C-t>y");
+        assert_eq!(buffer_text(&ed_state), "    yx");
+        feed(&mut ed_state, "<C-d>z");
+        assert_eq!(buffer_text(&ed_state), "  yzx");
+    }
+    #[test]
+    fn test_insert_mode_command_and_literal_keys() {
+        let mut ed_state = new_ed_state();
+        // <C-o> runs one normal mode command
+        feed(&mut ed_state, "ifoo bar<C-o>0x<C-o>wy");
+        assert_eq!(buffer_text(&ed_state), "xfoo ybar");
+        assert!(matches!(ed_state.mode, crate::editor_state::EditorMode::Insert));
+        // also after a command line, run or cancelled
+        feed(&mut ed_state, "<C-o>:set noap<CR>(");
+        assert!(matches!(ed_state.mode, crate::editor_state::EditorMode::Insert));
+        assert!(ed_state.insert_after_command.is_none());
+        feed(&mut ed_state, "<C-o>:<Esc>)");
+        assert_eq!(buffer_text(&ed_state), "xfoo y()bar");
+        // <C-v> inserts keys literally, or characters by their code
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("");
+        feed(&mut ed_state, "<C-v><Tab><C-v>u00e9<C-v>065<C-v>u41z<C-v><C-a><C-v>x4");
+        assert_eq!(buffer_text(&ed_state), "\téAAz\u{1}");
+        feed(&mut ed_state, "1<C-v>300<Esc>");
+        assert_eq!(buffer_text(&ed_state), "\téAAz\u{1}A\u{1e}0");
+    }
+    #[test]
+    fn test_insert_abbreviations() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":iab teh the<CR>iteh teh.uteh teh<CR>");
+        assert_eq!(buffer_text(&ed_state), "the the.uteh the\n");
+        feed(&mut ed_state, "<Esc>:iab<CR>");
+        assert_eq!(ed_state.registers.get('.').unwrap().text, "the the.uteh the\n");
+        assert_eq!(ed_state.command_line.buffer, "i  teh           the\n");
+        // buffer-local abbreviations go first and are only listed with their buffer
+        ed_state.command_line.buffer.clear();
+        feed(&mut ed_state, ":iab <lt>buffer> teh THE<CR>:ab t<CR>");
+        assert_eq!(
+            ed_state.command_line.buffer,
+            "i  teh          @THE\ni  teh           the\n"
+        );
+        // a new buffer does not have them
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("");
+        feed(&mut ed_state, "iteh <Esc>:iuna teh<CR>:una teh<CR>0iteh <Esc>");
+        assert_eq!(buffer_text(&ed_state), "teh the ");
+        assert_eq!(ed_state.command_line.buffer, "No such abbreviation: teh\n");
+        // keys that expand nothing change the buffer once
+        let change_tick = ed_state.get_buffer().change_tick;
+        feed(&mut ed_state, "i.");
+        assert_eq!(ed_state.get_buffer().change_tick, change_tick + 1);
+    }
+    #[test]
+    fn test_command_line_abbreviations() {
+        let mut ed_state = new_ed_state();
+        feed(&mut ed_state, ":cabbrev W set<CR>:W ts=3<CR>");
+        assert_eq!(ed_state.editor_globals.tab_size, 3);
+        // insert mode is not affected
+        feed(&mut ed_state, "iW W<Esc>");
+        assert_eq!(buffer_text(&ed_state), "W W");
+        feed(&mut ed_state, ":cabc<CR>:W<CR>");
+        assert_eq!(ed_state.command_line.buffer, "Not an editor command: W\n");
+    }
+    #[test]
+    fn test_comment_operator() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = buffer_with_file("  a\n\n    b\nc", "t.py");
+        // the markers go after the smallest indentation, blank lines are left alone
+        feed(&mut ed_state, "2gcj");
+        assert_eq!(buffer_text(&ed_state), "  # a\n\n  #   b\nc");
+    }
+    #[test]
+    fn test_comment_operator_comments_partly_commented_lines() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = buffer_with_file("  # a\n\n  #   b\nc", "t.py");
+        feed(&mut ed_state, "gcG");
+        assert_eq!(buffer_text(&ed_state), "#   # a\n\n#   #   b\n# c");
+    }
+    #[test]
+    fn test_comment_operator_uncomments_commented_lines() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = buffer_with_file("#   # a\n\n#   #   b\n# c", "t.py");
+        feed(&mut ed_state, "gcG");
+        assert_eq!(buffer_text(&ed_state), "  # a\n\n  #   b\nc");
+    }
+    #[test]
+    fn test_visual_comment() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = buffer_with_file("  # a\n\n  #   b\nc", "t.py");
+        feed(&mut ed_state, "Vjjgc");
+        assert_eq!(buffer_text(&ed_state), "  a\n\n    b\nc");
+    }
+    #[test]
+    fn test_comment_line_and_repeat() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = buffer_with_file("  a\n\n    b\nc", "t.py");
+        feed(&mut ed_state, "Ggcck.");
+        assert_eq!(buffer_text(&ed_state), "  a\n\n    # b\n# c");
+    }
+    #[test]
+    fn test_comment_with_end_marker() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = buffer_with_file("a {}", "t.css");
+        feed(&mut ed_state, "gcc");
+        assert_eq!(buffer_text(&ed_state), "/* a {} */");
+        feed(&mut ed_state, "gcc");
+        assert_eq!(buffer_text(&ed_state), "a {}");
+    }
+    #[test]
+    fn test_increment_number_after_cursor() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("x = 9; y = -1");
+        // the number at or after the cursor changes, the cursor goes to its end
+        feed(&mut ed_state, "<C-a>");
+        assert_eq!(buffer_text(&ed_state), "x = 10; y = -1");
+        assert_eq!(ed_state.get_buffer().cursor(), 5);
+    }
+    #[test]
+    fn test_increment_by_count() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("y = -1");
+        feed(&mut ed_state, "3<C-a>");
+        assert_eq!(buffer_text(&ed_state), "y = 2");
+    }
+    #[test]
+    fn test_decrement_below_zero() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("y = 2");
+        feed(&mut ed_state, "5<C-x>");
+        assert_eq!(buffer_text(&ed_state), "y = -3");
+    }
+    #[test]
+    fn test_increment_keeps_prefixes_case_and_zeros() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] =
+            crate::editor_buffer::Buffer::from_text("0xfF 0X0f 0b0111 0o7 007 2024-01-09");
+        feed(&mut ed_state, "<C-a>w<C-a>w<C-a>w<C-a>w<C-x>w<C-a>");
+        assert_eq!(buffer_text(&ed_state), "0x100 0X10 0b1000 0o10 006 2025-01-09");
+    }
+    #[test]
+    fn test_increment_is_repeated_with_dot() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("0x100");
+        feed(&mut ed_state, "<C-x>.");
+        assert_eq!(buffer_text(&ed_state), "0x0fe");
+    }
+    #[test]
+    fn test_increment_leaves_long_numbers_alone() {
+        let mut ed_state = new_ed_state();
+        let long = "9999999999999999999999999999999999999999 0x0123456789abcdef0123456789abcdef";
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text(long);
+        feed(&mut ed_state, "<C-a>w<C-x>");
+        assert_eq!(buffer_text(&ed_state), long);
+    }
+    #[test]
+    fn test_increment_selected_numbers_progressively() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("1. a\n1. b\nc\n1. d");
+        feed(&mut ed_state, "VGg<C-a>");
+        assert_eq!(buffer_text(&ed_state), "2. a\n3. b\nc\n4. d");
+        assert!(matches!(ed_state.mode, crate::editor_state::EditorMode::Normal));
+    }
+    #[test]
+    fn test_decrement_selected_numbers() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("2. a\n3. b\nc\n4. d");
+        feed(&mut ed_state, "jVj2<C-x>");
+        assert_eq!(buffer_text(&ed_state), "2. a\n1. b\nc\n4. d");
+    }
+    #[test]
+    fn test_shift_operators_and_retab() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] = crate::editor_buffer::Buffer::from_text("a\nb\n\nc");
+        feed(&mut ed_state, ":set sw=2<CR>2>>");
+        assert_eq!(buffer_text(&ed_state), "  a\n  b\n\nc");
+        assert_eq!(ed_state.get_buffer().cursor(), 2);
+        // empty lines are not indented
+        feed(&mut ed_state, "j.");
+        assert_eq!(buffer_text(&ed_state), "  a\n    b\n\nc");
+        feed(&mut ed_state, ">G<lt>gg");
+        assert_eq!(buffer_text(&ed_state), "a\n    b\n\n  c");
+        feed(&mut ed_state, ":set noet ts=4<CR>j>>");
+        assert_eq!(buffer_text(&ed_state), "a\n\t  b\n\n  c");
+        // runs with tabs are redone for the new tab size, with ! the ones of spaces too
+        feed(&mut ed_state, ":set et<CR>:retab 2<CR>");
+        assert_eq!(buffer_text(&ed_state), "a\n      b\n\n  c");
+        assert_eq!(ed_state.editor_globals.tab_size, 2);
+        feed(&mut ed_state, ":set noet<CR>:retab<CR>");
+        assert_eq!(buffer_text(&ed_state), "a\n      b\n\n  c");
+        feed(&mut ed_state, ":retab!<CR>");
+        assert_eq!(buffer_text(&ed_state), "a\n\t\t\tb\n\n\tc");
+    }
+    #[test]
+    fn test_reindent_operator() {
+        let mut ed_state = new_ed_state();
+        ed_state.buffers[0] =
+            crate::editor_buffer::Buffer::from_text("fn f() {\nif x {\ny\n}\n  \n}");
+        feed(&mut ed_state, "=G");
+        assert_eq!(
+            buffer_text(&ed_state),
+            "fn f() {\n    if x {\n        y\n    }\n\n}"
+        );
+        // without closers, lines may end a block by being further left
+        ed_state.buffers[0] = buffer_with_file("if x:\ny\n        z\nw", "t.py");
+        feed(&mut ed_state, "VG=");
+        assert_eq!(buffer_text(&ed_state), "if x:\n    y\n    z\nw");
+        assert_eq!(ed_state.mode.kind(), crate::editor_state::ModeKind::Normal);
+    }
 }
@@ -1,10 +1,44 @@
 use std::path::PathBuf;
 
+/// A change made at a cursor: `range` (around the cursor) is replaced with `text` and the cursor
+/// ends up `cursor` characters into it
+#[derive(Clone)]
+pub(crate) struct CursorEdit {
+    pub(crate) range: std::ops::Range<usize>,
+    pub(crate) text: Vec<char>,
+    pub(crate) cursor: usize,
+}
+
+impl CursorEdit {
+    /// Replaces `count` characters in front of `head` with `text`, the cursor goes behind it
+    pub(crate) fn before(head: usize, count: usize, text: Vec<char>) -> Self {
+        Self {
+            range: head.saturating_sub(count)..head,
+            cursor: text.len(),
+            text,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct Buffer {
     pub(crate) content: crate::gap_buffer::GapBuffer<char>,
     pub(crate) name: String,
     pub(crate) location: Option<PathBuf>,
+    pub(crate) undo: crate::undo::UndoTree,
+    /// incremented on every change to `content` (like vim's `b:changedtick`)
+    pub(crate) change_tick: usize,
+    /// the cursors as offsets into `content`, their screen positions are computed from them
+    pub(crate) selections: crate::selections::Selections,
+    /// positions that move along with the text, see `shift_positions`
+    pub(crate) anchors: crate::anchors::Anchors,
+    pub(crate) changelist: crate::jumplist::Changelist,
+    /// the buffer-local marks (`a`-`z` and the special ones that are set by the editor)
+    pub(crate) marks: std::collections::HashMap<char, crate::anchors::AnchorId>,
+    /// how the last visual area (between the `'<` and `'>` marks) was selected, for `gv`
+    pub(crate) last_visual: Option<crate::visual::LastVisual>,
+    /// the abbreviations defined with `:iabbrev <buffer>` and `:cabbrev <buffer>`
+    pub(crate) abbreviations: crate::abbreviations::Abbreviations,
 }
 
 impl Buffer {
@@ -13,6 +47,270 @@ impl Buffer {
             content: crate::gap_buffer::GapBuffer::<char>::new_empty(),
             name: String::new(),
             location: None,
+            undo: crate::undo::UndoTree::default(),
+            change_tick: 0,
+            selections: crate::selections::Selections::default(),
+            anchors: crate::anchors::Anchors::default(),
+            changelist: crate::jumplist::Changelist::default(),
+            marks: std::collections::HashMap::new(),
+            last_visual: None,
+            abbreviations: crate::abbreviations::Abbreviations::default(),
         }
     }
+
+    /// Loads the file at `path`, a file that does not exist yet gives an empty buffer that will be
+    /// written to `path`
+    pub(crate) fn from_file(path: &std::path::Path) -> std::io::Result<Self> {
+        let mut buffer = match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_text(&text),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Self::new(),
+            Err(error) => return Err(error),
+        };
+        buffer.name = path.display().to_string();
+        buffer.location = Some(path.to_path_buf());
+        Ok(buffer)
+    }
+
+    pub(crate) fn from_text(text: &str) -> Self {
+        let mut buffer = Self::new();
+        buffer
+            .content
+            .insert(&text.chars().collect::<Vec<char>>());
+        buffer
+    }
+
+    /// Offset of the primary cursor
+    pub(crate) fn cursor(&self) -> usize {
+        self.selections.primary().head
+    }
+
+    /// Moves the cursors and anchors along with the text after `range` was replaced with
+    /// `inserted` characters
+    pub(crate) fn shift_positions(&mut self, range: &std::ops::Range<usize>, inserted: usize) {
+        self.selections.shift(range, inserted);
+        self.anchors.shift(range, inserted);
+    }
+
+    /// Replaces `range` with `text`, moves the cursors and anchors along and records the edit for
+    /// undo. Returns the replaced text.
+    pub(crate) fn replace(&mut self, range: std::ops::Range<usize>, text: &[char]) -> Vec<char> {
+        if range.is_empty() && text.is_empty() {
+            return Vec::new();
+        }
+        let edit = crate::undo::UndoEdit {
+            range: range.clone(),
+            old: self.content.slice(range.clone()),
+            new: text.to_vec(),
+        };
+        self.undo.record(edit, &self.selections);
+        self.replace_unrecorded(range, text)
+    }
+
+    /// `replace` without recording the edit, for undo and redo themselves
+    pub(crate) fn replace_unrecorded(
+        &mut self,
+        range: std::ops::Range<usize>,
+        text: &[char],
+    ) -> Vec<char> {
+        let old = self.content.slice(range.clone());
+        self.content.replace(range.clone(), text);
+        self.shift_positions(&range, text.len());
+        old
+    }
+
+    /// Makes the edits since the last call one undo step (unless an insert session is running)
+    pub(crate) fn finish_undo_step(&mut self) {
+        self.undo.commit(&self.selections);
+    }
+
+    /// Sets every cursor to the column it is in now, after edits moved them
+    fn reset_desired_columns(&mut self) {
+        let mut selections = self.selections.clone();
+        selections.map(|selection| {
+            crate::selections::Selection::cursor(selection.head, self.position(selection.head).1)
+        });
+        self.selections = selections;
+    }
+
+    /// Inserts `text` at every cursor and moves the cursors behind it
+    pub(crate) fn insert_at_cursors(&mut self, text: &[char]) {
+        self.edit_at_cursors(|_, head| CursorEdit::before(head, 0, text.to_vec()));
+    }
+
+    /// Deletes up to `count` characters in front of every cursor (but never across another one)
+    #[allow(dead_code)]
+    pub(crate) fn delete_before_cursors(&mut self, count: usize) {
+        self.edit_at_cursors(|_, head| CursorEdit::before(head, count, Vec::new()));
+    }
+
+    /// Makes the edit `edit` gives for the offset of every cursor, the edits never reach across
+    /// another cursor or into the edit of the next one
+    pub(crate) fn edit_at_cursors(&mut self, edit: impl Fn(&Self, usize) -> CursorEdit) {
+        let heads = self.selections.iter().map(|s| s.head).collect::<Vec<_>>();
+        let edits = heads.iter().map(|&head| edit(self, head)).collect::<Vec<_>>();
+        // how far each cursor has to go from where the edits shift it to
+        let mut moved = vec![0; heads.len()];
+        let mut limit = usize::MAX;
+        // back to front, so the offsets of the cursors in front stay valid
+        for (i, (&head, edit)) in heads.iter().zip(edits).enumerate().rev() {
+            let previous = if i == 0 { 0 } else { heads[i - 1] };
+            let range = edit.range.start.max(previous)..edit.range.end.min(limit).max(head);
+            let shifted = crate::transaction::shift_offset(head, &range, edit.text.len());
+            moved[i] = (range.start + edit.cursor.min(edit.text.len())) as isize - shifted as isize;
+            self.replace(range.clone(), &edit.text);
+            limit = range.start;
+        }
+        let mut moved = moved.into_iter();
+        let mut selections = self.selections.clone();
+        selections.map(|selection| {
+            let head = selection.head.saturating_add_signed(moved.next().unwrap_or(0));
+            crate::selections::Selection::cursor(head, selection.desired_column)
+        });
+        self.selections = selections;
+        self.reset_desired_columns();
+        crate::jumplist::record_change(self, self.cursor());
+        self.finish_undo_step();
+        self.change_tick += 1;
+    }
+
+    /// Overwrites the character under every cursor with `c` and moves the cursors behind it. At the
+    /// end of a line (and for line breaks) `c` is inserted instead. Returns the overwritten
+    /// characters, `None` where `c` was inserted.
+    pub(crate) fn overwrite_at_cursors(&mut self, c: char) -> Vec<Option<char>> {
+        let heads = self.selections.iter().map(|s| s.head).collect::<Vec<_>>();
+        let mut overwritten = vec![None; heads.len()];
+        for (i, &head) in heads.iter().enumerate().rev() {
+            match self.char_at(head) {
+                Some(old) if old != '\n' && c != '\n' => {
+                    self.replace(head..head + 1, &[c]);
+                    overwritten[i] = Some(old);
+                }
+                _ => {
+                    self.replace(head..head, &[c]);
+                }
+            }
+        }
+        // inserting moved the cursors along, overwriting did not
+        let mut moved = overwritten.iter().map(|old| old.is_some() as usize);
+        let mut selections = self.selections.clone();
+        selections.map(|selection| {
+            let head = selection.head + moved.next().unwrap_or(0);
+            crate::selections::Selection::cursor(head, selection.desired_column)
+        });
+        self.selections = selections;
+        self.reset_desired_columns();
+        crate::jumplist::record_change(self, self.cursor());
+        self.finish_undo_step();
+        self.change_tick += 1;
+        overwritten
+    }
+
+    /// Takes back `overwrite_at_cursors` in front of every cursor: `restore` holds what each
+    /// cursor overwrote there (`Some(None)` if it inserted), cursors without anything to restore
+    /// just move left within their line
+    pub(crate) fn restore_before_cursors(&mut self, restore: &[Option<Option<char>>]) {
+        let heads = self.selections.iter().map(|s| s.head).collect::<Vec<_>>();
+        let mut moved = vec![false; heads.len()];
+        let mut changed = false;
+        for (i, &head) in heads.iter().enumerate().rev() {
+            match restore.get(i) {
+                Some(Some(Some(old))) if head > 0 => {
+                    self.replace(head - 1..head, &[*old]);
+                    moved[i] = true;
+                    changed = true;
+                }
+                Some(Some(None)) if head > 0 => {
+                    self.replace(head - 1..head, &[]);
+                    changed = true;
+                }
+                _ => moved[i] = head > self.line_start(head),
+            }
+        }
+        let mut moved = moved.into_iter();
+        let mut selections = self.selections.clone();
+        selections.map(|selection| {
+            let head = selection.head - moved.next().unwrap_or(false) as usize;
+            crate::selections::Selection::cursor(head, selection.desired_column)
+        });
+        self.selections = selections;
+        self.reset_desired_columns();
+        if changed {
+            crate::jumplist::record_change(self, self.cursor());
+            self.finish_undo_step();
+            self.change_tick += 1;
+        }
+    }
+
+    /// (line, column) of the primary cursor
+    pub(crate) fn cursor_position(&self) -> (usize, usize) {
+        self.position(self.cursor())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    pub(crate) fn char_at(&self, offset: usize) -> Option<char> {
+        self.content.get(offset).copied()
+    }
+
+    /// Offset of the first character of the line containing `offset`
+    pub(crate) fn line_start(&self, offset: usize) -> usize {
+        let mut start = offset.min(self.len());
+        while start > 0 && self.char_at(start - 1) != Some('\n') {
+            start -= 1;
+        }
+        start
+    }
+
+    /// Offset of the newline ending the line containing `offset` (or the buffer length for the
+    /// last line)
+    pub(crate) fn line_end(&self, offset: usize) -> usize {
+        let mut end = offset.min(self.len());
+        while end < self.len() && self.char_at(end) != Some('\n') {
+            end += 1;
+        }
+        end
+    }
+
+    pub(crate) fn line_count(&self) -> usize {
+        self.content.iter().filter(|&&c| c == '\n').count() + 1
+    }
+
+    /// Offset of the first character of line `line` (clamped to the last line)
+    pub(crate) fn line_offset(&self, line: usize) -> usize {
+        if line == 0 {
+            return 0;
+        }
+        let mut n_lines = 0;
+        let mut last_line_start = 0;
+        for (offset, &c) in self.content.iter().enumerate() {
+            if c == '\n' {
+                n_lines += 1;
+                last_line_start = offset + 1;
+                if n_lines == line {
+                    break;
+                }
+            }
+        }
+        last_line_start
+    }
+
+    /// Converts an offset into (line, column)
+    pub(crate) fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.len());
+        let line = self
+            .content
+            .iter()
+            .take(offset)
+            .filter(|&&c| c == '\n')
+            .count();
+        (line, offset - self.line_start(offset))
+    }
+
+    /// Converts (line, column) into an offset, the column is clamped to the end of the line
+    pub(crate) fn offset(&self, line: usize, column: usize) -> usize {
+        let start = self.line_offset(line);
+        start.saturating_add(column).min(self.line_end(start))
+    }
 }
@@ -1,12 +1,32 @@
+use std::fmt::Write;
+
 pub(crate) enum EditorMode {
     Normal,
     Insert,
-    Visual { cursor_start: crate::cursor::Cursor },
+    /// the selections of the current buffer reach from their anchors to their heads
+    Visual(crate::visual::VisualKind),
+    Command,
+    /// typed characters overwrite the ones under the cursors, `replaced` holds what every cursor
+    /// overwrote so far (`None` where it inserted), Backspace puts it back
+    Replace { replaced: Vec<Vec<Option<char>>> },
+}
+
+/// The mode an `EditorMode` belongs to without any per-mode data, used to address the per-mode
+/// motion trees (eg for `:nmap` vs `:imap`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ModeKind {
+    Normal,
+    Insert,
+    Visual,
+    Command,
+    Replace,
 }
 
 #[derive(Default)]
 pub(crate) struct CommandLine {
     pub(crate) buffer: String,
+    /// what the user typed after `:` while in command mode
+    pub(crate) input: String,
 }
 
 impl std::fmt::Write for CommandLine {
@@ -24,12 +44,35 @@ impl EditorMode {
         Self::Normal
     }
 
+    #[allow(dead_code)]
     pub(crate) fn new_insert() -> Self {
         Self::Insert
     }
 
-    pub(crate) fn new_visual(cursor_start: crate::cursor::Cursor) -> Self {
-        Self::Visual { cursor_start }
+    pub(crate) fn new_replace() -> Self {
+        Self::Replace {
+            replaced: Vec::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn new_visual() -> Self {
+        Self::Visual(crate::visual::VisualKind::Charwise)
+    }
+
+    pub(crate) fn kind(&self) -> ModeKind {
+        match self {
+            Self::Normal => ModeKind::Normal,
+            Self::Insert => ModeKind::Insert,
+            Self::Visual(_) => ModeKind::Visual,
+            Self::Command => ModeKind::Command,
+            Self::Replace { .. } => ModeKind::Replace,
+        }
+    }
+
+    /// Insert and replace mode, where keys that are not bound to anything go into the buffer
+    pub(crate) fn is_typing(&self) -> bool {
+        matches!(self.kind(), ModeKind::Insert | ModeKind::Replace)
     }
 }
 
@@ -42,8 +85,37 @@ pub(crate) struct EditorGlobals {
     pub(crate) normal_mode_motion_tree: crate::motion_interpreter::MotionTree,
     pub(crate) insert_mode_motion_tree: crate::motion_interpreter::MotionTree,
     pub(crate) visual_mode_motion_tree: crate::motion_interpreter::MotionTree,
-    pub(crate) tab_size: usize,
+    pub(crate) command_mode_motion_tree: crate::motion_interpreter::MotionTree,
+    pub(crate) replace_mode_motion_tree: crate::motion_interpreter::MotionTree,
+    pub(crate) ex_commands: Vec<crate::ex_commands::ExCommand>,
+    pub(crate) tab_size: usize, // `tabstop`, how wide a tab is displayed
+    /// how much indenting adds or removes, 0 uses `tab_size`
+    pub(crate) shift_width: usize,
+    /// when not 0, Tab and Backspace in insert mode move to multiples of this many columns
+    pub(crate) soft_tab_stop: usize,
+    pub(crate) expand_tab: bool, // Indent with spaces instead of tabs
+    pub(crate) auto_indent: bool, // New lines start with the indentation of the previous one
+    /// new lines get one level more after a line opening a block, closing one removes a level
+    pub(crate) smart_indent: bool,
+    pub(crate) auto_pairs: bool, // Brackets and quotes are typed together with their closers
+    /// (filetype, pairs) set with the `pairs` option, instead of the defaults of the filetype
+    pub(crate) pairs: Vec<(String, String)>,
     pub(crate) bottom_rows_skipped: usize, // How many rows to use for buffer displaying
+    pub(crate) max_map_depth: usize, // How often mappings may expand into other mappings
+    /// where undo files go, `.` puts them next to the edited file and an empty one (the default)
+    /// turns persistent undo off
+    pub(crate) undo_dir: String,
+    pub(crate) max_undo_size: usize, // Largest undo file (in KiB) that is written or read
+    /// the order the clipboard providers are tried in
+    pub(crate) clipboard_providers: Vec<crate::clipboard::Provider>,
+    /// shell commands of the `custom` clipboard provider, the text is piped into `clip_copy` and
+    /// read from the output of `clip_paste`
+    pub(crate) clip_copy: String,
+    pub(crate) clip_paste: String,
+    /// the sources <C-n> and <C-p> complete from
+    pub(crate) complete: Vec<crate::completion::Source>,
+    pub(crate) dictionary: String, // Comma separated word lists for dictionary completion
+    pub(crate) abbreviations: crate::abbreviations::Abbreviations,
 }
 
 impl Default for EditorGlobals {
@@ -52,76 +124,292 @@ impl Default for EditorGlobals {
             normal_mode_motion_tree: crate::motion_interpreter::MotionTree::default(),
             insert_mode_motion_tree: crate::motion_interpreter::MotionTree::default(),
             visual_mode_motion_tree: crate::motion_interpreter::MotionTree::default(),
+            command_mode_motion_tree: crate::motion_interpreter::MotionTree::default(),
+            replace_mode_motion_tree: crate::motion_interpreter::MotionTree::default(),
+            ex_commands: Vec::new(),
             tab_size: 4,
+            shift_width: 0,
+            soft_tab_stop: 0,
+            expand_tab: true,
+            auto_indent: false,
+            smart_indent: false,
+            auto_pairs: true,
+            pairs: Vec::new(),
             bottom_rows_skipped: 0,
+            max_map_depth: 1000,
+            undo_dir: String::new(),
+            max_undo_size: 1024,
+            clipboard_providers: crate::clipboard::default_providers(),
+            clip_copy: String::new(),
+            clip_paste: String::new(),
+            complete: crate::completion::default_sources(),
+            dictionary: String::new(),
+            abbreviations: crate::abbreviations::Abbreviations::default(),
+        }
+    }
+}
+
+impl EditorGlobals {
+    pub(crate) fn motion_tree(&self, mode: ModeKind) -> &crate::motion_interpreter::MotionTree {
+        match mode {
+            ModeKind::Normal => &self.normal_mode_motion_tree,
+            ModeKind::Insert => &self.insert_mode_motion_tree,
+            ModeKind::Visual => &self.visual_mode_motion_tree,
+            ModeKind::Command => &self.command_mode_motion_tree,
+            ModeKind::Replace => &self.replace_mode_motion_tree,
+        }
+    }
+
+    pub(crate) fn motion_tree_mut(
+        &mut self,
+        mode: ModeKind,
+    ) -> &mut crate::motion_interpreter::MotionTree {
+        match mode {
+            ModeKind::Normal => &mut self.normal_mode_motion_tree,
+            ModeKind::Insert => &mut self.insert_mode_motion_tree,
+            ModeKind::Visual => &mut self.visual_mode_motion_tree,
+            ModeKind::Command => &mut self.command_mode_motion_tree,
+            ModeKind::Replace => &mut self.replace_mode_motion_tree,
         }
     }
 }
 
+/// A key waiting to be fed into the motion interpreter, either typed by the user or produced by
+/// expanding a mapping
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PendingKey {
+    pub(crate) atom: crate::motion_interpreter::MotionAtom,
+    /// false for keys coming from a `noremap` mapping
+    pub(crate) remap: bool,
+    /// how many mappings (or macros) were expanded to produce this key
+    pub(crate) depth: usize,
+    /// whether the key is played back from a macro (dropped when a motion fails)
+    pub(crate) from_macro: bool,
+}
+
+/// An entry of the input queue
+#[derive(Debug, Clone)]
+pub(crate) enum QueuedInput {
+    Key(PendingKey),
+    /// the macro `keys` still has to run `count` more times, it is queued behind the keys of one
+    /// run (so a huge count does not fill the queue up front)
+    MacroRuns {
+        keys: Vec<crate::motion_interpreter::MotionAtom>,
+        count: usize,
+        depth: usize,
+    },
+}
+
 pub(crate) struct EditorState {
     pub(crate) mode: EditorMode,
-    pub(crate) cursor: crate::cursor::Cursor,
     pub(crate) command_line: CommandLine,
     pub(crate) buffers: Vec<crate::editor_buffer::Buffer>,
     pub(crate) buffer_idx: usize,
     pub(crate) term_info: TermInfo,
     pub(crate) motion_interpreter_state: crate::motion_interpreter::MotionInterpreterState,
+    pub(crate) input_queue: std::collections::VecDeque<QueuedInput>,
+    /// count typed so far in front of the motion that is being typed
+    pub(crate) pending_count: Option<usize>,
+    /// count of the motion that is being executed (motion functions read it from here)
+    pub(crate) count: Option<usize>,
+    pub(crate) dot_repeat: crate::dot_repeat::DotRepeat,
+    /// the key that is being processed (the last key of the motion that is being executed)
+    pub(crate) current_key: Option<PendingKey>,
+    pub(crate) registers: crate::registers::Registers,
+    /// the register given with `"{name}` for the next command
+    pub(crate) selected_register: Option<char>,
+    /// escape sequences (like OSC 52) that are written to the terminal before the next frame
+    pub(crate) terminal_output: Vec<String>,
+    pub(crate) macro_recording: Option<crate::macros::MacroRecording>,
+    pub(crate) last_macro_register: Option<char>,
+    pub(crate) scheduler: crate::scheduler::Scheduler,
+    pub(crate) jumplist: crate::jumplist::Jumplist,
+    pub(crate) global_marks: std::collections::HashMap<char, crate::marks::GlobalMark>,
+    /// the completion popup of insert mode, see `completion::active` for whether it is open
+    pub(crate) completion: Option<crate::completion::Completion>,
+    /// what was typed after <C-v> in insert mode (the digits of a character code), `None` if the
+    /// next key is not inserted literally
+    pub(crate) literal_input: Option<String>,
+    /// set by <C-o> in insert and replace mode to the mode that comes back after the next normal
+    /// mode command
+    pub(crate) insert_after_command: Option<ModeKind>,
     pub(crate) editor_globals: EditorGlobals,
 }
 
 pub(crate) enum EditorStateUpdate {
     None,
     Mode(EditorMode),
+    #[allow(dead_code)]
     Buffers(Vec<crate::editor_buffer::Buffer>),
+    #[allow(dead_code)]
     BufferIdx(usize),
+    #[allow(dead_code)]
     TermInfo(TermInfo),
-    Full(EditorState),
+    #[allow(dead_code)]
+    Full(Box<EditorState>),
+    /// Runs a task in the background, its update is applied once it finishes
+    Spawn(crate::scheduler::Task),
+    Transaction(crate::transaction::Transaction),
+    /// Repeats the last change, with a new count if one is given
+    DotRepeat(Option<usize>),
+    StartMacroRecording(char),
+    PlayMacro { register: char, count: usize },
+    /// Goes back (negative) or forward through the jumplist
+    StepJumplist(isize),
+    /// Goes back (negative) or forward through the changelist of the current buffer
+    StepChangelist(isize),
+    SetMark(char),
+    /// `linewise` jumps go to the first non-blank of the line of the mark
+    JumpToMark { mark: char, linewise: bool },
+    /// Moves through the undo tree of the current buffer
+    Undo(crate::undo::UndoCommand),
+    /// `"{name}`, the next command uses register `name`
+    SelectRegister(char),
+    Complete(crate::completion::CompletionCommand),
+    /// a clipboard tool running in the background is done
+    Clipboard(crate::clipboard::ClipboardUpdate),
+    /// <C-v> in insert mode, the next key is inserted literally
+    InsertLiteral,
+    /// <C-o> in insert mode, runs one normal mode command and goes back to insert mode
+    InsertCommand,
 }
 
 impl EditorState {
     pub(crate) fn new_normal(term_info: TermInfo, editor_globals: EditorGlobals) -> Self {
         Self {
             mode: EditorMode::new_normal(),
-            cursor: crate::cursor::Cursor::default(),
             command_line: CommandLine::default(),
             buffers: vec![crate::editor_buffer::Buffer::new()],
             buffer_idx: 0,
             term_info,
             motion_interpreter_state: crate::motion_interpreter::MotionInterpreterState::new(),
+            input_queue: std::collections::VecDeque::new(),
+            pending_count: None,
+            count: None,
+            dot_repeat: crate::dot_repeat::DotRepeat::default(),
+            current_key: None,
+            registers: crate::registers::Registers::default(),
+            selected_register: None,
+            terminal_output: Vec::new(),
+            macro_recording: None,
+            last_macro_register: None,
+            scheduler: crate::scheduler::Scheduler::default(),
+            jumplist: crate::jumplist::Jumplist::default(),
+            global_marks: std::collections::HashMap::new(),
+            completion: None,
+            literal_input: None,
+            insert_after_command: None,
             editor_globals,
         }
     }
 
+    #[allow(dead_code)]
     pub(crate) fn new_insert(term_info: TermInfo, editor_globals: EditorGlobals) -> Self {
         Self {
             mode: EditorMode::new_insert(),
-            cursor: crate::cursor::Cursor::default(),
             command_line: CommandLine::default(),
             buffers: vec![crate::editor_buffer::Buffer::new()],
             buffer_idx: 0,
             term_info,
             motion_interpreter_state: crate::motion_interpreter::MotionInterpreterState::new(),
+            input_queue: std::collections::VecDeque::new(),
+            pending_count: None,
+            count: None,
+            dot_repeat: crate::dot_repeat::DotRepeat::default(),
+            current_key: None,
+            registers: crate::registers::Registers::default(),
+            selected_register: None,
+            terminal_output: Vec::new(),
+            macro_recording: None,
+            last_macro_register: None,
+            scheduler: crate::scheduler::Scheduler::default(),
+            jumplist: crate::jumplist::Jumplist::default(),
+            global_marks: std::collections::HashMap::new(),
+            completion: None,
+            literal_input: None,
+            insert_after_command: None,
             editor_globals,
         }
     }
 
-    pub(crate) fn new_visual(
-        cursor_start: crate::cursor::Cursor,
-        term_info: TermInfo,
-        editor_globals: EditorGlobals,
-    ) -> Self {
+    #[allow(dead_code)]
+    pub(crate) fn new_visual(term_info: TermInfo, editor_globals: EditorGlobals) -> Self {
         Self {
-            mode: EditorMode::new_visual(cursor_start),
-            cursor: crate::cursor::Cursor::default(),
+            mode: EditorMode::new_visual(),
             command_line: CommandLine::default(),
             buffers: vec![crate::editor_buffer::Buffer::new()],
             buffer_idx: 0,
             term_info,
             motion_interpreter_state: crate::motion_interpreter::MotionInterpreterState::new(),
+            input_queue: std::collections::VecDeque::new(),
+            pending_count: None,
+            count: None,
+            dot_repeat: crate::dot_repeat::DotRepeat::default(),
+            current_key: None,
+            registers: crate::registers::Registers::default(),
+            selected_register: None,
+            terminal_output: Vec::new(),
+            macro_recording: None,
+            last_macro_register: None,
+            scheduler: crate::scheduler::Scheduler::default(),
+            jumplist: crate::jumplist::Jumplist::default(),
+            global_marks: std::collections::HashMap::new(),
+            completion: None,
+            literal_input: None,
+            insert_after_command: None,
             editor_globals,
         }
     }
 
+    /// How often any buffer changed, used to tell whether a motion changed text
+    pub(crate) fn change_tick(&self) -> usize {
+        self.buffers.iter().map(|buffer| buffer.change_tick).sum()
+    }
+
+    /// The count of the motion that is being executed, 1 if none was typed
+    pub(crate) fn count_or_one(&self) -> usize {
+        self.count.unwrap_or(1)
+    }
+
+    /// The kind of visual mode and how far the primary selection reaches from its start as (kind,
+    /// lines, columns)
+    pub(crate) fn visual_extent(&self) -> Option<(crate::visual::VisualKind, isize, isize)> {
+        match self.mode {
+            EditorMode::Visual(kind) => {
+                let buffer = self.get_buffer();
+                let selection = buffer.selections.primary();
+                let (start_line, start_column) = buffer.position(selection.anchor);
+                let (line, column) = buffer.position(selection.head);
+                Some((
+                    kind,
+                    line as isize - start_line as isize,
+                    column as isize - start_column as isize,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Index of the buffer of the file at `path`, the file is loaded into a new buffer if no buffer
+    /// has it open
+    pub(crate) fn find_or_open_buffer(&mut self, path: &std::path::Path) -> std::io::Result<usize> {
+        if let Some(buffer_idx) = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.location.as_deref() == Some(path))
+        {
+            return Ok(buffer_idx);
+        }
+        let mut buffer = crate::editor_buffer::Buffer::from_file(path)?;
+        // without a usable undo file the buffer just starts with an empty history
+        if let Err(error) = crate::undo_file::load(&mut buffer, path, &self.editor_globals) {
+            writeln!(&mut self.command_line, "{}", error)
+                .expect("Fatal: Could not write to command line buffer");
+        }
+        self.buffers.push(buffer);
+        Ok(self.buffers.len() - 1)
+    }
+
     pub(crate) fn get_buffer(&self) -> &crate::editor_buffer::Buffer {
         &self.buffers[self.buffer_idx]
     }
@@ -131,19 +419,12 @@ impl EditorState {
     }
 
     pub(crate) fn active_motion_tree(&self) -> &crate::motion_interpreter::MotionTree {
-        match self.mode {
-            EditorMode::Normal => &self.editor_globals.normal_mode_motion_tree,
-            EditorMode::Insert => &self.editor_globals.insert_mode_motion_tree,
-            EditorMode::Visual { .. } => &self.editor_globals.visual_mode_motion_tree,
-        }
+        self.editor_globals.motion_tree(self.mode.kind())
     }
 
+    #[allow(dead_code)]
     pub(crate) fn active_motion_tree_mut(&mut self) -> &mut crate::motion_interpreter::MotionTree {
-        match self.mode {
-            EditorMode::Normal => &mut self.editor_globals.normal_mode_motion_tree,
-            EditorMode::Insert => &mut self.editor_globals.insert_mode_motion_tree,
-            EditorMode::Visual { .. } => &mut self.editor_globals.visual_mode_motion_tree,
-        }
+        self.editor_globals.motion_tree_mut(self.mode.kind())
     }
 
     pub(crate) fn apply(&mut self, update: EditorStateUpdate) {
@@ -153,7 +434,105 @@ impl EditorState {
             EditorStateUpdate::Buffers(buffers) => self.buffers = buffers,
             EditorStateUpdate::BufferIdx(buffer_idx) => self.buffer_idx = buffer_idx,
             EditorStateUpdate::TermInfo(term_info) => self.term_info = term_info,
-            EditorStateUpdate::Full(new_state) => *self = new_state,
+            EditorStateUpdate::Full(new_state) => *self = *new_state,
+            EditorStateUpdate::Spawn(task) => {
+                self.scheduler.spawn(task);
+            }
+            EditorStateUpdate::DotRepeat(count) => {
+                let Some(keys) = self.dot_repeat.replay_keys(count) else {
+                    return;
+                };
+                let visual_extent = self
+                    .dot_repeat
+                    .last_change
+                    .as_ref()
+                    .and_then(|change| change.visual_extent);
+                if let Some((kind, lines, columns)) = visual_extent {
+                    // select a region of the same size at every cursor
+                    let buffer = self.get_buffer_mut();
+                    let mut selections = buffer.selections.clone();
+                    selections.map(|selection| {
+                        let (line, column) = buffer.position(selection.head);
+                        crate::selections::Selection {
+                            anchor: selection.head,
+                            head: buffer.offset(
+                                (line as isize + lines).max(0) as usize,
+                                (column as isize + columns).max(0) as usize,
+                            ),
+                            ..selection
+                        }
+                    });
+                    buffer.selections = selections;
+                    self.mode = EditorMode::Visual(kind);
+                }
+                // the recorded keys already had mappings expanded
+                let from_macro = self.current_key.is_some_and(|key| key.from_macro);
+                for &atom in keys.iter().rev() {
+                    self.input_queue.push_front(QueuedInput::Key(PendingKey {
+                        atom,
+                        remap: false,
+                        depth: 0,
+                        from_macro,
+                    }));
+                }
+            }
+            EditorStateUpdate::StartMacroRecording(register) => {
+                self.macro_recording = Some(crate::macros::MacroRecording::new(register))
+            }
+            EditorStateUpdate::PlayMacro { register, count } => {
+                crate::macros::play(self, register, count)
+            }
+            EditorStateUpdate::StepJumplist(steps) => crate::jumplist::step_jumplist(self, steps),
+            EditorStateUpdate::StepChangelist(steps) => {
+                crate::jumplist::step_changelist(self, steps)
+            }
+            EditorStateUpdate::SetMark(mark) => {
+                if let Err(error) = crate::marks::set_mark(self, mark) {
+                    crate::macros::abort(self);
+                    writeln!(&mut self.command_line, "{}", error)
+                        .expect("Fatal: Could not write to command line buffer");
+                }
+            }
+            EditorStateUpdate::JumpToMark { mark, linewise } => {
+                if let Err(error) = crate::marks::jump_to_mark(self, mark, linewise) {
+                    crate::macros::abort(self);
+                    writeln!(&mut self.command_line, "{}", error)
+                        .expect("Fatal: Could not write to command line buffer");
+                }
+            }
+            EditorStateUpdate::SelectRegister(name) => self.selected_register = Some(name),
+            EditorStateUpdate::Complete(command) => crate::completion::run(self, command),
+            EditorStateUpdate::Clipboard(update) => crate::clipboard::apply(self, update),
+            EditorStateUpdate::InsertLiteral => self.literal_input = Some(String::new()),
+            EditorStateUpdate::InsertCommand => {
+                self.insert_after_command = Some(self.mode.kind());
+                self.mode = EditorMode::Normal;
+            }
+            EditorStateUpdate::Undo(command) => {
+                let buffer = self.get_buffer_mut();
+                match crate::undo::run(buffer, command) {
+                    Ok(()) => {
+                        let mut selections = buffer.selections.clone();
+                        selections.map(|selection| {
+                            let head = crate::text_motions::clamp_to_line(buffer, selection.head);
+                            crate::selections::Selection::cursor(head, buffer.position(head).1)
+                        });
+                        buffer.selections = selections;
+                    }
+                    Err(error) => {
+                        crate::macros::abort(self);
+                        writeln!(&mut self.command_line, "{}", error)
+                            .expect("Fatal: Could not write to command line buffer");
+                    }
+                }
+            }
+            EditorStateUpdate::Transaction(transaction) => {
+                if let Err(error) = transaction.apply(self) {
+                    crate::macros::abort(self);
+                    writeln!(&mut self.command_line, "{}", error)
+                        .expect("Fatal: Could not write to command line buffer");
+                }
+            }
         }
     }
 }
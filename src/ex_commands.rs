@@ -0,0 +1,595 @@
+use crate::editor_state::{EditorState, ModeKind};
+use std::fmt::Write;
+use std::rc::Rc;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ExCommandError {
+    #[error("Not an editor command: {0}")]
+    NotAnEditorCommandError(String),
+    #[error("Argument required")]
+    ArgumentRequiredError,
+    #[error("Buffer {0} does not exist")]
+    NoSuchBufferError(String),
+    #[error("No file name")]
+    NoFileNameError,
+    #[error("{0}")]
+    KeyNotationError(#[from] crate::key_notation::KeyNotationError),
+    #[error("{0}")]
+    MotionTreeError(#[from] crate::motion_interpreter::MotionTreeError),
+    #[error("{0}")]
+    OptionError(#[from] crate::options::OptionError),
+    #[error("{0}")]
+    MarkError(#[from] crate::marks::MarkError),
+    #[error("{0}")]
+    AbbreviationError(#[from] crate::abbreviations::AbbreviationError),
+    #[error("{0}")]
+    UndoError(#[from] crate::undo::UndoError),
+    #[error("{0}")]
+    UndoFileError(#[from] crate::undo_file::UndoFileError),
+    #[error("{0}")]
+    TransactionError(#[from] crate::transaction::TransactionError),
+    #[error("{0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// The parsed arguments of an ex command, eg `:nnoremap! Y y$` has `bang` set and `args` is
+/// `Y y$`
+pub(crate) struct ExCommandArgs<'a> {
+    pub(crate) bang: bool,
+    pub(crate) args: &'a str,
+}
+
+type ExCommandFn = dyn Fn(&mut EditorState, &ExCommandArgs) -> Result<(), ExCommandError>;
+
+// ex commands get mutable access to the state (unlike motions) because they mostly reconfigure the
+// editor (mappings, options, ...) rather than edit text
+#[derive(Clone)]
+pub(crate) struct ExCommandFunction(pub(crate) Rc<ExCommandFn>);
+
+#[derive(Clone)]
+pub(crate) struct ExCommand {
+    pub(crate) name: &'static str,
+    /// how many characters of `name` have to be typed at least (eg 2 for `nm[ap]`)
+    pub(crate) min_len: usize,
+    pub(crate) function: ExCommandFunction,
+}
+
+fn split_command_name(line: &str) -> (&str, ExCommandArgs<'_>) {
+    let line = line.trim_start_matches([' ', ':']);
+    let name_len = line
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(line.len());
+    let (name, rest) = line.split_at(name_len);
+    let (bang, rest) = match rest.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let args = ExCommandArgs {
+        bang,
+        args: rest.trim(),
+    };
+    (name, args)
+}
+
+fn split_first_word(args: &str) -> (&str, &str) {
+    match args.split_once(char::is_whitespace) {
+        Some((first, rest)) => (first, rest.trim_start()),
+        None => (args, ""),
+    }
+}
+
+/// Executes a line typed on the command line (without the leading `:`)
+pub(crate) fn execute(ed_state: &mut EditorState, line: &str) -> Result<(), ExCommandError> {
+    let (name, args) = split_command_name(line);
+    if name.is_empty() {
+        return Ok(());
+    }
+    // clone the function so the command can borrow the state mutably
+    let function = ed_state
+        .editor_globals
+        .ex_commands
+        .iter()
+        .find(|command| name.len() >= command.min_len && command.name.starts_with(name))
+        .map(|command| command.function.clone())
+        .ok_or_else(|| ExCommandError::NotAnEditorCommandError(name.to_string()))?;
+    function.0(ed_state, &args)
+}
+
+fn map(
+    ed_state: &mut EditorState,
+    args: &ExCommandArgs,
+    modes: &[ModeKind],
+    recursive: bool,
+) -> Result<(), ExCommandError> {
+    let (lhs, rhs) = split_first_word(args.args);
+    if rhs.is_empty() {
+        return list_mappings(ed_state, lhs, modes);
+    }
+    let lhs = crate::motion_interpreter::MotionComponentBuffer(crate::key_notation::parse(lhs)?);
+    let rhs = crate::key_notation::parse(rhs)?;
+    // a mapping that conflicts in one of the modes is not added to any of them
+    for &mode in modes {
+        ed_state.editor_globals.motion_tree(mode).check_insert(&lhs)?;
+    }
+    for &mode in modes {
+        ed_state.editor_globals.motion_tree_mut(mode).insert_remap(
+            lhs.clone(),
+            crate::motion_interpreter::Remap {
+                keys: rhs.clone(),
+                recursive,
+                shadowed: None,
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn mode_letter(mode: ModeKind) -> char {
+    match mode {
+        ModeKind::Normal => 'n',
+        ModeKind::Insert => 'i',
+        ModeKind::Visual => 'v',
+        ModeKind::Command => 'c',
+        ModeKind::Replace => 'r',
+    }
+}
+
+/// Lists the mappings of `modes` whose keys start with `lhs` (all of them if `lhs` is empty),
+/// non-recursive mappings are marked with a `*` like in vim
+fn list_mappings(
+    ed_state: &mut EditorState,
+    lhs: &str,
+    modes: &[ModeKind],
+) -> Result<(), ExCommandError> {
+    let prefix = if lhs.is_empty() {
+        Vec::new()
+    } else {
+        crate::key_notation::parse(lhs)?
+    };
+    let mut lines = Vec::new();
+    // insert mode mappings also apply in replace mode, they are only listed once
+    let modes = modes.iter().filter(|&&mode| {
+        !(mode == ModeKind::Replace && modes.contains(&ModeKind::Insert))
+    });
+    for &mode in modes {
+        let mut mode_lines = ed_state
+            .editor_globals
+            .motion_tree(mode)
+            .iter()
+            .filter(|(motion, _)| motion.0.starts_with(&prefix))
+            .filter_map(|(motion, leaf)| match leaf {
+                crate::motion_interpreter::MotionTree::Remap(remap) => Some(format!(
+                    "{}  {:<12} {} {}",
+                    mode_letter(mode),
+                    motion.to_string(),
+                    if remap.recursive { ' ' } else { '*' },
+                    crate::key_notation::format(&remap.keys)
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        mode_lines.sort();
+        lines.extend(mode_lines);
+    }
+    if lines.is_empty() {
+        writeln!(ed_state.command_line, "No mapping found")
+    } else {
+        writeln!(ed_state.command_line, "{}", lines.join("\n"))
+    }
+    .expect("Fatal: Could not write to command line buffer");
+    Ok(())
+}
+
+fn unmap(
+    ed_state: &mut EditorState,
+    args: &ExCommandArgs,
+    modes: &[ModeKind],
+) -> Result<(), ExCommandError> {
+    if args.args.is_empty() {
+        return Err(ExCommandError::ArgumentRequiredError);
+    }
+    let lhs = crate::motion_interpreter::MotionComponentBuffer(crate::key_notation::parse(
+        args.args,
+    )?);
+    let mut result = Err(crate::motion_interpreter::MotionTreeError::NoSuchMappingError);
+    for &mode in modes {
+        if ed_state
+            .editor_globals
+            .motion_tree_mut(mode)
+            .unmap(&lhs)
+            .is_ok()
+        {
+            result = Ok(());
+        }
+    }
+    Ok(result?)
+}
+
+fn command(
+    name: &'static str,
+    min_len: usize,
+    function: impl Fn(&mut EditorState, &ExCommandArgs) -> Result<(), ExCommandError> + 'static,
+) -> ExCommand {
+    ExCommand {
+        name,
+        min_len,
+        function: ExCommandFunction(Rc::new(function)),
+    }
+}
+
+pub(crate) fn setup_map_commands(ex_commands: &mut Vec<ExCommand>) {
+    // (name, min_len, modes) of the map/noremap/unmap variants, abbreviations as in vim (and like
+    // there, insert mode mappings apply in replace mode too)
+    let map_commands: [(&'static str, usize, &'static [ModeKind]); 5] = [
+        ("map", 3, &[ModeKind::Normal, ModeKind::Visual]),
+        ("nmap", 2, &[ModeKind::Normal]),
+        ("imap", 2, &[ModeKind::Insert, ModeKind::Replace]),
+        ("vmap", 2, &[ModeKind::Visual]),
+        ("cmap", 2, &[ModeKind::Command]),
+    ];
+    let noremap_commands: [(&'static str, usize, &'static [ModeKind]); 5] = [
+        ("noremap", 2, &[ModeKind::Normal, ModeKind::Visual]),
+        ("nnoremap", 2, &[ModeKind::Normal]),
+        ("inoremap", 3, &[ModeKind::Insert, ModeKind::Replace]),
+        ("vnoremap", 2, &[ModeKind::Visual]),
+        ("cnoremap", 3, &[ModeKind::Command]),
+    ];
+    let unmap_commands: [(&'static str, usize, &'static [ModeKind]); 5] = [
+        ("unmap", 3, &[ModeKind::Normal, ModeKind::Visual]),
+        ("nunmap", 3, &[ModeKind::Normal]),
+        ("iunmap", 2, &[ModeKind::Insert, ModeKind::Replace]),
+        ("vunmap", 2, &[ModeKind::Visual]),
+        ("cunmap", 2, &[ModeKind::Command]),
+    ];
+    for (name, min_len, modes) in map_commands {
+        ex_commands.push(command(name, min_len, move |ed_state, args| {
+            map(ed_state, args, modes, true)
+        }));
+    }
+    for (name, min_len, modes) in noremap_commands {
+        ex_commands.push(command(name, min_len, move |ed_state, args| {
+            map(ed_state, args, modes, false)
+        }));
+    }
+    for (name, min_len, modes) in unmap_commands {
+        ex_commands.push(command(name, min_len, move |ed_state, args| {
+            unmap(ed_state, args, modes)
+        }));
+    }
+}
+
+/// Splits off the `<buffer>` in front of the arguments of the abbreviation commands, which makes
+/// them about the abbreviations of the current buffer
+fn split_buffer_local(args: &str) -> (bool, &str) {
+    match args.strip_prefix("<buffer>") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, args),
+    }
+}
+
+/// `:iabbrev lhs rhs` defines an abbreviation, `:iabbrev [lhs]` lists the ones starting with `lhs`
+fn abbreviate(
+    ed_state: &mut EditorState,
+    args: &ExCommandArgs,
+    modes: &[ModeKind],
+) -> Result<(), ExCommandError> {
+    let (buffer_local, args) = split_buffer_local(args.args);
+    let (lhs, rhs) = split_first_word(args);
+    if rhs.is_empty() {
+        list_abbreviations(ed_state, lhs, modes);
+    } else {
+        crate::abbreviations::define(ed_state, modes, buffer_local, lhs, rhs);
+    }
+    Ok(())
+}
+
+/// Lists the abbreviations of `modes` starting with `lhs`, buffer-local ones are marked with a `@`
+/// like in vim
+fn list_abbreviations(ed_state: &mut EditorState, lhs: &str, modes: &[ModeKind]) {
+    let mut lines = Vec::new();
+    for &mode in modes {
+        let buffer_local = ed_state.get_buffer().abbreviations.get(mode);
+        let global = ed_state.editor_globals.abbreviations.get(mode);
+        let marked = buffer_local
+            .iter()
+            .map(|entry| ('@', entry))
+            .chain(global.iter().map(|entry| (' ', entry)));
+        for (mark, (abbreviation, expansion)) in marked {
+            if abbreviation.starts_with(lhs) {
+                lines.push(format!(
+                    "{}  {:<12} {}{}",
+                    mode_letter(mode),
+                    abbreviation,
+                    mark,
+                    expansion
+                ));
+            }
+        }
+    }
+    if lines.is_empty() {
+        writeln!(ed_state.command_line, "No abbreviation found")
+    } else {
+        writeln!(ed_state.command_line, "{}", lines.join("\n"))
+    }
+    .expect("Fatal: Could not write to command line buffer");
+}
+
+/// `:abbreviate`, `:iabbrev` and `:cabbrev` with their `:unabbreviate` and `:abclear` variants,
+/// abbreviations apply to insert mode and the command line
+pub(crate) fn setup_abbreviation_commands(ex_commands: &mut Vec<ExCommand>) {
+    // (name, min_len, modes) of the abbreviate, unabbreviate and abclear variants
+    let both: &'static [ModeKind] = &[ModeKind::Insert, ModeKind::Command];
+    let insert: &'static [ModeKind] = &[ModeKind::Insert];
+    let command_line: &'static [ModeKind] = &[ModeKind::Command];
+    let abbreviate_commands = [
+        ("abbreviate", 2, both),
+        ("iabbrev", 2, insert),
+        ("cabbrev", 2, command_line),
+    ];
+    let unabbreviate_commands = [
+        ("unabbreviate", 3, both),
+        ("iunabbrev", 4, insert),
+        ("cunabbrev", 4, command_line),
+    ];
+    let abclear_commands = [
+        ("abclear", 3, both),
+        ("iabclear", 4, insert),
+        ("cabclear", 4, command_line),
+    ];
+    for (name, min_len, modes) in abbreviate_commands {
+        ex_commands.push(command(name, min_len, move |ed_state, args| {
+            abbreviate(ed_state, args, modes)
+        }));
+    }
+    for (name, min_len, modes) in unabbreviate_commands {
+        ex_commands.push(command(name, min_len, move |ed_state, args| {
+            let (buffer_local, lhs) = split_buffer_local(args.args);
+            if lhs.is_empty() {
+                return Err(ExCommandError::ArgumentRequiredError);
+            }
+            Ok(crate::abbreviations::remove(ed_state, modes, buffer_local, lhs)?)
+        }));
+    }
+    for (name, min_len, modes) in abclear_commands {
+        ex_commands.push(command(name, min_len, move |ed_state, args| {
+            let (buffer_local, _) = split_buffer_local(args.args);
+            crate::abbreviations::clear(ed_state, modes, buffer_local);
+            Ok(())
+        }));
+    }
+}
+
+/// Splits the arguments of `:set` at whitespace, a backslash in front of a space makes it part of
+/// the value (like in vim)
+fn split_set_args(args: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut current = String::new();
+    let mut chars = args.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.next_if_eq(&' ').is_some() => current.push(' '),
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    split.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        split.push(current);
+    }
+    split
+}
+
+/// `:set name=value` assigns, `:set name` and `:set name?` show the current value. Boolean options
+/// are switched with `:set name`, `:set noname` and `:set name!` instead. Nothing is set if one of
+/// the arguments is invalid.
+fn set(ed_state: &mut EditorState, args: &ExCommandArgs) -> Result<(), ExCommandError> {
+    if args.args.is_empty() {
+        return Err(ExCommandError::ArgumentRequiredError);
+    }
+    let mut transaction = crate::transaction::Transaction::new();
+    let mut queried = Vec::new();
+    for arg in split_set_args(args.args) {
+        match arg.split_once('=') {
+            Some((name, value)) => {
+                transaction.set_option(name, value)?;
+            }
+            None => match crate::options::parse_flag(&ed_state.editor_globals, &arg) {
+                Some(option) => {
+                    transaction.option(option);
+                }
+                None => {
+                    let name = arg.trim_end_matches('?').to_string();
+                    // fails for unknown options
+                    crate::options::get(&ed_state.editor_globals, &name)?;
+                    queried.push(name);
+                }
+            },
+        }
+    }
+    transaction.apply(ed_state)?;
+    // the values are shown after the assignments, like in vim
+    let shown = queried
+        .iter()
+        .map(|name| crate::options::get(&ed_state.editor_globals, name))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !shown.is_empty() {
+        writeln!(ed_state.command_line, "{}", shown.join("  "))
+            .expect("Fatal: Could not write to command line buffer");
+    }
+    Ok(())
+}
+
+/// `:retab[!] [tabstop]` redoes whitespace with tabs or spaces (as `expandtab` says), laid out for
+/// a new `tabstop` if one is given
+fn retab(ed_state: &mut EditorState, args: &ExCommandArgs) -> Result<(), ExCommandError> {
+    let tab_size = match args.args {
+        "" => ed_state.editor_globals.tab_size,
+        value => match crate::options::parse("tabstop", value)? {
+            crate::options::EditorOption::TabSize(tab_size) if tab_size > 0 => tab_size,
+            _ => Err(crate::options::OptionError::InvalidValueError {
+                name: "tabstop".to_string(),
+                value: value.to_string(),
+            })?,
+        },
+    };
+    let update = crate::indent::retab(ed_state, args.bang, tab_size);
+    ed_state.apply(update);
+    ed_state.editor_globals.tab_size = tab_size;
+    Ok(())
+}
+
+/// Switching buffers is a jump, so <C-o> comes back to where the cursor was
+fn switch_buffer(ed_state: &mut EditorState, buffer_idx: usize) {
+    crate::jumplist::record_jump(ed_state);
+    ed_state.buffer_idx = buffer_idx;
+}
+
+/// `:buffer N` switches to the buffer with number N (counting from 1), every buffer keeps its own
+/// cursor
+fn buffer(ed_state: &mut EditorState, args: &ExCommandArgs) -> Result<(), ExCommandError> {
+    if args.args.is_empty() {
+        return Err(ExCommandError::ArgumentRequiredError);
+    }
+    match args.args.trim().parse::<usize>() {
+        Ok(number) if (1..=ed_state.buffers.len()).contains(&number) => {
+            switch_buffer(ed_state, number - 1);
+            Ok(())
+        }
+        _ => Err(ExCommandError::NoSuchBufferError(args.args.to_string())),
+    }
+}
+
+fn cycle_buffers(ed_state: &mut EditorState, forward: bool) {
+    let len = ed_state.buffers.len();
+    let buffer_idx = if forward {
+        (ed_state.buffer_idx + 1) % len
+    } else {
+        (ed_state.buffer_idx + len - 1) % len
+    };
+    switch_buffer(ed_state, buffer_idx);
+}
+
+pub(crate) fn setup_buffer_commands(ex_commands: &mut Vec<ExCommand>) {
+    ex_commands.push(command("buffer", 1, buffer));
+    ex_commands.push(command("bnext", 2, |ed_state, _| {
+        cycle_buffers(ed_state, true);
+        Ok(())
+    }));
+    ex_commands.push(command("bprevious", 2, |ed_state, _| {
+        cycle_buffers(ed_state, false);
+        Ok(())
+    }));
+    ex_commands.push(command("edit", 1, |ed_state, args| {
+        if args.args.is_empty() {
+            return Err(ExCommandError::ArgumentRequiredError);
+        }
+        let buffer_idx = ed_state.find_or_open_buffer(std::path::Path::new(args.args))?;
+        switch_buffer(ed_state, buffer_idx);
+        Ok(())
+    }));
+    ex_commands.push(command("enew", 3, |ed_state, _| {
+        ed_state.buffers.push(crate::editor_buffer::Buffer::new());
+        switch_buffer(ed_state, ed_state.buffers.len() - 1);
+        Ok(())
+    }));
+}
+
+/// `:write [file]` writes the buffer to its file (an unnamed buffer gets the name `file`) and
+/// saves its undo history along with it
+fn write(ed_state: &mut EditorState, args: &ExCommandArgs) -> Result<(), ExCommandError> {
+    let buffer = ed_state.get_buffer_mut();
+    let path = if args.args.is_empty() {
+        buffer.location.clone().ok_or(ExCommandError::NoFileNameError)?
+    } else {
+        std::path::PathBuf::from(args.args)
+    };
+    let text = buffer.content.iter().collect::<String>();
+    std::fs::write(&path, &text)?;
+    if buffer.location.is_none() {
+        buffer.name = path.display().to_string();
+        buffer.location = Some(path.clone());
+    }
+    let own_file = buffer.location.as_ref() == Some(&path);
+    if own_file {
+        buffer.undo.mark_written();
+    }
+    writeln!(
+        ed_state.command_line,
+        "\"{}\" {}L, {}B written",
+        path.display(),
+        text.lines().count(),
+        text.len()
+    )
+    .expect("Fatal: Could not write to command line buffer");
+    if own_file {
+        crate::undo_file::save(ed_state.get_buffer(), &path, &ed_state.editor_globals)?;
+    }
+    Ok(())
+}
+
+fn undo(
+    ed_state: &mut EditorState,
+    command: crate::undo::UndoCommand,
+) -> Result<(), ExCommandError> {
+    ed_state.apply(crate::editor_state::EditorStateUpdate::Undo(command));
+    Ok(())
+}
+
+/// `:undo`, `:redo`, `:earlier`, `:later` and `:undolist`, the ones that take a count take it as
+/// their argument
+pub(crate) fn setup_undo_commands(ex_commands: &mut Vec<ExCommand>) {
+    let count = |args: &ExCommandArgs| match args.args {
+        "" => Ok(1),
+        arg => arg
+            .parse::<usize>()
+            .map_err(|_| crate::undo::UndoError::InvalidStepError(arg.to_string())),
+    };
+    ex_commands.push(command("undo", 1, move |ed_state, args| {
+        undo(ed_state, crate::undo::UndoCommand::Undo(count(args)?))
+    }));
+    ex_commands.push(command("undolist", 5, |ed_state, _| {
+        let list = crate::undo::list(ed_state.get_buffer());
+        writeln!(ed_state.command_line, "{}", list)
+            .expect("Fatal: Could not write to command line buffer");
+        Ok(())
+    }));
+    ex_commands.push(command("redo", 3, move |ed_state, args| {
+        undo(ed_state, crate::undo::UndoCommand::Redo(count(args)?))
+    }));
+    ex_commands.push(command("earlier", 2, |ed_state, args| {
+        let step = crate::undo::parse_time_step(args.args)?;
+        undo(ed_state, crate::undo::UndoCommand::Earlier(step))
+    }));
+    ex_commands.push(command("later", 3, |ed_state, args| {
+        let step = crate::undo::parse_time_step(args.args)?;
+        undo(ed_state, crate::undo::UndoCommand::Later(step))
+    }));
+}
+
+pub(crate) fn setup_ex_commands(ex_commands: &mut Vec<ExCommand>) {
+    setup_map_commands(ex_commands);
+    setup_buffer_commands(ex_commands);
+    setup_undo_commands(ex_commands);
+    setup_abbreviation_commands(ex_commands);
+    ex_commands.push(command("set", 2, set));
+    ex_commands.push(command("write", 1, write));
+    ex_commands.push(command("retab", 3, retab));
+    for (name, min_len) in [("registers", 3), ("display", 2)] {
+        ex_commands.push(command(name, min_len, |ed_state, args| {
+            crate::registers::list(ed_state, args.args);
+            Ok(())
+        }));
+    }
+    ex_commands.push(command("marks", 4, |ed_state, args| {
+        Ok(crate::marks::list(ed_state, args.args)?)
+    }));
+    ex_commands.push(command("delmarks", 4, |ed_state, args| {
+        if args.args.is_empty() && !args.bang {
+            return Err(ExCommandError::ArgumentRequiredError);
+        }
+        Ok(crate::marks::delete(ed_state, args.args, args.bang)?)
+    }));
+}
@@ -0,0 +1,82 @@
+use crate::editor_buffer::Buffer;
+
+/// The filetype of a buffer, told by the extension of its file ("" if it is not known)
+pub(crate) fn detect(buffer: &Buffer) -> &'static str {
+    let Some(path) = buffer.location.as_deref() else {
+        return "";
+    };
+    if path.file_name().is_some_and(|name| name == "Makefile") {
+        return "make";
+    }
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension {
+        "rs" => "rust",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "py" => "python",
+        "js" | "mjs" => "javascript",
+        "ts" => "typescript",
+        "go" => "go",
+        "java" => "java",
+        "lua" => "lua",
+        "sh" | "bash" => "sh",
+        "toml" => "toml",
+        "json" => "json",
+        "md" => "markdown",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "yml" | "yaml" => "yaml",
+        _ => "",
+    }
+}
+
+/// The pairs auto-pairing inserts for a filetype, every two characters are an opener and its
+/// closer
+pub(crate) fn pairs(filetype: &str) -> &'static str {
+    match filetype {
+        // single quotes are lifetimes and character literals
+        "rust" => "()[]{}\"\"",
+        "markdown" => "()[]{}\"\"``",
+        "html" => "()[]{}\"\"''<>",
+        _ => "()[]{}\"\"''",
+    }
+}
+
+/// How smartindent indents a filetype: after a line ending in one of `openers` the next line gets
+/// one level more, typing one of `closers` first on a line takes a level away again
+pub(crate) struct IndentRules {
+    pub(crate) openers: &'static str,
+    pub(crate) closers: &'static str,
+}
+
+pub(crate) fn indent_rules(filetype: &str) -> IndentRules {
+    match filetype {
+        "python" | "yaml" => IndentRules {
+            openers: ":",
+            closers: "",
+        },
+        _ => IndentRules {
+            openers: "{",
+            closers: "}",
+        },
+    }
+}
+
+/// How lines are commented out in a filetype, `end` is empty for comments running to the end of
+/// the line
+pub(crate) struct CommentString {
+    pub(crate) start: &'static str,
+    pub(crate) end: &'static str,
+}
+
+pub(crate) fn comment_string(filetype: &str) -> CommentString {
+    let (start, end) = match filetype {
+        "rust" | "c" | "cpp" | "javascript" | "typescript" | "go" | "java" | "json" => ("//", ""),
+        "lua" => ("--", ""),
+        "css" => ("/*", "*/"),
+        "html" | "markdown" => ("<!--", "-->"),
+        // shell, python, toml, yaml and make, and the default for unknown files
+        _ => ("#", ""),
+    };
+    CommentString { start, end }
+}
@@ -1,4 +1,4 @@
-use std::{array::IntoIter, ops::RangeBounds};
+use std::ops::RangeBounds;
 
 fn is_empty_range(range: &impl RangeBounds<usize>) -> bool {
     let start = match range.start_bound() {
@@ -60,9 +60,7 @@ impl<T: Clone + Default + PartialEq> GapBuffer<T> {
     }
     pub(crate) fn new(cap: usize) -> Self {
         // pad the buffer (because the splice method requires it and is heavily used here)
-        let buffer = std::iter::repeat(T::default())
-            .take(cap)
-            .collect::<Vec<T>>();
+        let buffer = std::iter::repeat_n(T::default(), cap).collect::<Vec<T>>();
         let gap_window = GapWindow {
             index: 0,
             size: cap,
@@ -107,8 +105,7 @@ impl<T: Clone + Default + PartialEq> GapBuffer<T> {
             // materialize the padding because otherwise rust compiler go "F U"
             // reason: mutable and immutable references at the same time because iterator
             // contains immutable ref and splice takes &mut self
-            let inserted = std::iter::repeat(T::default())
-                .take(padding_size)
+            let inserted = std::iter::repeat_n(T::default(), padding_size)
                 .chain(
                     self.buffer
                         [self.gap_window.index + chars.len()..old_buffer_capacity + chars.len()]
@@ -164,6 +161,31 @@ impl<T: Clone + Default + PartialEq> GapBuffer<T> {
         }
     }
 
+    /// Returns the element at `index` (not counting the gap)
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        if index < self.gap_window.index {
+            self.buffer.get(index)
+        } else {
+            self.buffer.get(index + self.gap_window.size)
+        }
+    }
+
+    /// Copies the elements in `range` (not counting the gap) out of the buffer
+    pub(crate) fn slice(&self, range: std::ops::Range<usize>) -> Vec<T> {
+        self.iter()
+            .skip(range.start)
+            .take(range.end.saturating_sub(range.start))
+            .cloned()
+            .collect()
+    }
+
+    /// Replaces the elements in `range` with `items`, leaving the gap right after them
+    pub(crate) fn replace(&mut self, range: std::ops::Range<usize>, items: &[T]) {
+        self.move_gap(range.end);
+        self.delete(range.end - range.start);
+        self.insert(items);
+    }
+
     /// Returns an iterator over the non-gap elements (by chaining iterators over both non-gap
     /// buffer parts)
     pub(crate) fn iter(&self) -> impl Iterator<Item = &T> {
@@ -297,6 +319,24 @@ mod tests {
         }
     }
     #[test]
+    fn test_gap_buffer_get_slice_replace() {
+        let mut buffer = GapBuffer::new(4);
+        buffer.insert(&"hello world".chars().collect::<Vec<char>>());
+        buffer.move_gap(5);
+        assert_eq!(buffer.get(4), Some(&'o'));
+        assert_eq!(buffer.get(5), Some(&' '));
+        assert_eq!(buffer.get(11), None);
+        assert_eq!(buffer.slice(3..8), "lo wo".chars().collect::<Vec<char>>());
+        buffer.replace(0..5, &"goodbye".chars().collect::<Vec<char>>());
+        assert_eq!(
+            buffer.iter().collect::<String>(),
+            "goodbye world".to_string()
+        );
+        buffer.replace(8..13, &[]);
+        assert_eq!(buffer.iter().collect::<String>(), "goodbye ".to_string());
+        assert_eq!(buffer.len(), 8);
+    }
+    #[test]
     fn test_gap_buffer_with_padding_checked() {
         // println!("{:?}", (1..3).collect::<Vec<usize>>());
         let mut buffer = GapBuffer::new_empty();
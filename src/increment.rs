@@ -0,0 +1,218 @@
+use crate::editor_buffer::Buffer;
+use crate::editor_state::{EditorState, EditorStateUpdate};
+use crate::transaction::Transaction;
+use std::ops::Range;
+
+/// A number in the text: hexadecimal (`0x1f`), binary (`0b101`), octal (`0o17`) or decimal (with
+/// the `-` in front of it)
+struct Number {
+    range: Range<usize>,
+    radix: u32,
+    /// `0x`, `0B`, ..., empty for decimal numbers
+    prefix: String,
+    digits: String,
+    negative: bool,
+}
+
+/// The numbers in `range` of the buffer, from left to right
+fn numbers(buffer: &Buffer, range: Range<usize>) -> Vec<Number> {
+    let text = buffer.content.slice(range.clone());
+    let mut numbers = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        let radix = match (text[i], text.get(i + 1), text.get(i + 2)) {
+            ('0', Some('x' | 'X'), Some(c)) if c.is_ascii_hexdigit() => 16,
+            ('0', Some('b' | 'B'), Some('0' | '1')) => 2,
+            ('0', Some('o' | 'O'), Some(c)) if c.is_digit(8) => 8,
+            (c, ..) if c.is_ascii_digit() => 10,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let digits_start = if radix == 10 { i } else { i + 2 };
+        let mut end = digits_start;
+        while end < text.len() && text[end].is_digit(radix) {
+            end += 1;
+        }
+        // a `-` joining words (like in `x-1` or in dates) is not a sign
+        let negative =
+            radix == 10 && i > 0 && text[i - 1] == '-' && !(i > 1 && text[i - 2].is_alphanumeric());
+        numbers.push(Number {
+            range: range.start + i - usize::from(negative)..range.start + end,
+            radix,
+            prefix: text[i..digits_start].iter().collect(),
+            digits: text[digits_start..end].iter().collect(),
+            negative,
+        });
+        i = end;
+    }
+    numbers
+}
+
+/// The text of `number` changed by `delta`. Decimal numbers stop at the limits of 64 bits, the
+/// others are unsigned and wrap around. Leading zeros and the case of hex digits are kept.
+/// Numbers too long for 64 bits are left alone (`None`).
+fn changed(number: &Number, delta: i128) -> Option<String> {
+    let width = number.digits.len();
+    if number.radix == 10 {
+        let value = i128::from(number.digits.parse::<u64>().ok()?);
+        let value = if number.negative { -value } else { value };
+        let value = value
+            .saturating_add(delta)
+            .clamp(i64::MIN.into(), i64::MAX.into());
+        let sign = if value < 0 { "-" } else { "" };
+        // only numbers that were padded with zeros stay padded
+        let width = if number.digits.starts_with('0') {
+            width
+        } else {
+            0
+        };
+        return Some(format!("{sign}{:0width$}", value.unsigned_abs()));
+    }
+    let value = u64::from_str_radix(&number.digits, number.radix).ok()?;
+    let value = i128::from(value).saturating_add(delta).rem_euclid(1 << 64) as u64;
+    let digits = match number.radix {
+        16 => format!("{value:0width$x}"),
+        8 => format!("{value:0width$o}"),
+        _ => format!("{value:0width$b}"),
+    };
+    // the digits tell the case, or the prefix if there are no letters among them
+    let upper = match number.digits.chars().find(char::is_ascii_alphabetic) {
+        Some(c) => c.is_ascii_uppercase(),
+        None => number.prefix == "0X",
+    };
+    let digits = if upper {
+        digits.to_ascii_uppercase()
+    } else {
+        digits
+    };
+    Some(format!("{}{digits}", number.prefix))
+}
+
+/// <C-a> and <C-x>: changes the number under or after each cursor (on its line) by the count,
+/// `sign` is -1 for <C-x>. The cursors go to the last characters of the numbers.
+pub(crate) fn increment(ed_state: &EditorState, sign: i128) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let delta = sign * ed_state.count_or_one() as i128;
+    let targets = buffer
+        .selections
+        .iter()
+        .map(|selection| {
+            let start = buffer.line_start(selection.head);
+            numbers(buffer, start..buffer.line_end(start))
+                .into_iter()
+                .find(|number| number.range.end > selection.head)
+        })
+        .collect::<Vec<_>>();
+    let mut transaction = Transaction::new();
+    let mut ranges = Vec::new();
+    for number in targets.iter().flatten().rev() {
+        // cursors on the same number change it once
+        if ranges.contains(&number.range) {
+            continue;
+        }
+        ranges.push(number.range.clone());
+        if let Some(text) = changed(number, delta) {
+            transaction.replace(ed_state.buffer_idx, number.range.clone(), &text);
+        }
+    }
+    let mut selections = buffer.selections.clone();
+    let mut targets = targets.iter();
+    selections.map(|selection| {
+        let offset = match targets.next() {
+            Some(Some(number)) => transaction.shifted(ed_state.buffer_idx, number.range.end) - 1,
+            _ => transaction.shifted(ed_state.buffer_idx, selection.head),
+        };
+        let line_start = match buffer.line_start(selection.head) {
+            0 => 0,
+            start => transaction.shifted(ed_state.buffer_idx, start - 1) + 1,
+        };
+        crate::selections::Selection::cursor(offset, offset - line_start)
+    });
+    transaction.selections(selections);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// <C-a> and <C-x> in visual mode change the first number of every selected line by the count,
+/// `g<C-a>` and `g<C-x>` change the one of the n-th line n times as much (to number lists)
+pub(crate) fn increment_selected(
+    ed_state: &EditorState,
+    sign: i128,
+    progressive: bool,
+) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let step = sign * ed_state.count_or_one() as i128;
+    let mut targets = Vec::new();
+    for range in crate::visual::selected_ranges(ed_state) {
+        let mut start = range.start;
+        while start < range.end {
+            let end = buffer.line_end(start);
+            if let Some(number) = numbers(buffer, start..end.min(range.end))
+                .into_iter()
+                .next()
+            {
+                let delta = if progressive {
+                    step * (targets.len() as i128 + 1)
+                } else {
+                    step
+                };
+                targets.push((number, delta));
+            }
+            start = end + 1;
+        }
+    }
+    let mut transaction = Transaction::new();
+    for (number, delta) in targets.iter().rev() {
+        if let Some(text) = changed(number, *delta) {
+            transaction.replace(ed_state.buffer_idx, number.range.clone(), &text);
+        }
+    }
+    crate::visual::back_to_normal(ed_state, &mut transaction);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first number of `text` changed by `delta`
+    fn change(text: &str, delta: i128) -> Option<String> {
+        let buffer = Buffer::from_text(text);
+        changed(&numbers(&buffer, 0..buffer.len())[0], delta)
+    }
+
+    #[test]
+    fn test_hex_digits_keep_their_case() {
+        assert_eq!(change("0xab", 1).as_deref(), Some("0xac"));
+        assert_eq!(change("0xAB", 1).as_deref(), Some("0xAC"));
+        assert_eq!(change("0xfF", 1).as_deref(), Some("0x100"));
+        // without letters among the digits the prefix tells the case
+        assert_eq!(change("0X9", 1).as_deref(), Some("0XA"));
+        assert_eq!(change("0x9", 1).as_deref(), Some("0xa"));
+    }
+    #[test]
+    fn test_zero_padding() {
+        assert_eq!(change("007", -1).as_deref(), Some("006"));
+        assert_eq!(change("099", 1).as_deref(), Some("100"));
+        assert_eq!(change("-007", 10).as_deref(), Some("003"));
+        assert_eq!(change("0x00ff", 1).as_deref(), Some("0x0100"));
+        assert_eq!(change("0b0111", 1).as_deref(), Some("0b1000"));
+        // unpadded numbers get shorter
+        assert_eq!(change("10", -1).as_deref(), Some("9"));
+    }
+    #[test]
+    fn test_decimal_numbers_stop_at_i64_limits() {
+        assert_eq!(change("9223372036854775807", 1).as_deref(), Some("9223372036854775807"));
+        assert_eq!(change("-9223372036854775808", -1).as_deref(), Some("-9223372036854775808"));
+        assert_eq!(change("18446744073709551615", 0).as_deref(), Some("9223372036854775807"));
+        assert_eq!(change("18446744073709551616", 1), None);
+    }
+    #[test]
+    fn test_other_numbers_wrap_around_u64_limits() {
+        assert_eq!(change("0xffffffffffffffff", 1).as_deref(), Some("0x0000000000000000"));
+        assert_eq!(change("0x0", -1).as_deref(), Some("0xffffffffffffffff"));
+        assert_eq!(change("0o0", -1).as_deref(), Some("0o1777777777777777777777"));
+        assert_eq!(change("0x10000000000000000", 1), None);
+    }
+}
@@ -0,0 +1,393 @@
+use crate::editor_buffer::{Buffer, CursorEdit};
+use crate::editor_state::{EditorGlobals, EditorMode, EditorState, EditorStateUpdate};
+use crate::selections::{Selection, Selections};
+use crate::transaction::Transaction;
+
+/// `shiftwidth`, which falls back to the tab size when it is 0 (like in vim)
+pub(crate) fn shift_width(editor_globals: &EditorGlobals) -> usize {
+    match editor_globals.shift_width {
+        0 => editor_globals.tab_size.max(1),
+        shift_width => shift_width,
+    }
+}
+
+/// The display column of `offset` in its line, tabs reach to the next tab stop
+pub(crate) fn column(buffer: &Buffer, offset: usize, tab_size: usize) -> usize {
+    let tab_size = tab_size.max(1);
+    (buffer.line_start(offset)..offset).fold(0, |column, offset| match buffer.char_at(offset) {
+        Some('\t') => (column / tab_size + 1) * tab_size,
+        _ => column + 1,
+    })
+}
+
+/// How wide the leading whitespace of the line of `offset` is
+pub(crate) fn indent_width(buffer: &Buffer, offset: usize, tab_size: usize) -> usize {
+    column(
+        buffer,
+        crate::text_motions::first_non_blank(buffer, offset),
+        tab_size,
+    )
+}
+
+/// The whitespace filling the columns from `from` to `to`: as many tabs as fit (unless
+/// `expandtab` is set) and spaces for the rest
+pub(crate) fn whitespace(editor_globals: &EditorGlobals, from: usize, to: usize) -> Vec<char> {
+    fill(editor_globals.tab_size, editor_globals.expand_tab, from, to)
+}
+
+fn fill(tab_size: usize, expand_tab: bool, from: usize, to: usize) -> Vec<char> {
+    let tab_size = tab_size.max(1);
+    let mut text = Vec::new();
+    let mut column = from;
+    while !expand_tab && (column / tab_size + 1) * tab_size <= to {
+        text.push('\t');
+        column = (column / tab_size + 1) * tab_size;
+    }
+    text.resize(text.len() + to.saturating_sub(column), ' ');
+    text
+}
+
+fn is_blank(c: Option<char>) -> bool {
+    matches!(c, Some(' ' | '\t'))
+}
+
+/// Enter in insert mode: the new line gets the indentation of the current one with `autoindent`,
+/// and one level more after a line opening a block with `smartindent`
+pub(crate) fn newline_edit(
+    buffer: &Buffer,
+    editor_globals: &EditorGlobals,
+    head: usize,
+) -> CursorEdit {
+    let mut width = 0;
+    if editor_globals.auto_indent || editor_globals.smart_indent {
+        width = indent_width(buffer, head, editor_globals.tab_size);
+    }
+    if editor_globals.smart_indent {
+        let rules = crate::filetype::indent_rules(crate::filetype::detect(buffer));
+        let line_start = buffer.line_start(head);
+        let last = (line_start..head)
+            .rev()
+            .map(|offset| buffer.char_at(offset))
+            .find(|&c| !is_blank(c))
+            .flatten();
+        if last.is_some_and(|c| rules.openers.contains(c)) {
+            width += shift_width(editor_globals);
+        }
+    }
+    let mut text = vec!['\n'];
+    text.extend(whitespace(editor_globals, 0, width));
+    CursorEdit::before(head, 0, text)
+}
+
+/// A typed character, with `smartindent` a closer typed first on a line takes away a level
+pub(crate) fn char_edit(
+    buffer: &Buffer,
+    editor_globals: &EditorGlobals,
+    head: usize,
+    c: char,
+) -> CursorEdit {
+    let line_start = buffer.line_start(head);
+    let rules = crate::filetype::indent_rules(crate::filetype::detect(buffer));
+    if editor_globals.smart_indent
+        && rules.closers.contains(c)
+        && head > line_start
+        && (line_start..head).all(|offset| is_blank(buffer.char_at(offset)))
+    {
+        let width = column(buffer, head, editor_globals.tab_size)
+            .saturating_sub(shift_width(editor_globals));
+        let mut text = whitespace(editor_globals, 0, width);
+        text.push(c);
+        return CursorEdit::before(head, head - line_start, text);
+    }
+    CursorEdit::before(head, 0, vec![c])
+}
+
+/// Tab in insert mode goes to the next tab stop (or soft tab stop), with spaces if `expandtab` is
+/// set. Without it the whitespace in front of the cursor is redone with as many tabs as fit.
+pub(crate) fn tab_edit(buffer: &Buffer, editor_globals: &EditorGlobals, head: usize) -> CursorEdit {
+    let tab_size = editor_globals.tab_size.max(1);
+    let stop = match editor_globals.soft_tab_stop {
+        0 => tab_size,
+        soft_tab_stop => soft_tab_stop,
+    };
+    let current = column(buffer, head, tab_size);
+    let target = (current / stop + 1) * stop;
+    if editor_globals.expand_tab {
+        return CursorEdit::before(head, 0, vec![' '; target - current]);
+    }
+    if editor_globals.soft_tab_stop == 0 {
+        return CursorEdit::before(head, 0, vec!['\t']);
+    }
+    let line_start = buffer.line_start(head);
+    let mut start = head;
+    while start > line_start && is_blank(buffer.char_at(start - 1)) {
+        start -= 1;
+    }
+    let from = column(buffer, start, tab_size);
+    CursorEdit::before(head, head - start, whitespace(editor_globals, from, target))
+}
+
+/// Backspace in insert mode deletes one character, or with `softtabstop` the whitespace back to
+/// the previous soft tab stop (a tab reaching past it is made up for with spaces)
+pub(crate) fn backspace_edit(
+    buffer: &Buffer,
+    editor_globals: &EditorGlobals,
+    head: usize,
+) -> CursorEdit {
+    let soft_tab_stop = editor_globals.soft_tab_stop;
+    let line_start = buffer.line_start(head);
+    if soft_tab_stop == 0 || head == line_start || !is_blank(buffer.char_at(head - 1)) {
+        return CursorEdit::before(head, 1, Vec::new());
+    }
+    let tab_size = editor_globals.tab_size;
+    let target = (column(buffer, head, tab_size) - 1) / soft_tab_stop * soft_tab_stop;
+    let mut start = head;
+    while start > line_start
+        && is_blank(buffer.char_at(start - 1))
+        && column(buffer, start, tab_size) > target
+    {
+        start -= 1;
+    }
+    let refill = whitespace(editor_globals, column(buffer, start, tab_size), target);
+    CursorEdit::before(head, head - start, refill)
+}
+
+/// The lines an indent operator works on as (first, last) spans: from every cursor to where
+/// `text_motion` takes it, or `count` lines from the cursor for `>>`, `<<` and `==` (no motion)
+pub(crate) fn operator_lines(
+    ed_state: &EditorState,
+    text_motion: Option<&crate::text_motions::TextMotion>,
+) -> Vec<(usize, usize)> {
+    let buffer = ed_state.get_buffer();
+    let last_line = buffer.line_count().saturating_sub(1);
+    let mut lines = buffer
+        .selections
+        .iter()
+        .map(|selection| {
+            let line = buffer.position(selection.head).0;
+            let Some(text_motion) = text_motion else {
+                return (line, (line + ed_state.count_or_one() - 1).min(last_line));
+            };
+            let target = (text_motion.function)(
+                buffer,
+                selection.head,
+                selection.desired_column,
+                ed_state.count,
+            );
+            let target = buffer.position(target.min(buffer.len())).0;
+            (line.min(target), line.max(target))
+        })
+        .collect::<Vec<_>>();
+    lines.sort();
+    // cursors close together can reach into each other's lines
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for (first, last) in lines {
+        match spans.last_mut() {
+            Some(span) if first <= span.1 => span.1 = span.1.max(last),
+            _ => spans.push((first, last)),
+        }
+    }
+    spans
+}
+
+/// Replaces the indentation of the line starting at `start` with `width` columns of whitespace
+fn set_indent(transaction: &mut Transaction, ed_state: &EditorState, start: usize, width: usize) {
+    let buffer = ed_state.get_buffer();
+    let end = crate::text_motions::first_non_blank(buffer, start);
+    let text = whitespace(&ed_state.editor_globals, 0, width);
+    if buffer.content.slice(start..end) != text {
+        transaction.replace(
+            ed_state.buffer_idx,
+            start..end,
+            &text.iter().collect::<String>(),
+        );
+    }
+}
+
+/// Puts the cursors on the first non-blank of the first line of every span and goes back to
+/// normal mode
+pub(crate) fn finish(
+    ed_state: &EditorState,
+    spans: &[(usize, usize)],
+    mut transaction: Transaction,
+) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let cursors = spans
+        .iter()
+        .map(|&(first, _)| {
+            let start = buffer.line_offset(first);
+            let first_non_blank = crate::text_motions::first_non_blank(buffer, start);
+            let offset = transaction.shifted(ed_state.buffer_idx, first_non_blank);
+            // the line break in front stays where it is, unlike the indentation replaced after it
+            let line_start = match start {
+                0 => 0,
+                start => transaction.shifted(ed_state.buffer_idx, start - 1) + 1,
+            };
+            Selection::cursor(offset, offset - line_start)
+        })
+        .collect::<Vec<_>>();
+    transaction
+        .selections(Selections::new(cursors, 0))
+        .mode(EditorMode::Normal);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// `>` and `<`: shifts the lines of `spans` right (or left) by `levels` times `shiftwidth`, the
+/// indentation is redone with tabs unless `expandtab` is set. Empty lines are left alone.
+pub(crate) fn shift(
+    ed_state: &EditorState,
+    spans: &[(usize, usize)],
+    right: bool,
+    levels: usize,
+) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let amount = shift_width(&ed_state.editor_globals) * levels;
+    let mut transaction = Transaction::new();
+    for &(first, last) in spans.iter().rev() {
+        for line in (first..=last).rev() {
+            let start = buffer.line_offset(line);
+            if buffer.line_end(start) == start {
+                continue;
+            }
+            let width = indent_width(buffer, start, ed_state.editor_globals.tab_size);
+            let width = if right {
+                width + amount
+            } else {
+                width.saturating_sub(amount)
+            };
+            set_indent(&mut transaction, ed_state, start, width);
+        }
+    }
+    finish(ed_state, spans, transaction)
+}
+
+/// Whether the line starting at `start` ends in one of the characters opening a block
+fn opens_block(buffer: &Buffer, start: usize, openers: &str) -> bool {
+    (start..buffer.line_end(start))
+        .rev()
+        .map(|offset| buffer.char_at(offset))
+        .find(|&c| !is_blank(c))
+        .flatten()
+        .is_some_and(|c| openers.contains(c))
+}
+
+/// `=`: indents the lines of `spans` by the indent rules of the filetype, every line gets the
+/// indentation of the line above, a level more if that one opens a block and a level less if it
+/// closes one itself. Without closers (like in python) a line can stay further left, ending the
+/// block. Lines of only whitespace are emptied.
+pub(crate) fn reindent(ed_state: &EditorState, spans: &[(usize, usize)]) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let tab_size = ed_state.editor_globals.tab_size;
+    let shift_width = shift_width(&ed_state.editor_globals);
+    let rules = crate::filetype::indent_rules(crate::filetype::detect(buffer));
+    let is_blank_line = |start: usize| {
+        crate::text_motions::first_non_blank(buffer, start) == buffer.line_end(start)
+    };
+    let mut widths = Vec::new();
+    for &(first, last) in spans {
+        // the line above the span stays as it is
+        let mut above = (0..first)
+            .rev()
+            .map(|line| buffer.line_offset(line))
+            .find(|&start| !is_blank_line(start))
+            .map(|start| {
+                let width = indent_width(buffer, start, tab_size);
+                (width, opens_block(buffer, start, rules.openers))
+            });
+        for line in first..=last {
+            let start = buffer.line_offset(line);
+            if is_blank_line(start) {
+                widths.push((start, 0));
+                continue;
+            }
+            let (mut width, opens) = above.unwrap_or((0, false));
+            if opens {
+                width += shift_width;
+            }
+            let first_non_blank = crate::text_motions::first_non_blank(buffer, start);
+            if buffer
+                .char_at(first_non_blank)
+                .is_some_and(|c| rules.closers.contains(c))
+            {
+                width = width.saturating_sub(shift_width);
+            }
+            if rules.closers.is_empty() && !opens {
+                width = width.min(indent_width(buffer, start, tab_size));
+            }
+            widths.push((start, width));
+            above = Some((width, opens_block(buffer, start, rules.openers)));
+        }
+    }
+    let mut transaction = Transaction::new();
+    for &(start, width) in widths.iter().rev() {
+        set_indent(&mut transaction, ed_state, start, width);
+    }
+    finish(ed_state, spans, transaction)
+}
+
+/// `:retab`: redoes the runs of whitespace containing tabs (with `bang` all of them) with tabs or
+/// spaces as `expandtab` says. With a new `tab_size` the runs are laid out for it, so the text
+/// looks the same after the tab size changed.
+pub(crate) fn retab(ed_state: &EditorState, bang: bool, tab_size: usize) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let old_tab_size = ed_state.editor_globals.tab_size.max(1);
+    let mut runs = Vec::new();
+    let mut column = 0;
+    let mut offset = 0;
+    while offset < buffer.len() {
+        if !is_blank(buffer.char_at(offset)) {
+            column = match buffer.char_at(offset) {
+                Some('\n') => 0,
+                _ => column + 1,
+            };
+            offset += 1;
+            continue;
+        }
+        let (start, from) = (offset, column);
+        let mut has_tab = false;
+        while let Some(c @ (' ' | '\t')) = buffer.char_at(offset) {
+            has_tab |= c == '\t';
+            column = if c == '\t' {
+                (column / old_tab_size + 1) * old_tab_size
+            } else {
+                column + 1
+            };
+            offset += 1;
+        }
+        if has_tab || bang {
+            runs.push((start..offset, from, column));
+        }
+    }
+    let mut transaction = Transaction::new();
+    for (range, from, to) in runs.into_iter().rev() {
+        let text = fill(tab_size, ed_state.editor_globals.expand_tab, from, to);
+        if buffer.content.slice(range.clone()) != text {
+            transaction.replace(ed_state.buffer_idx, range, &text.iter().collect::<String>());
+        }
+    }
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// <C-t> and <C-d> in insert mode: the lines of the cursors get one `shiftwidth` more (or less)
+/// indentation, rounded to a multiple of it. The cursors stay on the same text.
+pub(crate) fn shift_cursor_lines(ed_state: &EditorState, right: bool) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let shift_width = shift_width(&ed_state.editor_globals);
+    let mut line_starts = buffer
+        .selections
+        .iter()
+        .map(|selection| buffer.line_start(selection.head))
+        .collect::<Vec<_>>();
+    line_starts.dedup();
+    let mut transaction = Transaction::new();
+    for &start in line_starts.iter().rev() {
+        let width = indent_width(buffer, start, ed_state.editor_globals.tab_size);
+        let width = if right {
+            (width / shift_width + 1) * shift_width
+        } else {
+            width.saturating_sub(1) / shift_width * shift_width
+        };
+        set_indent(&mut transaction, ed_state, start, width);
+    }
+    EditorStateUpdate::Transaction(transaction)
+}
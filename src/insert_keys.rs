@@ -0,0 +1,188 @@
+use crate::editor_state::{EditorState, EditorStateUpdate};
+use crossterm::event::{KeyCode, KeyModifiers};
+
+/// Deletes from every cursor back to the offset `start` gives for it, a cursor at the start of a
+/// line joins it with the line above instead
+fn delete_back(ed_state: &EditorState, start: impl Fn(usize) -> usize) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let heads = buffer.selections.iter().map(|s| s.head).collect::<Vec<_>>();
+    let mut transaction = crate::transaction::Transaction::new();
+    for (i, &head) in heads.iter().enumerate().rev() {
+        let from = if buffer.line_start(head) == head {
+            head.saturating_sub(1)
+        } else {
+            start(head)
+        };
+        // never across the cursor in front
+        let from = if i == 0 { from } else { from.max(heads[i - 1]) };
+        if from < head {
+            transaction.delete(ed_state.buffer_idx, from..head);
+        }
+    }
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// <C-w> deletes the word in front of the cursors (and the whitespace behind it)
+pub(crate) fn delete_word(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let class = |c: char| (!c.is_whitespace(), c.is_alphanumeric() || c == '_');
+    delete_back(ed_state, |head| {
+        let line_start = buffer.line_start(head);
+        let mut start = head;
+        while start > line_start && buffer.char_at(start - 1).is_some_and(|c| c.is_whitespace()) {
+            start -= 1;
+        }
+        let Some(word_class) = start
+            .checked_sub(1)
+            .and_then(|o| buffer.char_at(o))
+            .map(class)
+        else {
+            return start;
+        };
+        while start > line_start && buffer.char_at(start - 1).map(class) == Some(word_class) {
+            start -= 1;
+        }
+        start
+    })
+}
+
+/// <C-u> deletes the text in front of the cursors back to the indentation, or the indentation
+/// if that is all there is
+pub(crate) fn delete_line(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    delete_back(
+        ed_state,
+        |head| match crate::text_motions::first_non_blank(buffer, head) {
+            first_non_blank if first_non_blank < head => first_non_blank,
+            _ => buffer.line_start(head),
+        },
+    )
+}
+
+/// <C-r>{register} inserts the text of the register at every cursor
+pub(crate) fn insert_register(ed_state: &EditorState, name: char) -> EditorStateUpdate {
+    crate::clipboard::paste(ed_state, name, move |ed_state| {
+        insert_register_text(ed_state, name)
+    })
+}
+
+fn insert_register_text(ed_state: &EditorState, name: char) -> EditorStateUpdate {
+    let mut transaction = crate::transaction::Transaction::new();
+    match crate::registers::read(ed_state, name) {
+        Ok(register) => {
+            let buffer = ed_state.get_buffer();
+            for selection in buffer.selections.iter().rev() {
+                transaction.insert(ed_state.buffer_idx, selection.head, &register.text);
+            }
+        }
+        Err(error) => {
+            transaction.message(error.to_string());
+        }
+    }
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// Goes back to insert (or replace) mode when the command run with <C-o> is done (and back in
+/// normal mode), returns whether it did
+pub(crate) fn resume_insert(ed_state: &mut EditorState) -> bool {
+    let Some(kind) = ed_state.insert_after_command else {
+        return false;
+    };
+    match ed_state.mode {
+        crate::editor_state::EditorMode::Normal => {
+            ed_state.mode = match kind {
+                crate::editor_state::ModeKind::Replace => {
+                    crate::editor_state::EditorMode::new_replace()
+                }
+                _ => crate::editor_state::EditorMode::Insert,
+            };
+            ed_state.insert_after_command = None;
+            true
+        }
+        // the command started typing itself (like `<C-o>cc`)
+        ref mode if mode.is_typing() => {
+            ed_state.insert_after_command = None;
+            false
+        }
+        _ => false,
+    }
+}
+
+/// The character the digits typed after <C-v> stand for: `u` and four hex digits, `U` and eight,
+/// `x` and two, or three decimal digits (up to 255)
+fn code_point(input: &str) -> (Option<char>, bool) {
+    let (digits, radix, max_len) = match input.chars().next() {
+        Some('u') => (&input[1..], 16, 4),
+        Some('U') => (&input[1..], 16, 8),
+        Some('x') => (&input[1..], 16, 2),
+        _ => (input, 10, 3),
+    };
+    let c = u32::from_str_radix(digits, radix)
+        .ok()
+        .and_then(char::from_u32);
+    (c, digits.len() == max_len)
+}
+
+/// A key typed after <C-v> in insert (or replace) mode: it is inserted as it is (control keys as
+/// control characters), or it starts or continues a character code
+pub(crate) fn literal_key(key: crate::editor_state::PendingKey, ed_state: &mut EditorState) {
+    let Some(mut input) = ed_state.literal_input.take() else {
+        return;
+    };
+    let atom = key.atom;
+    // a decimal digit that would go past 255 ends the code instead
+    let is_digit = |c: char| match input.chars().next() {
+        Some('u' | 'U' | 'x') => c.is_ascii_hexdigit(),
+        _ => c.is_ascii_digit() && format!("{input}{c}").parse::<u32>().is_ok_and(|n| n <= 255),
+    };
+    let ends_code = !input.is_empty()
+        && !matches!(atom.code, KeyCode::Char(c) if is_digit(c) && atom.modifiers.is_empty());
+    // a key ending a code is recorded when it is typed as usual
+    if !ends_code && ed_state.dot_repeat.in_insert_session() {
+        ed_state.dot_repeat.record_keys(&[atom]);
+    }
+    let text = match atom.code {
+        KeyCode::Char(c @ ('u' | 'U' | 'x')) if input.is_empty() && atom.modifiers.is_empty() => {
+            ed_state.literal_input = Some(c.to_string());
+            return;
+        }
+        KeyCode::Char(c) if is_digit(c) && atom.modifiers.is_empty() => {
+            input.push(c);
+            match code_point(&input) {
+                (Some(c), true) => c.to_string(),
+                _ => {
+                    ed_state.literal_input = Some(input);
+                    return;
+                }
+            }
+        }
+        // any other key ends a code, and is typed as usual then
+        _ if ends_code => {
+            ed_state
+                .input_queue
+                .push_front(crate::editor_state::QueuedInput::Key(key));
+            match code_point(&input) {
+                (Some(c), _) => c.to_string(),
+                _ => return,
+            }
+        }
+        KeyCode::Char(c) if c.is_ascii() && atom.modifiers.contains(KeyModifiers::CONTROL) => {
+            char::from(c.to_ascii_uppercase() as u8 & 0x1f).to_string()
+        }
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Tab => "\t".to_string(),
+        // a line break without autoindent
+        KeyCode::Enter => "\n".to_string(),
+        KeyCode::Esc => "\x1b".to_string(),
+        KeyCode::Backspace => "\x08".to_string(),
+        _ => return,
+    };
+    if let crate::editor_state::EditorMode::Replace { .. } = ed_state.mode {
+        crate::replace::overwrite(ed_state, &text);
+        return;
+    }
+    ed_state
+        .get_buffer_mut()
+        .insert_at_cursors(&text.chars().collect::<Vec<_>>());
+    ed_state.registers.inserted(&text);
+}
@@ -0,0 +1,143 @@
+use crate::anchors::AnchorId;
+use crate::editor_state::EditorState;
+use std::fmt::Write;
+
+/// How many entries the jumplist and the changelists keep (like vim)
+const MAX_ENTRIES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Jump {
+    pub(crate) buffer_idx: usize,
+    /// anchor in the anchors of buffer `buffer_idx`
+    pub(crate) anchor: AnchorId,
+}
+
+/// Positions the cursor jumped away from (gg, G, switching buffers, ...), one list for all buffers.
+/// `idx` is how far <C-o> went back, it is `jumps.len()` while not stepping through the list.
+#[derive(Debug, Default)]
+pub(crate) struct Jumplist {
+    pub(crate) jumps: Vec<Jump>,
+    pub(crate) idx: usize,
+}
+
+/// Positions of the changes made to a buffer, for `g;` and `g,`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Changelist {
+    pub(crate) changes: Vec<AnchorId>,
+    pub(crate) idx: usize,
+}
+
+fn fail(ed_state: &mut EditorState, message: &str) {
+    crate::macros::abort(ed_state);
+    writeln!(&mut ed_state.command_line, "{}", message)
+        .expect("Fatal: Could not write to command line buffer");
+}
+
+/// Moves the cursor to `offset` in buffer `buffer_idx`, switching to it if needed
+pub(crate) fn go_to(ed_state: &mut EditorState, buffer_idx: usize, offset: usize) {
+    ed_state.buffer_idx = buffer_idx;
+    let buffer = ed_state.get_buffer_mut();
+    let offset = crate::text_motions::clamp_to_line(buffer, offset.min(buffer.len()));
+    buffer.selections = crate::selections::Selections::single(
+        crate::selections::Selection::cursor(offset, buffer.position(offset).1),
+    );
+}
+
+/// Remembers the position of the primary cursor before a jump. Older entries on the same line are
+/// dropped, so every line is in the list only once.
+pub(crate) fn record_jump(ed_state: &mut EditorState) {
+    let buffer_idx = ed_state.buffer_idx;
+    let buffers = &mut ed_state.buffers;
+    let offset = buffers[buffer_idx].cursor();
+    let line = buffers[buffer_idx].position(offset).0;
+    let jumplist = &mut ed_state.jumplist;
+    jumplist.jumps.retain(|jump| {
+        let buffer = &mut buffers[jump.buffer_idx];
+        let same_line = jump.buffer_idx == buffer_idx
+            && buffer
+                .anchors
+                .get(jump.anchor)
+                .is_some_and(|offset| buffer.position(offset).0 == line);
+        if same_line {
+            buffer.anchors.remove(jump.anchor);
+        }
+        !same_line
+    });
+    let anchor = buffers[buffer_idx].anchors.insert(offset);
+    jumplist.jumps.push(Jump { buffer_idx, anchor });
+    if jumplist.jumps.len() > MAX_ENTRIES {
+        let oldest = jumplist.jumps.remove(0);
+        buffers[oldest.buffer_idx].anchors.remove(oldest.anchor);
+    }
+    jumplist.idx = jumplist.jumps.len();
+}
+
+/// <C-o> (negative `steps`) and <C-i> (positive `steps`)
+pub(crate) fn step_jumplist(ed_state: &mut EditorState, steps: isize) {
+    if steps < 0 && ed_state.jumplist.idx >= ed_state.jumplist.jumps.len() {
+        // remember where the cursor was, so <C-i> can come back to it
+        record_jump(ed_state);
+        ed_state.jumplist.idx -= 1;
+    }
+    let jumplist = &ed_state.jumplist;
+    if steps < 0 && jumplist.idx == 0 {
+        return fail(ed_state, "At start of jumplist");
+    }
+    if steps > 0 && jumplist.idx + 1 >= jumplist.jumps.len() {
+        return fail(ed_state, "At end of jumplist");
+    }
+    let target = (jumplist.idx as isize + steps).clamp(0, jumplist.jumps.len() as isize - 1);
+    let jump = jumplist.jumps[target as usize];
+    ed_state.jumplist.idx = target as usize;
+    if let Some(offset) = ed_state.buffers[jump.buffer_idx].anchors.get(jump.anchor) {
+        go_to(ed_state, jump.buffer_idx, offset);
+    }
+}
+
+/// Records a change at `offset` in the changelist of `buffer`. A change on the line of the last
+/// one replaces it, so typing a line of text is one entry.
+pub(crate) fn record_change(buffer: &mut crate::editor_buffer::Buffer, offset: usize) {
+    let line = buffer.position(offset).0;
+    let last = buffer
+        .changelist
+        .changes
+        .last()
+        .and_then(|&anchor| Some((anchor, buffer.anchors.get(anchor)?)));
+    match last {
+        Some((anchor, last_offset)) if buffer.position(last_offset).0 == line => {
+            buffer.anchors.set(anchor, offset);
+        }
+        _ => {
+            let anchor = buffer.anchors.insert(offset);
+            buffer.changelist.changes.push(anchor);
+            if buffer.changelist.changes.len() > MAX_ENTRIES {
+                let oldest = buffer.changelist.changes.remove(0);
+                buffer.anchors.remove(oldest);
+            }
+        }
+    }
+    buffer.changelist.idx = buffer.changelist.changes.len();
+}
+
+/// `g;` (negative `steps`) and `g,` (positive `steps`)
+pub(crate) fn step_changelist(ed_state: &mut EditorState, steps: isize) {
+    let changelist = &ed_state.get_buffer().changelist;
+    let len = changelist.changes.len();
+    if len == 0 {
+        return fail(ed_state, "Changelist is empty");
+    }
+    if steps < 0 && changelist.idx == 0 {
+        return fail(ed_state, "At start of changelist");
+    }
+    if steps > 0 && changelist.idx + 1 >= len {
+        return fail(ed_state, "At end of changelist");
+    }
+    let target = (changelist.idx as isize + steps).clamp(0, len as isize - 1) as usize;
+    let anchor = changelist.changes[target];
+    let buffer_idx = ed_state.buffer_idx;
+    let buffer = ed_state.get_buffer_mut();
+    buffer.changelist.idx = target;
+    if let Some(offset) = buffer.anchors.get(anchor) {
+        go_to(ed_state, buffer_idx, offset);
+    }
+}
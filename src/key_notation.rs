@@ -0,0 +1,218 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::motion_interpreter::MotionAtom;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum KeyNotationError {
+    #[error("Empty key sequence")]
+    EmptyKeySequenceError,
+    #[error("Unknown key name: <{0}>")]
+    UnknownKeyNameError(String),
+}
+
+fn parse_key_name(name: &str) -> Option<KeyCode> {
+    let code = match name.to_ascii_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "bar" => KeyCode::Char('|'),
+        "bslash" => KeyCode::Char('\\'),
+        "del" | "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "nul" => KeyCode::Null,
+        lower => {
+            if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                KeyCode::F(n)
+            } else {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return None,
+                }
+            }
+        }
+    };
+    Some(code)
+}
+
+/// Parses the contents of a `<...>` group (without the angle brackets), eg `C-w` or `Esc`
+fn parse_special(inner: &str) -> Option<MotionAtom> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = inner;
+    // a modifier prefix needs at least one character after the dash, otherwise `<C-->` and `<->`
+    // would not parse
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        modifiers |= match rest.as_bytes()[0].to_ascii_lowercase() {
+            b'c' => KeyModifiers::CONTROL,
+            b's' => KeyModifiers::SHIFT,
+            b'a' | b'm' => KeyModifiers::ALT,
+            _ => return None,
+        };
+        rest = &rest[2..];
+    }
+    let mut code = parse_key_name(rest)?;
+    // keys are stored the way the terminal reports them (see `MotionAtom::from_key_event`): `<S-x>`
+    // comes as `X`, `<S-Tab>` as a back tab and `<C-A>` as `<C-a>`
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        match code {
+            KeyCode::Char(c) => code = KeyCode::Char(c.to_ascii_uppercase()),
+            KeyCode::Tab => code = KeyCode::BackTab,
+            _ => {}
+        }
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+    }
+    if let KeyCode::Char(c) = code {
+        if modifiers.contains(KeyModifiers::CONTROL) {
+            code = KeyCode::Char(c.to_ascii_lowercase());
+        }
+    }
+    Some(MotionAtom::new(code, modifiers))
+}
+
+/// Parses vim style key notation (eg `y$`, `<Esc>`, `<C-w>j`) into a sequence of motion atoms
+pub(crate) fn parse(notation: &str) -> Result<Vec<MotionAtom>, KeyNotationError> {
+    let mut atoms = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let inner = &rest[1..end];
+                // `<>` or `<` followed by something that is not a key name is a literal `<`
+                if !inner.is_empty() && !inner.contains('<') {
+                    match parse_special(inner) {
+                        Some(atom) => {
+                            atoms.push(atom);
+                            rest = &rest[end + 1..];
+                            continue;
+                        }
+                        None if inner.len() > 1
+                            && inner.chars().all(|c| c.is_alphanumeric() || c == '-') =>
+                        {
+                            return Err(KeyNotationError::UnknownKeyNameError(inner.to_string()))
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+        atoms.push(MotionAtom::new(KeyCode::Char(c), KeyModifiers::NONE));
+        rest = &rest[c.len_utf8()..];
+    }
+    if atoms.is_empty() {
+        return Err(KeyNotationError::EmptyKeySequenceError);
+    }
+    Ok(atoms)
+}
+
+fn format_atom(atom: &MotionAtom) -> String {
+    let name = match atom.code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(c) if atom.modifiers.is_empty() => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Enter => "CR".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        KeyCode::Backspace => "BS".to_string(),
+        KeyCode::Delete => "Del".to_string(),
+        KeyCode::Insert => "Insert".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::PageUp => "PageUp".to_string(),
+        KeyCode::PageDown => "PageDown".to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::Null => "Nul".to_string(),
+        other => format!("{:?}", other),
+    };
+    let mut prefix = String::new();
+    if atom.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("C-");
+    }
+    if atom.modifiers.contains(KeyModifiers::SHIFT) {
+        prefix.push_str("S-");
+    }
+    if atom.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("A-");
+    }
+    format!("<{}{}>", prefix, name)
+}
+
+/// Formats a sequence of motion atoms back into key notation (inverse of `parse`)
+pub(crate) fn format(atoms: &[MotionAtom]) -> String {
+    atoms.iter().map(format_atom).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plain_chars() {
+        let atoms = parse("y$").unwrap();
+        assert_eq!(
+            atoms,
+            vec![
+                MotionAtom::new(KeyCode::Char('y'), KeyModifiers::NONE),
+                MotionAtom::new(KeyCode::Char('$'), KeyModifiers::NONE),
+            ]
+        );
+    }
+    #[test]
+    fn test_parse_special_keys() {
+        let atoms = parse("<Esc><C-w>j<lt>").unwrap();
+        assert_eq!(
+            atoms,
+            vec![
+                MotionAtom::new(KeyCode::Esc, KeyModifiers::NONE),
+                MotionAtom::new(KeyCode::Char('w'), KeyModifiers::CONTROL),
+                MotionAtom::new(KeyCode::Char('j'), KeyModifiers::NONE),
+                MotionAtom::new(KeyCode::Char('<'), KeyModifiers::NONE),
+            ]
+        );
+    }
+    #[test]
+    fn test_parse_literal_angle_bracket() {
+        let atoms = parse("<>").unwrap();
+        assert_eq!(atoms.len(), 2);
+        assert!(parse("<Foo>").is_err());
+        assert!(parse("").is_err());
+    }
+    #[test]
+    fn test_parse_keys_as_the_terminal_reports_them() {
+        let atoms = parse("<C-A><S-x><S-Tab><C-S-b>").unwrap();
+        assert_eq!(
+            atoms,
+            vec![
+                MotionAtom::new(KeyCode::Char('a'), KeyModifiers::CONTROL),
+                MotionAtom::new(KeyCode::Char('X'), KeyModifiers::NONE),
+                MotionAtom::new(KeyCode::BackTab, KeyModifiers::NONE),
+                MotionAtom::new(KeyCode::Char('b'), KeyModifiers::CONTROL),
+            ]
+        );
+        assert_eq!(format(&atoms), "<C-a>X<S-Tab><C-b>");
+        assert_eq!(parse(&format(&atoms)).unwrap(), atoms);
+    }
+    #[test]
+    fn test_format_roundtrip() {
+        let notation = "<C-w>j<Esc><Space>x<lt><F5>";
+        assert_eq!(format(&parse(notation).unwrap()), notation);
+    }
+}
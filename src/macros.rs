@@ -0,0 +1,101 @@
+use crate::editor_state::{EditorState, PendingKey, QueuedInput};
+use crate::motion_interpreter::MotionAtom;
+use std::fmt::Write;
+
+/// The registers macros can be recorded into (uppercase letters append) and played from
+pub(crate) const MACRO_REGISTERS: &str =
+    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789\"";
+
+/// Keys typed by the user since `q{register}`, before any mappings are expanded
+#[derive(Debug, Clone)]
+pub(crate) struct MacroRecording {
+    pub(crate) register: char,
+    pub(crate) keys: Vec<MotionAtom>,
+}
+
+impl MacroRecording {
+    pub(crate) fn new(register: char) -> Self {
+        Self {
+            register,
+            keys: Vec::new(),
+        }
+    }
+}
+
+fn message(ed_state: &mut EditorState, message: &str) {
+    writeln!(&mut ed_state.command_line, "{}", message)
+        .expect("Fatal: Could not write to command line buffer");
+}
+
+/// Stops recording and stores the keys in the register as key notation (so the macro can be
+/// edited like any other text). The `q` that stopped the recording is not part of the macro.
+pub(crate) fn stop_recording(ed_state: &mut EditorState) {
+    if let Some(mut recording) = ed_state.macro_recording.take() {
+        recording.keys.pop();
+        ed_state.registers.set(
+            recording.register,
+            crate::key_notation::format(&recording.keys),
+        );
+    }
+}
+
+/// Feeds the keys stored in `register` to the interpreter `count` times, `@` plays the register
+/// that was played last
+pub(crate) fn play(ed_state: &mut EditorState, register: char, count: usize) {
+    let register = match register {
+        '@' => match ed_state.last_macro_register {
+            Some(register) => register,
+            None => return message(ed_state, "No previously used register"),
+        },
+        register => register,
+    };
+    let text = match ed_state.registers.get(register) {
+        Some(register) if !register.text.is_empty() => register.text.clone(),
+        _ => return message(ed_state, &format!("Register {} is empty", register)),
+    };
+    let keys = match crate::key_notation::parse(&text) {
+        Ok(keys) => keys,
+        Err(error) => return message(ed_state, &error.to_string()),
+    };
+    ed_state.last_macro_register = Some(register);
+    // macros calling themselves run until a motion fails, but not forever
+    let depth = ed_state.current_key.map_or(0, |key| key.depth) + 1;
+    if depth > ed_state.editor_globals.max_map_depth {
+        abort(ed_state);
+        return message(ed_state, &format!("Recursive macro: @{}", register));
+    }
+    queue_runs(ed_state, keys, count, depth);
+}
+
+/// Queues the keys of one run of a macro, followed by the `count - 1` runs left
+pub(crate) fn queue_runs(
+    ed_state: &mut EditorState,
+    keys: Vec<MotionAtom>,
+    count: usize,
+    depth: usize,
+) {
+    for &atom in keys.iter().rev() {
+        ed_state.input_queue.push_front(QueuedInput::Key(PendingKey {
+            atom,
+            remap: true,
+            depth,
+            from_macro: true,
+        }));
+    }
+    if count > 1 {
+        let len = keys.len();
+        let runs = QueuedInput::MacroRuns {
+            keys,
+            count: count - 1,
+            depth,
+        };
+        ed_state.input_queue.insert(len, runs);
+    }
+}
+
+/// Drops the keys (and runs) of macros that are still queued, called when a motion fails
+pub(crate) fn abort(ed_state: &mut EditorState) {
+    ed_state
+        .input_queue
+        .retain(|input| matches!(input, QueuedInput::Key(key) if !key.from_macro));
+}
@@ -1,23 +1,49 @@
+// error variants are named `...Error` throughout the crate
+#![allow(clippy::enum_variant_names)]
+
 use crossterm::execute;
 
-mod cursor;
+mod abbreviations;
+mod anchors;
+mod autopairs;
+mod clipboard;
+mod comment;
+mod completion;
+mod dot_repeat;
 mod editor;
 mod editor_buffer;
 mod editor_state;
+mod ex_commands;
+mod filetype;
 mod gap_buffer;
+mod increment;
+mod indent;
+mod insert_keys;
+mod jumplist;
+mod key_notation;
+mod macros;
+mod marks;
 mod motion_interpreter;
+mod options;
+mod registers;
 mod setup_motions;
 mod render;
-
-// TODO refactor the crate to remove Cursor struct completely and instead compute it while
-// rendering on the fly
+mod replace;
+mod scheduler;
+mod selections;
+mod text_motions;
+mod transaction;
+mod undo;
+mod undo_file;
+mod visual;
 
 pub(crate) fn panic(reason: &str) -> ! {
     let mut stdout = std::io::stdout();
     execute!(
         stdout,
         crossterm::terminal::Clear(crossterm::terminal::ClearType::All),
-        crossterm::cursor::MoveTo(0, 0)
+        crossterm::cursor::MoveTo(0, 0),
+        crossterm::event::DisableMouseCapture
     ).unwrap();
     crossterm::terminal::disable_raw_mode().unwrap();
     panic!("{}", reason)
@@ -28,7 +54,8 @@ pub(crate) fn quit() -> ! {
     execute!(
         stdout,
         crossterm::terminal::Clear(crossterm::terminal::ClearType::All),
-        crossterm::cursor::MoveTo(0, 0)
+        crossterm::cursor::MoveTo(0, 0),
+        crossterm::event::DisableMouseCapture
     ).unwrap();
     crossterm::terminal::disable_raw_mode().unwrap();
     std::process::exit(0)
@@ -36,7 +63,9 @@ pub(crate) fn quit() -> ! {
 
 fn main() -> std::io::Result<()> {
     crossterm::terminal::enable_raw_mode()?;
+    execute!(std::io::stdout(), crossterm::event::EnableMouseCapture)?;
     let error = editor::run();
+    execute!(std::io::stdout(), crossterm::event::DisableMouseCapture)?;
     crossterm::terminal::disable_raw_mode()?;
     error
 }
@@ -0,0 +1,286 @@
+use crate::editor_state::EditorState;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MarkError {
+    #[error("Invalid mark: {0}")]
+    InvalidMarkError(char),
+    #[error("Mark not set: {0}")]
+    MarkNotSetError(char),
+    #[error("{0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// A mark `A`-`Z`, it can be jumped to from any buffer
+#[derive(Debug, Clone)]
+pub(crate) struct GlobalMark {
+    pub(crate) buffer_idx: usize,
+    pub(crate) anchor: crate::anchors::AnchorId,
+    /// the file of the buffer, to open it again when the buffer is gone
+    pub(crate) location: Option<PathBuf>,
+    /// (line, column) when the mark was set, used when the file has to be opened again
+    pub(crate) position: (usize, usize),
+}
+
+/// Whether the buffer the global mark was set in still holds its file
+fn is_open(ed_state: &EditorState, global_mark: &GlobalMark) -> bool {
+    ed_state
+        .buffers
+        .get(global_mark.buffer_idx)
+        .is_some_and(|buffer| buffer.location == global_mark.location)
+}
+
+/// Frees the anchor of a global mark that was moved or deleted, unless its buffer was replaced by
+/// another one (which has its own anchors)
+fn remove_anchor(ed_state: &mut EditorState, global_mark: &GlobalMark) {
+    if is_open(ed_state, global_mark) {
+        ed_state.buffers[global_mark.buffer_idx]
+            .anchors
+            .remove(global_mark.anchor);
+    }
+}
+
+/// Sets a buffer-local mark in `buffer` or moves it
+fn set_local(buffer: &mut crate::editor_buffer::Buffer, mark: char, offset: usize) {
+    match buffer.marks.get(&mark) {
+        Some(&anchor) => buffer.anchors.set(anchor, offset),
+        None => {
+            let anchor = buffer.anchors.insert(offset);
+            buffer.marks.insert(mark, anchor);
+        }
+    }
+}
+
+/// `m{mark}`, lowercase marks belong to the buffer, uppercase ones are global
+pub(crate) fn set_mark(ed_state: &mut EditorState, mark: char) -> Result<(), MarkError> {
+    let buffer_idx = ed_state.buffer_idx;
+    let buffer = &mut ed_state.buffers[buffer_idx];
+    let offset = buffer.cursor();
+    match mark {
+        'a'..='z' => set_local(buffer, mark, offset),
+        'A'..='Z' => {
+            let global_mark = GlobalMark {
+                buffer_idx,
+                anchor: buffer.anchors.insert(offset),
+                location: buffer.location.clone(),
+                position: buffer.position(offset),
+            };
+            if let Some(old) = ed_state.global_marks.insert(mark, global_mark) {
+                remove_anchor(ed_state, &old);
+            }
+        }
+        _ => return Err(MarkError::InvalidMarkError(mark)),
+    }
+    Ok(())
+}
+
+/// Where `mark` is as (buffer index, offset). The file of a global mark is opened again if no
+/// buffer has it anymore.
+fn mark_position(ed_state: &mut EditorState, mark: char) -> Result<(usize, usize), MarkError> {
+    let buffer = ed_state.get_buffer();
+    let position = match mark {
+        'a'..='z' | '<' | '>' | '^' => buffer
+            .marks
+            .get(&mark)
+            .and_then(|&anchor| buffer.anchors.get(anchor))
+            .map(|offset| (ed_state.buffer_idx, offset)),
+        '.' => buffer
+            .changelist
+            .changes
+            .last()
+            .and_then(|&anchor| buffer.anchors.get(anchor))
+            .map(|offset| (ed_state.buffer_idx, offset)),
+        // the position before the latest jump
+        '\'' | '`' => ed_state.jumplist.jumps.last().and_then(|jump| {
+            let offset = ed_state.buffers[jump.buffer_idx].anchors.get(jump.anchor)?;
+            Some((jump.buffer_idx, offset))
+        }),
+        'A'..='Z' => match ed_state.global_marks.get(&mark).cloned() {
+            Some(global_mark) => {
+                match ed_state.buffers.get(global_mark.buffer_idx) {
+                    Some(buffer) if is_open(ed_state, &global_mark) => buffer
+                        .anchors
+                        .get(global_mark.anchor)
+                        .map(|offset| (global_mark.buffer_idx, offset)),
+                    _ => match &global_mark.location {
+                        Some(location) => {
+                            let buffer_idx = ed_state.find_or_open_buffer(location)?;
+                            let (line, column) = global_mark.position;
+                            let offset = ed_state.buffers[buffer_idx].offset(line, column);
+                            Some((buffer_idx, offset))
+                        }
+                        None => None,
+                    },
+                }
+            }
+            None => None,
+        },
+        _ => return Err(MarkError::InvalidMarkError(mark)),
+    };
+    position.ok_or(MarkError::MarkNotSetError(mark))
+}
+
+/// `'{mark}` (`linewise`, to the first non-blank of the line) and `` `{mark} ``
+pub(crate) fn jump_to_mark(
+    ed_state: &mut EditorState,
+    mark: char,
+    linewise: bool,
+) -> Result<(), MarkError> {
+    let (buffer_idx, offset) = mark_position(ed_state, mark)?;
+    crate::jumplist::record_jump(ed_state);
+    let offset = if linewise {
+        crate::text_motions::first_non_blank(&ed_state.buffers[buffer_idx], offset)
+    } else {
+        offset
+    };
+    crate::jumplist::go_to(ed_state, buffer_idx, offset);
+    Ok(())
+}
+
+/// The primary selection of visual mode from before a command, its ends are kept in anchors so
+/// `'<` and `'>` stay on the same text when the command edits it
+pub(crate) struct VisualSelection {
+    buffer_idx: usize,
+    kind: crate::visual::VisualKind,
+    selection: crate::selections::Selection,
+    start: crate::anchors::AnchorId,
+    end: crate::anchors::AnchorId,
+}
+
+/// Starts tracking the primary selection if the editor is in visual mode, `update_special_marks`
+/// stops it again
+pub(crate) fn track_visual_selection(ed_state: &mut EditorState) -> Option<VisualSelection> {
+    let crate::editor_state::EditorMode::Visual(kind) = ed_state.mode else {
+        return None;
+    };
+    let buffer_idx = ed_state.buffer_idx;
+    let buffer = ed_state.get_buffer_mut();
+    let selection = buffer.selections.primary();
+    Some(VisualSelection {
+        buffer_idx,
+        kind,
+        selection,
+        start: buffer.anchors.insert(selection.start()),
+        end: buffer.anchors.insert(selection.end()),
+    })
+}
+
+/// Updates the special marks after a command ran: `'<`/`'>` when it left visual mode (`selection`
+/// is the visual selection from before the command) and `'^` when it left insert mode
+pub(crate) fn update_special_marks(
+    ed_state: &mut EditorState,
+    selection: Option<VisualSelection>,
+    was_inserting: bool,
+) {
+    let kind = ed_state.mode.kind();
+    if let Some(visual) = selection {
+        if let Some(buffer) = ed_state.buffers.get_mut(visual.buffer_idx) {
+            let (start, end) = (buffer.anchors.get(visual.start), buffer.anchors.get(visual.end));
+            buffer.anchors.remove(visual.start);
+            buffer.anchors.remove(visual.end);
+            match (start, end) {
+                (Some(start), Some(end)) if kind != crate::editor_state::ModeKind::Visual => {
+                    set_local(buffer, '<', start);
+                    set_local(buffer, '>', end);
+                    buffer.last_visual = Some(crate::visual::LastVisual {
+                        kind: visual.kind,
+                        backwards: visual.selection.head < visual.selection.anchor,
+                        desired_column: visual.selection.desired_column,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+    if was_inserting && kind != crate::editor_state::ModeKind::Insert {
+        let buffer = ed_state.get_buffer_mut();
+        let offset = buffer.cursor();
+        set_local(buffer, '^', offset);
+    }
+}
+
+/// `:marks` lists the marks (only the ones in `filter` if it is not empty) with their line, column
+/// and the text of the line (or the file for marks in other buffers)
+pub(crate) fn list(ed_state: &mut EditorState, filter: &str) -> Result<(), MarkError> {
+    let mut lines = vec!["mark line  col file/text".to_string()];
+    let marks = std::iter::once('\'')
+        .chain('a'..='z')
+        .chain('A'..='Z')
+        .chain("^.<>".chars())
+        .filter(|&mark| filter.is_empty() || filter.contains(mark));
+    for mark in marks {
+        // global marks of files that are not open are not opened just to list them
+        if let Some(global_mark) = ed_state.global_marks.get(&mark) {
+            if !is_open(ed_state, global_mark) {
+                let (line, column) = global_mark.position;
+                let file = global_mark
+                    .location
+                    .as_ref()
+                    .map_or(String::new(), |location| location.display().to_string());
+                lines.push(format!(" {} {:>6} {:>4} {}", mark, line + 1, column, file));
+                continue;
+            }
+        }
+        let Ok((buffer_idx, offset)) = mark_position(ed_state, mark) else {
+            continue;
+        };
+        let buffer = &ed_state.buffers[buffer_idx];
+        let (line, column) = buffer.position(offset);
+        let text = if buffer_idx == ed_state.buffer_idx {
+            let start = buffer.line_start(offset);
+            buffer.content.slice(start..buffer.line_end(offset)).iter().collect()
+        } else {
+            buffer.name.clone()
+        };
+        lines.push(format!(" {} {:>6} {:>4} {}", mark, line + 1, column, text));
+    }
+    writeln!(ed_state.command_line, "{}", lines.join("\n"))
+        .expect("Fatal: Could not write to command line buffer");
+    Ok(())
+}
+
+/// `:delmarks a-dX <` deletes the given marks (ranges like `a-d` included), `:delmarks!` all
+/// lowercase marks of the current buffer
+pub(crate) fn delete(ed_state: &mut EditorState, marks: &str, bang: bool) -> Result<(), MarkError> {
+    let mut to_delete = Vec::new();
+    if bang {
+        to_delete.extend('a'..='z');
+    }
+    let chars = marks.chars().filter(|c| !c.is_whitespace()).collect::<Vec<_>>();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i..] {
+            [from, '-', to, ..] if from.is_ascii_alphabetic() && from <= to => {
+                if from.is_ascii_lowercase() != to.is_ascii_lowercase() {
+                    return Err(MarkError::InvalidMarkError(to));
+                }
+                to_delete.extend(from..=to);
+                i += 3;
+            }
+            [mark, ..] => {
+                to_delete.push(mark);
+                i += 1;
+            }
+            [] => unreachable!(),
+        }
+    }
+    // nothing is deleted if one of the marks is invalid
+    let valid = |mark: char| matches!(mark, 'a'..='z' | 'A'..='Z' | '<' | '>' | '^');
+    if let Some(&mark) = to_delete.iter().find(|&&mark| !valid(mark)) {
+        return Err(MarkError::InvalidMarkError(mark));
+    }
+    for mark in to_delete {
+        if mark.is_ascii_uppercase() {
+            if let Some(global_mark) = ed_state.global_marks.remove(&mark) {
+                remove_anchor(ed_state, &global_mark);
+            }
+        } else {
+            let buffer = ed_state.get_buffer_mut();
+            if let Some(anchor) = buffer.marks.remove(&mark) {
+                buffer.anchors.remove(anchor);
+            }
+        }
+    }
+    Ok(())
+}
@@ -6,26 +6,56 @@ use std::rc::Rc;
 pub(crate) enum MotionInterpreterError {
     #[error("Unknown motion: {0:?}")]
     UnknownMotionError(MotionComponentBuffer),
-    // TODO when async execution is supported, the role of this
-    // will change from meaning a previous command has not been
-    // executed to meaning a previous command has not been scheduled
-    // for execution yet
+    // the previous motion has not been scheduled for execution yet (motions that return
+    // `EditorStateUpdate::Spawn` are scheduled, not awaited, so this does not wait on them)
     #[error("Pending motion")]
     PendingMotionError,
+    #[error("Recursive mapping: {0:?}")]
+    RecursiveMappingError(MotionComponentBuffer),
 }
 
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum MotionTreeError {
     #[error("Empty motion")]
     EmptyMotionError,
-    #[error("Motion already exists")]
-    MotionAlreadyExistsError,
+    /// keys run as soon as they match, so nothing can start with keys that already do something
+    /// (eg `:nmap jk <Esc>` while `j` is a motion)
+    #[error(
+        "{motion} would turn the existing mapping {leaf} into a prefix, mappings can not start \
+         with keys that are mapped already"
+    )]
+    LeafBecomesPrefixError {
+        leaf: MotionComponentBuffer,
+        motion: MotionComponentBuffer,
+    },
+    #[error("{prefix} would shadow the existing mappings {}", format_motion_list(.shadowed))]
+    ShadowsPrefixError {
+        prefix: MotionComponentBuffer,
+        shadowed: Vec<MotionComponentBuffer>,
+    },
+    #[error("No such mapping")]
+    NoSuchMappingError,
+}
+
+fn format_motion_list(motions: &[MotionComponentBuffer]) -> String {
+    motions
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct MotionComponentBuffer(Vec<MotionAtom>);
+pub(crate) struct MotionComponentBuffer(pub(crate) Vec<MotionAtom>);
+
+impl std::fmt::Display for MotionComponentBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", crate::key_notation::format(&self.0))
+    }
+}
 
-// TODO use async; for example:
+// motion functions that need to do slow work return `EditorStateUpdate::Spawn` with a task,
+// which the scheduler drives between input events
 #[derive(Clone)]
 pub(crate) struct MotionFunction(pub(crate) Rc<dyn Fn(&EditorState) -> EditorStateUpdate>);
 
@@ -35,6 +65,50 @@ pub(crate) struct MotionAtom {
     pub(crate) modifiers: crossterm::event::KeyModifiers,
 }
 
+impl MotionAtom {
+    pub(crate) fn new(
+        code: crossterm::event::KeyCode,
+        modifiers: crossterm::event::KeyModifiers,
+    ) -> Self {
+        Self { code, modifiers }
+    }
+
+    pub(crate) fn from_key_event(key_event: crossterm::event::KeyEvent) -> Self {
+        let crossterm::event::KeyEvent {
+            code, modifiers, ..
+        } = key_event;
+        // the case of a char already encodes shift, so strip it to make `Y` in a mapping match
+        // the `Y` the terminal reports (which comes with the SHIFT modifier set), the same goes for
+        // a back tab. With control the case is dropped, like most terminals do (`<C-A>` is `<C-a>`)
+        let (code, modifiers) = match code {
+            crossterm::event::KeyCode::Char(c)
+                if modifiers.contains(crossterm::event::KeyModifiers::CONTROL) =>
+            {
+                (
+                    crossterm::event::KeyCode::Char(c.to_ascii_lowercase()),
+                    modifiers.difference(crossterm::event::KeyModifiers::SHIFT),
+                )
+            }
+            crossterm::event::KeyCode::Char(_) | crossterm::event::KeyCode::BackTab => {
+                (code, modifiers.difference(crossterm::event::KeyModifiers::SHIFT))
+            }
+            _ => (code, modifiers),
+        };
+        Self { code, modifiers }
+    }
+}
+
+/// A key-to-key mapping. When the interpreter reaches a remap, its keys are fed back into the
+/// interpreter as if the user typed them.
+#[derive(Clone)]
+pub(crate) struct Remap {
+    pub(crate) keys: Vec<MotionAtom>,
+    /// whether mappings are applied to `keys` again (`map`) or not (`noremap`)
+    pub(crate) recursive: bool,
+    /// the builtin motion this remap replaced (if any), used when remapping is disabled
+    pub(crate) shadowed: Option<MotionFunction>,
+}
+
 // so other modules can use it without having hardcoded copies
 // this ideally avoids refactoring pain
 pub(crate) type MotionTreeMap = HashMap<MotionAtom, MotionTree>;
@@ -42,6 +116,7 @@ pub(crate) type MotionTreeMap = HashMap<MotionAtom, MotionTree>;
 pub(crate) enum MotionTree {
     Tree(MotionTreeMap),
     Atom(MotionFunction),
+    Remap(Remap),
 }
 
 impl MotionTree {
@@ -49,10 +124,137 @@ impl MotionTree {
         &mut self,
         motion: MotionComponentBuffer,
         motion_function: MotionFunction,
+    ) -> Result<(), MotionTreeError> {
+        self.insert_leaf(motion, MotionTree::Atom(motion_function))
+    }
+
+    /// Inserts a key-to-key mapping. If a builtin motion is bound to the same keys, it is kept
+    /// around so non-recursive mappings (and `unmap`) can still get to it.
+    pub(crate) fn insert_remap(
+        &mut self,
+        motion: MotionComponentBuffer,
+        mut remap: Remap,
+    ) -> Result<(), MotionTreeError> {
+        remap.shadowed = match self.get(&motion.0) {
+            Some(MotionTree::Atom(motion_function)) => Some(motion_function.clone()),
+            Some(MotionTree::Remap(old_remap)) => old_remap.shadowed.clone(),
+            _ => None,
+        };
+        self.insert_leaf(motion, MotionTree::Remap(remap))
+    }
+
+    /// Removes a key-to-key mapping, restoring the builtin motion it shadowed (if any)
+    pub(crate) fn unmap(&mut self, motion: &MotionComponentBuffer) -> Result<(), MotionTreeError> {
+        let shadowed = match self.get(&motion.0) {
+            Some(MotionTree::Remap(remap)) => remap.shadowed.clone(),
+            _ => return Err(MotionTreeError::NoSuchMappingError),
+        };
+        self.remove(&motion.0)?;
+        if let Some(motion_function) = shadowed {
+            self.insert(motion.clone(), motion_function)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the node (a leaf or a subtree of longer motions) reached by following `motion`
+    pub(crate) fn get(&self, motion: &[MotionAtom]) -> Option<&MotionTree> {
+        let mut possible_motions = self;
+        for m in motion {
+            possible_motions = match possible_motions {
+                Self::Tree(motion_tree) => motion_tree.get(m)?,
+                _ => return None,
+            };
+        }
+        Some(possible_motions)
+    }
+
+    /// Removes the node reached by following `motion` (including everything below it) and prunes
+    /// subtrees that became empty because of that
+    pub(crate) fn remove(&mut self, motion: &[MotionAtom]) -> Result<MotionTree, MotionTreeError> {
+        fn remove_from(
+            motion_tree: &mut MotionTreeMap,
+            motion: &[MotionAtom],
+        ) -> Option<MotionTree> {
+            let (first, rest) = motion.split_first()?;
+            if rest.is_empty() {
+                return motion_tree.remove(first);
+            }
+            let removed = match motion_tree.get_mut(first)? {
+                MotionTree::Tree(subtree) => remove_from(subtree, rest)?,
+                _ => return None,
+            };
+            if matches!(motion_tree.get(first), Some(MotionTree::Tree(subtree)) if subtree.is_empty())
+            {
+                motion_tree.remove(first);
+            }
+            Some(removed)
+        }
+
+        if motion.is_empty() {
+            return Err(MotionTreeError::EmptyMotionError);
+        }
+        match self {
+            Self::Tree(motion_tree) => {
+                remove_from(motion_tree, motion).ok_or(MotionTreeError::NoSuchMappingError)
+            }
+            Self::Atom(_) | Self::Remap(_) => Err(MotionTreeError::NoSuchMappingError),
+        }
+    }
+
+    /// Iterates over all leaves (motions and mappings) together with the full key path leading
+    /// to them. The order is unspecified.
+    pub(crate) fn iter(&self) -> MotionTreeIter<'_> {
+        MotionTreeIter {
+            stack: vec![(Vec::new(), self)],
+        }
+    }
+
+    /// Checks that a motion or mapping can be inserted at `motion` without conflicting with the
+    /// ones already in the tree
+    pub(crate) fn check_insert(
+        &self,
+        motion: &MotionComponentBuffer,
     ) -> Result<(), MotionTreeError> {
         if motion.0.is_empty() {
             return Err(MotionTreeError::EmptyMotionError);
         }
+        for prefix_len in 1..motion.0.len() {
+            if let Some(MotionTree::Atom(_) | MotionTree::Remap(_)) =
+                self.get(&motion.0[..prefix_len])
+            {
+                return Err(MotionTreeError::LeafBecomesPrefixError {
+                    leaf: MotionComponentBuffer(motion.0[..prefix_len].to_vec()),
+                    motion: motion.clone(),
+                });
+            }
+        }
+        if let Some(subtree @ MotionTree::Tree(motion_subtree)) = self.get(&motion.0) {
+            if !motion_subtree.is_empty() {
+                let mut shadowed = subtree
+                    .iter()
+                    .map(|(path, _)| {
+                        let mut full_path = motion.0.clone();
+                        full_path.extend(path.0);
+                        MotionComponentBuffer(full_path)
+                    })
+                    .collect::<Vec<_>>();
+                shadowed.sort_by_key(|m| m.to_string());
+                return Err(MotionTreeError::ShadowsPrefixError {
+                    prefix: motion.clone(),
+                    shadowed,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_leaf(
+        &mut self,
+        motion: MotionComponentBuffer,
+        leaf: MotionTree,
+    ) -> Result<(), MotionTreeError> {
+        // check for conflicts up front so a failed insert leaves the tree untouched
+        self.check_insert(&motion)?;
         match self {
             Self::Tree(motion_tree) => {
                 let mut possible_motions = motion_tree;
@@ -67,19 +269,42 @@ impl MotionTree {
                         .or_insert_with(MotionTree::default);
                     possible_motions = match subtree {
                         MotionTree::Tree(subtree) => subtree,
-                        MotionTree::Atom(_) => {
-                            return Err(MotionTreeError::MotionAlreadyExistsError)
-                        }
+                        // conflicts were ruled out above
+                        MotionTree::Atom(_) | MotionTree::Remap(_) => unreachable!(),
                     };
                 }
-                possible_motions.insert(final_motion, MotionTree::Atom(motion_function));
+                possible_motions.insert(final_motion, leaf);
                 Ok(())
             }
-            Self::Atom(_) => Err(MotionTreeError::EmptyMotionError),
+            Self::Atom(_) | Self::Remap(_) => Err(MotionTreeError::EmptyMotionError),
         }
     }
 }
 
+pub(crate) struct MotionTreeIter<'a> {
+    stack: Vec<(Vec<MotionAtom>, &'a MotionTree)>,
+}
+
+impl<'a> Iterator for MotionTreeIter<'a> {
+    type Item = (MotionComponentBuffer, &'a MotionTree);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((path, node)) = self.stack.pop() {
+            match node {
+                MotionTree::Tree(motion_tree) => {
+                    for (m, subtree) in motion_tree {
+                        let mut subpath = path.clone();
+                        subpath.push(*m);
+                        self.stack.push((subpath, subtree));
+                    }
+                }
+                leaf => return Some((MotionComponentBuffer(path), leaf)),
+            }
+        }
+        None
+    }
+}
+
 impl Default for MotionTree {
     fn default() -> Self {
         Self::Tree(HashMap::new())
@@ -103,6 +328,8 @@ impl Default for MotionTree {
 pub(crate) enum MotionInterpreterState {
     Pending(MotionComponentBuffer),
     Done(MotionFunction),
+    /// the motion resolved to a key-to-key mapping whose keys still have to be fed back
+    Remapped(Remap),
 }
 
 impl MotionInterpreterState {
@@ -110,10 +337,39 @@ impl MotionInterpreterState {
         Self::Pending(MotionComponentBuffer(Vec::new()))
     }
 
+    /// Whether no keys of a motion have been typed yet
+    pub(crate) fn is_idle(&self) -> bool {
+        matches!(self, Self::Pending(motion_component_buffer) if motion_component_buffer.0.is_empty())
+    }
+
+    /// Resolves a leaf of the motion tree. With `remap` disabled, mappings resolve to the builtin
+    /// motion they shadow (if any), like vim's `noremap`.
+    fn resolve(
+        leaf: &MotionTree,
+        motion_component_buffer: MotionComponentBuffer,
+        remap: bool,
+    ) -> Result<Self, MotionInterpreterError> {
+        match leaf {
+            MotionTree::Tree(_) => Ok(Self::Pending(motion_component_buffer)),
+            MotionTree::Atom(motion_function) => {
+                Ok(Self::Done(MotionFunction(Rc::clone(&motion_function.0))))
+            }
+            MotionTree::Remap(remap_leaf) if remap => Ok(Self::Remapped(remap_leaf.clone())),
+            MotionTree::Remap(Remap {
+                shadowed: Some(motion_function),
+                ..
+            }) => Ok(Self::Done(MotionFunction(Rc::clone(&motion_function.0)))),
+            MotionTree::Remap(_) => Err(MotionInterpreterError::UnknownMotionError(
+                motion_component_buffer,
+            )),
+        }
+    }
+
     pub(crate) fn update(
         self,
         motion_tree: &MotionTree,
         next: MotionAtom,
+        remap: bool,
     ) -> Result<Self, MotionInterpreterError> {
         match self {
             MotionInterpreterState::Pending(mut motion_component_buffer) => {
@@ -132,21 +388,82 @@ impl MotionInterpreterState {
                                 }
                             }
                         }
-                        MotionTree::Atom(motion_function) => {
-                            return Ok(Self::Done(MotionFunction(Rc::clone(&motion_function.0))))
-                        }
-                    }
-                }
-                match possible_motions {
-                    MotionTree::Tree(_) => {
-                        Ok(Self::Pending(motion_component_buffer))
-                    }
-                    MotionTree::Atom(motion_function) => {
-                        Ok(Self::Done(MotionFunction(Rc::clone(&motion_function.0))))
+                        leaf => return Self::resolve(leaf, motion_component_buffer, remap),
                     }
                 }
+                Self::resolve(possible_motions, motion_component_buffer, remap)
+            }
+            MotionInterpreterState::Done(_) | MotionInterpreterState::Remapped(_) => {
+                Err(MotionInterpreterError::PendingMotionError)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(notation: &str) -> MotionComponentBuffer {
+        MotionComponentBuffer(crate::key_notation::parse(notation).unwrap())
+    }
+
+    fn noop() -> MotionFunction {
+        MotionFunction(Rc::new(|_| EditorStateUpdate::None))
+    }
+
+    #[test]
+    fn test_get_and_iter_report_full_paths() {
+        let mut motion_tree = MotionTree::default();
+        motion_tree.insert(keys("gg"), noop()).unwrap();
+        motion_tree.insert(keys("gu"), noop()).unwrap();
+        motion_tree.insert(keys("x"), noop()).unwrap();
+        assert!(matches!(motion_tree.get(&keys("g").0), Some(MotionTree::Tree(_))));
+        assert!(matches!(motion_tree.get(&keys("gg").0), Some(MotionTree::Atom(_))));
+        assert!(motion_tree.get(&keys("gx").0).is_none());
+        let mut paths = motion_tree
+            .iter()
+            .map(|(motion, _)| motion.to_string())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, vec!["gg", "gu", "x"]);
+    }
+    #[test]
+    fn test_remove_prunes_empty_subtrees() {
+        let mut motion_tree = MotionTree::default();
+        motion_tree.insert(keys("gg"), noop()).unwrap();
+        assert!(motion_tree.remove(&keys("gg").0).is_ok());
+        assert!(motion_tree.get(&keys("g").0).is_none());
+        assert!(matches!(
+            motion_tree.remove(&keys("gg").0),
+            Err(MotionTreeError::NoSuchMappingError)
+        ));
+    }
+    #[test]
+    fn test_insert_reports_shadowed_prefix() {
+        let mut motion_tree = MotionTree::default();
+        motion_tree.insert(keys("gg"), noop()).unwrap();
+        motion_tree.insert(keys("gu"), noop()).unwrap();
+        match motion_tree.insert(keys("g"), noop()) {
+            Err(MotionTreeError::ShadowsPrefixError { prefix, shadowed }) => {
+                assert_eq!(prefix.to_string(), "g");
+                assert_eq!(format_motion_list(&shadowed), "gg, gu");
+            }
+            _ => panic!("expected a shadowed prefix conflict"),
+        }
+        // the failed insert must not have touched the tree
+        assert_eq!(motion_tree.iter().count(), 2);
+    }
+    #[test]
+    fn test_insert_reports_leaf_becoming_prefix() {
+        let mut motion_tree = MotionTree::default();
+        motion_tree.insert(keys("d"), noop()).unwrap();
+        match motion_tree.insert(keys("dd"), noop()) {
+            Err(MotionTreeError::LeafBecomesPrefixError { leaf, motion }) => {
+                assert_eq!(leaf.to_string(), "d");
+                assert_eq!(motion.to_string(), "dd");
             }
-            MotionInterpreterState::Done(_) => Err(MotionInterpreterError::PendingMotionError),
+            _ => panic!("expected a leaf becoming prefix conflict"),
         }
     }
 }
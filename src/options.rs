@@ -0,0 +1,185 @@
+use crate::editor_state::EditorGlobals;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum OptionError {
+    #[error("Unknown option: {0}")]
+    UnknownOptionError(String),
+    #[error("Invalid value for {name}: {value}")]
+    InvalidValueError { name: String, value: String },
+}
+
+/// A parsed option assignment, so the value can be checked before it is applied (which is
+/// needed to apply transactions atomically)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum EditorOption {
+    TabSize(usize),
+    ShiftWidth(usize),
+    SoftTabStop(usize),
+    ExpandTab(bool),
+    AutoIndent(bool),
+    SmartIndent(bool),
+    AutoPairs(bool),
+    Pairs(Vec<(String, String)>),
+    MaxMapDepth(usize),
+    UndoDir(String),
+    MaxUndoSize(usize),
+    Clipboard(Vec<crate::clipboard::Provider>),
+    ClipCopy(String),
+    ClipPaste(String),
+    Complete(Vec<crate::completion::Source>),
+    Dictionary(String),
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, OptionError> {
+    value
+        .parse::<usize>()
+        .map_err(|_| OptionError::InvalidValueError {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+}
+
+/// `filetype:pairs` separated by commas, like `rust:()[]{},python:()""`, every two characters of
+/// the pairs are an opener and its closer
+fn parse_pairs(value: &str) -> Option<Vec<(String, String)>> {
+    if value.is_empty() {
+        return Some(Vec::new());
+    }
+    value
+        .split(',')
+        .map(|entry| {
+            let (filetype, pairs) = entry.split_once(':')?;
+            (pairs.chars().count() % 2 == 0).then(|| (filetype.to_string(), pairs.to_string()))
+        })
+        .collect()
+}
+
+pub(crate) fn parse(name: &str, value: &str) -> Result<EditorOption, OptionError> {
+    match name {
+        "tabstop" | "ts" | "tabsize" | "tsz" => {
+            Ok(EditorOption::TabSize(parse_number(name, value)?))
+        }
+        "shiftwidth" | "sw" => Ok(EditorOption::ShiftWidth(parse_number(name, value)?)),
+        "softtabstop" | "sts" => Ok(EditorOption::SoftTabStop(parse_number(name, value)?)),
+        // switched on and off with `:set name` and `:set noname`, see `parse_flag`
+        "pairs" => parse_pairs(value)
+            .map(EditorOption::Pairs)
+            .ok_or_else(|| OptionError::InvalidValueError {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+        "expandtab" | "et" | "autoindent" | "ai" | "smartindent" | "si" | "autopairs" | "ap" => {
+            Err(OptionError::InvalidValueError {
+                name: name.to_string(),
+                value: value.to_string(),
+            })
+        }
+        "maxmapdepth" | "mmd" => Ok(EditorOption::MaxMapDepth(parse_number(name, value)?)),
+        "undodir" | "udir" => Ok(EditorOption::UndoDir(value.to_string())),
+        "maxundosize" | "mus" => Ok(EditorOption::MaxUndoSize(parse_number(name, value)?)),
+        "clipboard" | "cb" => match crate::clipboard::parse_providers(value) {
+            Some(providers) => Ok(EditorOption::Clipboard(providers)),
+            None => Err(OptionError::InvalidValueError {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+        },
+        "clipcopy" => Ok(EditorOption::ClipCopy(value.to_string())),
+        "clippaste" => Ok(EditorOption::ClipPaste(value.to_string())),
+        "complete" | "cpt" => match crate::completion::parse_sources(value) {
+            Some(sources) => Ok(EditorOption::Complete(sources)),
+            None => Err(OptionError::InvalidValueError {
+                name: name.to_string(),
+                value: value.to_string(),
+            }),
+        },
+        "dictionary" | "dict" => Ok(EditorOption::Dictionary(value.to_string())),
+        _ => Err(OptionError::UnknownOptionError(name.to_string())),
+    }
+}
+
+/// The boolean option `name`, set to what `value` makes of its current value
+fn flag(
+    editor_globals: &EditorGlobals,
+    name: &str,
+    value: impl FnOnce(bool) -> bool,
+) -> Option<EditorOption> {
+    match name {
+        "expandtab" | "et" => Some(EditorOption::ExpandTab(value(editor_globals.expand_tab))),
+        "autoindent" | "ai" => Some(EditorOption::AutoIndent(value(editor_globals.auto_indent))),
+        "smartindent" | "si" => {
+            Some(EditorOption::SmartIndent(value(editor_globals.smart_indent)))
+        }
+        "autopairs" | "ap" => Some(EditorOption::AutoPairs(value(editor_globals.auto_pairs))),
+        _ => None,
+    }
+}
+
+/// `:set name` switches a boolean option on, `:set noname` off and `:set invname` or
+/// `:set name!` toggles it. `None` if `arg` is none of these.
+pub(crate) fn parse_flag(editor_globals: &EditorGlobals, arg: &str) -> Option<EditorOption> {
+    if let Some(name) = arg.strip_suffix('!').or_else(|| arg.strip_prefix("inv")) {
+        return flag(editor_globals, name, |current| !current);
+    }
+    flag(editor_globals, arg, |_| true)
+        .or_else(|| flag(editor_globals, arg.strip_prefix("no")?, |_| false))
+}
+
+pub(crate) fn apply(editor_globals: &mut EditorGlobals, option: EditorOption) {
+    match option {
+        EditorOption::TabSize(tab_size) => editor_globals.tab_size = tab_size,
+        EditorOption::ShiftWidth(shift_width) => editor_globals.shift_width = shift_width,
+        EditorOption::SoftTabStop(soft_tab_stop) => editor_globals.soft_tab_stop = soft_tab_stop,
+        EditorOption::ExpandTab(expand_tab) => editor_globals.expand_tab = expand_tab,
+        EditorOption::AutoIndent(auto_indent) => editor_globals.auto_indent = auto_indent,
+        EditorOption::SmartIndent(smart_indent) => editor_globals.smart_indent = smart_indent,
+        EditorOption::AutoPairs(auto_pairs) => editor_globals.auto_pairs = auto_pairs,
+        EditorOption::Pairs(pairs) => editor_globals.pairs = pairs,
+        EditorOption::MaxMapDepth(max_map_depth) => editor_globals.max_map_depth = max_map_depth,
+        EditorOption::UndoDir(undo_dir) => editor_globals.undo_dir = undo_dir,
+        EditorOption::MaxUndoSize(max_undo_size) => editor_globals.max_undo_size = max_undo_size,
+        EditorOption::Clipboard(providers) => editor_globals.clipboard_providers = providers,
+        EditorOption::ClipCopy(command) => editor_globals.clip_copy = command,
+        EditorOption::ClipPaste(command) => editor_globals.clip_paste = command,
+        EditorOption::Complete(sources) => editor_globals.complete = sources,
+        EditorOption::Dictionary(paths) => editor_globals.dictionary = paths,
+    }
+}
+
+/// Formats the current value of an option as `name=value`, boolean ones as `name` or `noname`
+pub(crate) fn get(editor_globals: &EditorGlobals, name: &str) -> Result<String, OptionError> {
+    let flag = |value: bool, name: &str| format!("{}{}", if value { "" } else { "no" }, name);
+    match name {
+        "tabstop" | "ts" | "tabsize" | "tsz" => Ok(format!("tabstop={}", editor_globals.tab_size)),
+        "shiftwidth" | "sw" => Ok(format!("shiftwidth={}", editor_globals.shift_width)),
+        "softtabstop" | "sts" => Ok(format!("softtabstop={}", editor_globals.soft_tab_stop)),
+        "expandtab" | "et" => Ok(flag(editor_globals.expand_tab, "expandtab")),
+        "autoindent" | "ai" => Ok(flag(editor_globals.auto_indent, "autoindent")),
+        "smartindent" | "si" => Ok(flag(editor_globals.smart_indent, "smartindent")),
+        "autopairs" | "ap" => Ok(flag(editor_globals.auto_pairs, "autopairs")),
+        "pairs" => Ok(format!(
+            "pairs={}",
+            editor_globals
+                .pairs
+                .iter()
+                .map(|(filetype, pairs)| format!("{filetype}:{pairs}"))
+                .collect::<Vec<_>>()
+                .join(",")
+        )),
+        "maxmapdepth" | "mmd" => Ok(format!("maxmapdepth={}", editor_globals.max_map_depth)),
+        "undodir" | "udir" => Ok(format!("undodir={}", editor_globals.undo_dir)),
+        "maxundosize" | "mus" => Ok(format!("maxundosize={}", editor_globals.max_undo_size)),
+        "clipboard" | "cb" => Ok(format!(
+            "clipboard={}",
+            crate::clipboard::format_providers(&editor_globals.clipboard_providers)
+        )),
+        "clipcopy" => Ok(format!("clipcopy={}", editor_globals.clip_copy)),
+        "clippaste" => Ok(format!("clippaste={}", editor_globals.clip_paste)),
+        "complete" | "cpt" => Ok(format!(
+            "complete={}",
+            crate::completion::format_sources(&editor_globals.complete)
+        )),
+        "dictionary" | "dict" => Ok(format!("dictionary={}", editor_globals.dictionary)),
+        _ => Err(OptionError::UnknownOptionError(name.to_string())),
+    }
+}
@@ -0,0 +1,671 @@
+use crate::editor_state::{EditorState, EditorStateUpdate};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Registers that are filled by the editor itself: the last inserted text, the current file name,
+/// the last command line and the last search pattern
+pub(crate) const READ_ONLY_REGISTERS: &str = ".%:/";
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RegisterError {
+    #[error("Invalid register name: {0}")]
+    InvalidRegisterError(char),
+    #[error("Register {0} is read-only")]
+    ReadOnlyRegisterError(char),
+    #[error("Nothing in register {0}")]
+    EmptyRegisterError(char),
+}
+
+/// How the text of a register is put: into the line (charwise), as whole lines (linewise, the text
+/// ends with a newline) or as a rectangle with one line of the text per screen line (blockwise)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum RegisterKind {
+    #[default]
+    Charwise,
+    Linewise,
+    Blockwise,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Register {
+    pub(crate) text: String,
+    pub(crate) kind: RegisterKind,
+}
+
+impl Register {
+    pub(crate) fn new(text: String, kind: RegisterKind) -> Self {
+        Self { text, kind }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Registers {
+    registers: HashMap<char, Register>,
+    /// the text typed in the running insert session, it becomes `".` when the session ends
+    insertion: String,
+}
+
+/// Checks that a yank or delete can write to register `name` (`None` is the unnamed register)
+pub(crate) fn check_writable(name: Option<char>) -> Result<(), RegisterError> {
+    match name {
+        None | Some('a'..='z' | 'A'..='Z' | '0'..='9' | '"' | '-' | '_' | '+' | '*') => Ok(()),
+        Some(name) if READ_ONLY_REGISTERS.contains(name) => {
+            Err(RegisterError::ReadOnlyRegisterError(name))
+        }
+        Some(name) => Err(RegisterError::InvalidRegisterError(name)),
+    }
+}
+
+impl Registers {
+    /// Returns the register `name`, uppercase names read the lowercase register
+    pub(crate) fn get(&self, name: char) -> Option<&Register> {
+        self.registers.get(&name.to_ascii_lowercase())
+    }
+
+    /// Sets the register `name`, uppercase names append to the lowercase register
+    pub(crate) fn set(&mut self, name: char, text: String) {
+        self.write(name, Register::new(text, RegisterKind::Charwise));
+    }
+
+    fn write(&mut self, name: char, register: Register) {
+        if !name.is_ascii_uppercase() {
+            self.registers.insert(name, register);
+            return;
+        }
+        let existing = self
+            .registers
+            .entry(name.to_ascii_lowercase())
+            .or_default();
+        // appending lines to text makes the register linewise, like in vim
+        if register.kind == RegisterKind::Linewise {
+            if !existing.text.is_empty() && !existing.text.ends_with('\n') {
+                existing.text.push('\n');
+            }
+            existing.kind = RegisterKind::Linewise;
+        }
+        existing.text.push_str(&register.text);
+    }
+
+    /// Writes `register` to `name` and lets the unnamed register point at the result
+    fn write_named(&mut self, name: char, register: Register) {
+        self.write(name, register);
+        let written = self.get(name).cloned().unwrap_or_default();
+        self.registers.insert('"', written);
+    }
+
+    /// Stores yanked text: in `name` if one was given, otherwise in `"0`
+    pub(crate) fn yank(&mut self, name: Option<char>, register: Register) {
+        match name {
+            Some('_') => {}
+            None | Some('"') => self.write_named('0', register),
+            Some(name) => self.write_named(name, register),
+        }
+    }
+
+    /// Stores deleted text: deletes of whole lines or more shift `"1`-`"8` into `"2`-`"9` and go
+    /// into `"1`, smaller ones without a register name go into `"-`
+    pub(crate) fn delete(&mut self, name: Option<char>, register: Register) {
+        if name == Some('_') {
+            return;
+        }
+        let name = name.filter(|&name| name != '"');
+        if register.kind == RegisterKind::Linewise || register.text.contains('\n') {
+            for number in (1..9).rev() {
+                let digit = |number| char::from_digit(number, 10).expect("a single digit");
+                if let Some(shifted) = self.registers.remove(&digit(number)) {
+                    self.registers.insert(digit(number + 1), shifted);
+                }
+            }
+            self.write_named('1', register.clone());
+        } else if name.is_none() {
+            self.write_named('-', register.clone());
+        }
+        if let Some(name) = name {
+            self.write_named(name, register);
+        }
+    }
+
+    /// Sets one of the read-only registers
+    pub(crate) fn set_read_only(&mut self, name: char, text: String) {
+        self.registers
+            .insert(name, Register::new(text, RegisterKind::Charwise));
+    }
+
+    /// Stores the text read from the system clipboard in `name` (`"+` or `"*`)
+    pub(crate) fn set_clipboard(&mut self, name: char, text: String) {
+        // text copied with a trailing newline was most likely whole lines
+        let kind = if text.ends_with('\n') {
+            RegisterKind::Linewise
+        } else {
+            RegisterKind::Charwise
+        };
+        self.registers.insert(name, Register::new(text, kind));
+    }
+
+    pub(crate) fn insert_started(&mut self) {
+        self.insertion.clear();
+    }
+
+    /// Keeps track of what is typed in an insert session for `".`
+    pub(crate) fn inserted(&mut self, text: &str) {
+        self.insertion.push_str(text);
+    }
+
+    pub(crate) fn insert_deleted(&mut self, count: usize) {
+        for _ in 0..count {
+            self.insertion.pop();
+        }
+    }
+
+    pub(crate) fn insert_finished(&mut self) {
+        let text = std::mem::take(&mut self.insertion);
+        self.set_read_only('.', text);
+    }
+}
+
+/// The content of register `name`, including the ones that are computed when they are read. The
+/// clipboard registers hold what was yanked into them or read from the clipboard last, see
+/// `clipboard::paste` for reading the clipboard first.
+pub(crate) fn read(ed_state: &EditorState, name: char) -> Result<Register, RegisterError> {
+    let register = match name {
+        '%' => Some(Register::new(
+            ed_state.get_buffer().name.clone(),
+            RegisterKind::Charwise,
+        )),
+        '_' => None,
+        'a'..='z' | 'A'..='Z' | '0'..='9' | '"' | '-' | '.' | ':' | '/' | '+' | '*' => {
+            ed_state.registers.get(name).cloned()
+        }
+        _ => return Err(RegisterError::InvalidRegisterError(name)),
+    };
+    register
+        .filter(|register| !register.text.is_empty())
+        .ok_or(RegisterError::EmptyRegisterError(name))
+}
+
+/// The range to delete to remove the lines `first..=last` and their text for a linewise register
+fn line_range(
+    buffer: &crate::editor_buffer::Buffer,
+    first: usize,
+    last: usize,
+) -> (std::ops::Range<usize>, String) {
+    let start = buffer.line_offset(first);
+    let end = buffer.line_end(buffer.line_offset(last));
+    let mut text = buffer.content.slice(start..end).into_iter().collect::<String>();
+    text.push('\n');
+    // the newline behind the last line goes with it, or the one in front if it is the last line
+    let range = if end < buffer.len() {
+        start..end + 1
+    } else {
+        start.saturating_sub(1)..end
+    };
+    (range, text)
+}
+
+/// `yy` yanks `count` lines starting at the line of the primary cursor
+pub(crate) fn yank_lines(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let line = buffer.cursor_position().0;
+    let last = (line + ed_state.count_or_one() - 1).min(buffer.line_count() - 1);
+    EditorStateUpdate::Transaction(yank_line_span(ed_state, line, last))
+}
+
+/// Yanks the lines `first..=last`
+fn yank_line_span(
+    ed_state: &EditorState,
+    first: usize,
+    last: usize,
+) -> crate::transaction::Transaction {
+    let (_, text) = line_range(ed_state.get_buffer(), first, last);
+    let mut transaction = crate::transaction::Transaction::new();
+    transaction.yank(
+        ed_state.selected_register,
+        Register::new(text, RegisterKind::Linewise),
+    );
+    transaction
+}
+
+/// Where the cursor goes after the lines from `first` on were deleted (`range` is from
+/// `line_range`), as an offset from before the deletion: the first non-blank of the line that takes
+/// their place (the line in front of them if the last line was deleted)
+fn cursor_after_deleting_lines(
+    buffer: &crate::editor_buffer::Buffer,
+    first: usize,
+    range: &std::ops::Range<usize>,
+) -> usize {
+    let rest = if range.start == buffer.line_offset(first) {
+        range.end
+    } else {
+        buffer.line_start(range.start)
+    };
+    let blanks = buffer
+        .content
+        .iter()
+        .skip(rest)
+        .take_while(|&&c| c == ' ' || c == '\t')
+        .count();
+    rest + blanks
+}
+
+/// `dd` deletes `count` lines starting at the line of the primary cursor
+pub(crate) fn delete_lines(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let line = buffer.cursor_position().0;
+    let last = (line + ed_state.count_or_one() - 1).min(buffer.line_count() - 1);
+    EditorStateUpdate::Transaction(delete_line_span(ed_state, line, last))
+}
+
+/// Deletes the lines `first..=last`, the cursor goes to the first non-blank of the line after them
+fn delete_line_span(
+    ed_state: &EditorState,
+    first: usize,
+    last: usize,
+) -> crate::transaction::Transaction {
+    let buffer = ed_state.get_buffer();
+    let (range, text) = line_range(buffer, first, last);
+    let cursor = cursor_after_deleting_lines(buffer, first, &range);
+    let mut transaction = crate::transaction::Transaction::new();
+    transaction
+        .delete_into(
+            ed_state.selected_register,
+            Register::new(text, RegisterKind::Linewise),
+        )
+        .delete(ed_state.buffer_idx, range);
+    let offset = transaction.shifted(ed_state.buffer_idx, cursor);
+    transaction.cursor(offset, cursor - buffer.line_start(cursor));
+    transaction
+}
+
+/// Yanks (and deletes if `delete`) the characters in `range`, the cursor goes to its start
+fn yank_range(
+    ed_state: &EditorState,
+    range: std::ops::Range<usize>,
+    delete: bool,
+) -> crate::transaction::Transaction {
+    let buffer = ed_state.get_buffer();
+    let text = buffer.content.slice(range.clone()).into_iter().collect::<String>();
+    let register = Register::new(text, RegisterKind::Charwise);
+    let mut transaction = crate::transaction::Transaction::new();
+    let start = range.start;
+    let after = if delete {
+        transaction
+            .delete_into(ed_state.selected_register, register)
+            .delete(ed_state.buffer_idx, range.clone());
+        buffer.char_at(range.end)
+    } else {
+        transaction.yank(ed_state.selected_register, register);
+        buffer.char_at(start)
+    };
+    // in normal mode the cursor can not be behind the end of the line
+    let line_start = buffer.line_start(start);
+    let at_line_end = matches!(after, None | Some('\n')) && start > line_start;
+    let offset = transaction.shifted(ed_state.buffer_idx, start) - at_line_end as usize;
+    transaction.cursor(offset, start - line_start - at_line_end as usize);
+    transaction
+}
+
+/// `y{motion}` and `d{motion}` yank (and delete if `delete`) the text from the primary cursor to
+/// where `text_motion` goes, whole lines if it is linewise. The cursor goes to the start of it.
+pub(crate) fn operator(
+    ed_state: &EditorState,
+    text_motion: &crate::text_motions::TextMotion,
+    delete: bool,
+) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let selection = buffer.selections.primary();
+    let target = (text_motion.function)(
+        buffer,
+        selection.head,
+        selection.desired_column,
+        ed_state.count,
+    )
+    .min(buffer.len());
+    let (start, end) = (selection.head.min(target), selection.head.max(target));
+    let transaction = match text_motion.kind {
+        crate::text_motions::MotionKind::Linewise => {
+            let (first, last) = (buffer.position(start).0, buffer.position(end).0);
+            if delete {
+                delete_line_span(ed_state, first, last)
+            } else {
+                let mut transaction = yank_line_span(ed_state, first, last);
+                // `yk` and `ygg` go up to the first yanked line
+                if start < selection.head {
+                    transaction.cursor(start, buffer.position(start).1);
+                }
+                transaction
+            }
+        }
+        kind => {
+            let end = match kind {
+                // the line break of an empty line is not included
+                crate::text_motions::MotionKind::Inclusive
+                    if !matches!(buffer.char_at(end), None | Some('\n')) =>
+                {
+                    end + 1
+                }
+                // an exclusive motion to the start of a line stops at the end of the line before
+                // (so `dw` on the last word of a line keeps the line break)
+                crate::text_motions::MotionKind::Exclusive
+                    if end > start && end == buffer.line_start(end) =>
+                {
+                    end - 1
+                }
+                _ => end,
+            };
+            if start == end {
+                return EditorStateUpdate::None;
+            }
+            yank_range(ed_state, start..end, delete)
+        }
+    };
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// `x` deletes `count` characters from the primary cursor on, but not the line break
+pub(crate) fn delete_chars(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let start = buffer.selections.primary().head;
+    let end = start
+        .saturating_add(ed_state.count_or_one())
+        .min(buffer.line_end(start));
+    if start == end {
+        return EditorStateUpdate::None;
+    }
+    EditorStateUpdate::Transaction(yank_range(ed_state, start..end, true))
+}
+
+/// The text of the selections of visual mode as it goes into a register (the texts of several
+/// selections are joined with line breaks) and the ranges deleted along with it, selected lines
+/// are deleted with their line breaks
+pub(crate) fn selected_text(ed_state: &EditorState) -> (Register, Vec<std::ops::Range<usize>>) {
+    let buffer = ed_state.get_buffer();
+    let text_of = |range: &std::ops::Range<usize>| {
+        buffer.content.slice(range.clone()).into_iter().collect::<String>()
+    };
+    match crate::visual::kind(ed_state) {
+        crate::visual::VisualKind::Linewise => {
+            let (ranges, texts): (Vec<_>, Vec<_>) = crate::visual::selected_lines(buffer)
+                .into_iter()
+                .map(|(first, last)| line_range(buffer, first, last))
+                .unzip();
+            (Register::new(texts.concat(), RegisterKind::Linewise), ranges)
+        }
+        kind => {
+            let ranges = crate::visual::selected_ranges(ed_state);
+            let text = ranges.iter().map(text_of).collect::<Vec<_>>().join("\n");
+            let register_kind = if kind == crate::visual::VisualKind::Blockwise {
+                RegisterKind::Blockwise
+            } else {
+                RegisterKind::Charwise
+            };
+            (Register::new(text, register_kind), ranges)
+        }
+    }
+}
+
+/// Visual `y`, `d` and `x`: yank (and delete if `delete`) the selected text, then go back to
+/// normal mode with the cursors at the starts of the selections (the top left corner of a block,
+/// the first non-blank of the line that takes the place of deleted lines)
+pub(crate) fn yank_selections(ed_state: &EditorState, delete: bool) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let kind = crate::visual::kind(ed_state);
+    let (register, ranges) = selected_text(ed_state);
+    let mut transaction = crate::transaction::Transaction::new();
+    // the cursors as offsets from before the deletion along with the character they end up on
+    let cursors: Vec<(usize, Option<char>)> = if !delete {
+        transaction.yank(ed_state.selected_register, register);
+        let selections = match kind {
+            crate::visual::VisualKind::Blockwise => vec![buffer.selections.primary()],
+            _ => buffer.selections.iter().copied().collect(),
+        };
+        selections
+            .iter()
+            .map(|selection| {
+                let start = crate::visual::start(buffer, selection, kind);
+                (start, buffer.char_at(start))
+            })
+            .collect()
+    } else {
+        transaction.delete_into(ed_state.selected_register, register);
+        for range in ranges.iter().rev() {
+            transaction.delete(ed_state.buffer_idx, range.clone());
+        }
+        match kind {
+            crate::visual::VisualKind::Linewise => crate::visual::selected_lines(buffer)
+                .iter()
+                .zip(&ranges)
+                .map(|(&(first, _), range)| {
+                    let cursor = cursor_after_deleting_lines(buffer, first, range);
+                    (cursor, buffer.char_at(cursor))
+                })
+                .collect(),
+            crate::visual::VisualKind::Blockwise => {
+                vec![(ranges[0].start, buffer.char_at(ranges[0].end))]
+            }
+            crate::visual::VisualKind::Charwise => ranges
+                .iter()
+                .map(|range| (range.start, buffer.char_at(range.end)))
+                .collect(),
+        }
+    };
+    let cursors = cursors
+        .into_iter()
+        .map(|(offset, c)| {
+            let line_start = buffer.line_start(offset);
+            // in normal mode the cursor can not be behind the end of the line
+            let at_line_end = matches!(c, None | Some('\n')) && offset > line_start;
+            let column = offset - line_start - at_line_end as usize;
+            let offset = transaction.shifted(ed_state.buffer_idx, offset) - at_line_end as usize;
+            crate::selections::Selection::cursor(offset, column)
+        })
+        .collect::<Vec<_>>();
+    let primary = buffer.selections.primary_index().min(cursors.len() - 1);
+    transaction
+        .selections(crate::selections::Selections::new(cursors, primary))
+        .mode(crate::editor_state::EditorMode::Normal);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// The edits putting `register` `count` times at `offset` in descending order (so they can be
+/// applied one after the other) and where the cursor goes, in offsets from before the edits
+fn put_edits(
+    buffer: &crate::editor_buffer::Buffer,
+    offset: usize,
+    register: &Register,
+    count: usize,
+    before: bool,
+) -> (Vec<(usize, String)>, usize) {
+    match register.kind {
+        RegisterKind::Charwise => {
+            let at = if before || buffer.line_end(offset) == offset {
+                offset
+            } else {
+                offset + 1
+            };
+            let text = register.text.repeat(count);
+            let len = text.chars().count();
+            (vec![(at, text)], at + len.saturating_sub(1))
+        }
+        RegisterKind::Linewise => {
+            let text = register.text.repeat(count);
+            if before {
+                let at = buffer.line_start(offset);
+                (vec![(at, text)], at)
+            } else if buffer.line_end(offset) < buffer.len() {
+                let at = buffer.line_end(offset) + 1;
+                (vec![(at, text)], at)
+            } else {
+                // behind the last line, which has no newline to put the lines after
+                let at = buffer.len();
+                let text = format!("\n{}", text.strip_suffix('\n').unwrap_or(&text));
+                (vec![(at, text)], at + 1)
+            }
+        }
+        RegisterKind::Blockwise => {
+            let (first_line, column) = buffer.position(offset);
+            let column = if before || buffer.line_end(offset) == offset {
+                column
+            } else {
+                column + 1
+            };
+            let block = register.text.split('\n').collect::<Vec<_>>();
+            let width = block.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+            let mut edits = Vec::new();
+            for (i, line) in block.iter().enumerate() {
+                let line_number = first_line + i;
+                let mut text = String::new();
+                for repeat in 0..count {
+                    text.push_str(line);
+                    // pad every copy but the last so the copies line up
+                    if repeat + 1 < count {
+                        let padding = width - line.chars().count();
+                        text.push_str(&" ".repeat(padding));
+                    }
+                }
+                if line_number < buffer.line_count() {
+                    let start = buffer.line_offset(line_number);
+                    let line_len = buffer.line_end(start) - start;
+                    let padding = column.saturating_sub(line_len);
+                    let padded = " ".repeat(padding) + &text;
+                    edits.push((start + column.min(line_len), padded));
+                } else {
+                    // lines below the end of the buffer are added
+                    let text = format!("\n{}{}", " ".repeat(column), text);
+                    match edits.last_mut() {
+                        Some((at, last)) if *at == buffer.len() => last.push_str(&text),
+                        _ => edits.push((buffer.len(), text)),
+                    }
+                }
+            }
+            edits.reverse();
+            // the first line of the block is padded up to the column if it is shorter
+            (edits, buffer.line_offset(first_line) + column)
+        }
+    }
+}
+
+/// `p` (and `P` if `before`) puts the text of the register `count` times after (before) every
+/// cursor, linewise text goes below (above) the line
+pub(crate) fn put(ed_state: &EditorState, before: bool) -> EditorStateUpdate {
+    let name = ed_state.selected_register.unwrap_or('"');
+    let count = ed_state.count_or_one();
+    crate::clipboard::paste(ed_state, name, move |ed_state| {
+        put_register(ed_state, name, count, before)
+    })
+}
+
+fn put_register(
+    ed_state: &EditorState,
+    name: char,
+    count: usize,
+    before: bool,
+) -> EditorStateUpdate {
+    let mut transaction = crate::transaction::Transaction::new();
+    let register = match read(ed_state, name) {
+        Ok(register) => register,
+        Err(error) => {
+            transaction.message(error.to_string());
+            return EditorStateUpdate::Transaction(transaction);
+        }
+    };
+    let buffer = ed_state.get_buffer();
+    let mut selections = buffer.selections.clone();
+    if register.kind == RegisterKind::Blockwise {
+        // blocks of several cursors would run into each other
+        selections = crate::selections::Selections::single(selections.primary());
+    }
+    let puts = selections
+        .iter()
+        .map(|selection| put_edits(buffer, selection.head, &register, count, before))
+        .collect::<Vec<_>>();
+    for (edits, _) in puts.iter().rev() {
+        for (at, text) in edits {
+            transaction.insert(ed_state.buffer_idx, *at, text);
+        }
+    }
+    // the cursors end up behind the text put by the cursors in front of them
+    let mut inserted = 0;
+    let mut puts = puts.iter();
+    selections.map(|selection| {
+        let (edits, cursor) = puts.next().expect("one put per selection");
+        let cursor = cursor + inserted;
+        inserted += edits.iter().map(|(_, text)| text.chars().count()).sum::<usize>();
+        crate::selections::Selection::cursor(cursor, selection.desired_column)
+    });
+    transaction.selections(selections);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// Shows control characters (like the newlines of linewise registers) as `^J`
+fn printable(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\0'..='\x1f' => format!("^{}", (c as u8 + b'@') as char),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// `:registers` lists the registers (only the ones in `filter` if it is not empty) with their type
+/// (`c`harwise, `l`inewise or `b`lockwise) and content
+pub(crate) fn list(ed_state: &mut EditorState, filter: &str) {
+    let mut lines = vec!["Type Name Content".to_string()];
+    let width = (ed_state.term_info.cols as usize).saturating_sub(10).max(10);
+    let names = std::iter::once('"')
+        .chain('0'..='9')
+        .chain('a'..='z')
+        .chain("-*+.:%/".chars())
+        .filter(|&name| filter.is_empty() || filter.contains(name));
+    for name in names {
+        let Ok(register) = read(ed_state, name) else {
+            continue;
+        };
+        let kind = match register.kind {
+            RegisterKind::Charwise => 'c',
+            RegisterKind::Linewise => 'l',
+            RegisterKind::Blockwise => 'b',
+        };
+        let content = printable(&register.text).chars().take(width).collect::<String>();
+        lines.push(format!("  {}  \"{}   {}", kind, name, content));
+    }
+    writeln!(ed_state.command_line, "{}", lines.join("\n"))
+        .expect("Fatal: Could not write to command line buffer");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(registers: &Registers, name: char) -> Option<&str> {
+        registers.get(name).map(|register| register.text.as_str())
+    }
+
+    #[test]
+    fn test_vim_register_rules() {
+        let mut registers = Registers::default();
+        let chars = |text: &str| Register::new(text.to_string(), RegisterKind::Charwise);
+        let lines = |text: &str| Register::new(text.to_string(), RegisterKind::Linewise);
+        registers.yank(None, chars("yanked"));
+        registers.delete(None, chars("word"));
+        assert_eq!(text(&registers, '0'), Some("yanked"));
+        assert_eq!(text(&registers, '-'), Some("word"));
+        assert_eq!(text(&registers, '"'), Some("word"));
+        registers.delete(None, lines("first\n"));
+        registers.delete(Some('a'), lines("second\n"));
+        assert_eq!(text(&registers, '1'), Some("second\n"));
+        assert_eq!(text(&registers, '2'), Some("first\n"));
+        assert_eq!(text(&registers, 'a'), Some("second\n"));
+        // appending lines to text makes it linewise
+        registers.yank(Some('b'), chars("text"));
+        registers.yank(Some('B'), lines("line\n"));
+        assert_eq!(
+            registers.get('b'),
+            Some(&Register::new("text\nline\n".to_string(), RegisterKind::Linewise))
+        );
+        assert_eq!(text(&registers, '"'), Some("text\nline\n"));
+        registers.delete(Some('_'), lines("gone\n"));
+        assert_eq!(text(&registers, '1'), Some("second\n"));
+        assert!(check_writable(Some('.')).is_err());
+        assert!(check_writable(Some('!')).is_err());
+    }
+}
@@ -5,17 +5,160 @@ use crossterm::{
 };
 use std::io::{self, Write};
 
+/// Number of terminal columns the characters of `line` up to `column` take up: tabs reach to the
+/// next multiple of the tab size, wide characters take two columns
+fn display_column(line: &[char], column: usize, tab_size: usize) -> usize {
+    line.iter()
+        .take(column)
+        .fold(0, |width, &c| advance(width, c, tab_size))
+}
+
+/// The display width of a line after drawing `c` when it was `width` before
+fn advance(width: usize, c: char, tab_size: usize) -> usize {
+    match c {
+        '\t' => (width / tab_size + 1) * tab_size,
+        c => width + char_width(c),
+    }
+}
+
+fn char_width(c: char) -> usize {
+    // the common east asian wide and emoji blocks, good enough without pulling in a unicode crate
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// The first buffer line on screen and how many empty rows are drawn above it (the primary cursor
+/// is kept in the middle of the screen)
+fn top_line(ed_state: &crate::editor_state::EditorState) -> (usize, usize) {
+    let (cursor_y, _) = ed_state.get_buffer().cursor_position();
+    let half = ed_state.term_info.rows as usize / 2;
+    (cursor_y.saturating_sub(half), half.saturating_sub(cursor_y))
+}
+
+/// The buffer offset drawn at screen position (column, row), used for mouse clicks
+pub(crate) fn offset_at(
+    ed_state: &crate::editor_state::EditorState,
+    column: u16,
+    row: u16,
+) -> Option<usize> {
+    let buffer = ed_state.get_buffer();
+    let tab_size = ed_state.editor_globals.tab_size.max(1);
+    let (top_y, top_offset) = top_line(ed_state);
+    let line = (row as usize + top_y).checked_sub(top_offset)?;
+    if line >= buffer.line_count() {
+        return None;
+    }
+    let start = buffer.line_offset(line);
+    let end = buffer.line_end(start);
+    let mut width = 0;
+    for offset in start..end {
+        width = advance(width, buffer.char_at(offset)?, tab_size);
+        if width > column as usize {
+            return Some(offset);
+        }
+    }
+    Some(end)
+}
+
+/// Selected text in visual mode (`selected` are the ranges from `visual::highlighted_ranges`) and
+/// the cursors besides the primary one (which is the terminal cursor) are drawn highlighted
+fn is_highlighted(
+    ed_state: &crate::editor_state::EditorState,
+    selected: &[std::ops::Range<usize>],
+    offset: usize,
+) -> bool {
+    let selections = &ed_state.get_buffer().selections;
+    match ed_state.mode {
+        crate::editor_state::EditorMode::Visual(_) => {
+            selected.iter().any(|range| range.contains(&offset))
+        }
+        _ => selections
+            .iter()
+            .enumerate()
+            .any(|(i, selection)| i != selections.primary_index() && selection.head == offset),
+    }
+}
+
+/// Writes the escape sequences queued by commands (like copying to the clipboard with OSC 52)
+pub(crate) fn write_terminal_output(
+    ed_state: &mut crate::editor_state::EditorState,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for sequence in ed_state.terminal_output.drain(..) {
+        queue!(out, style::Print(sequence))?;
+    }
+    out.flush()
+}
+
+/// Most candidates the completion popup shows at once
+const POPUP_HEIGHT: usize = 10;
+
+/// Draws the completion popup below the start of the completed text (above it if there is no room
+/// below the cursor row), scrolled so the selected candidate is visible
+fn draw_popup(
+    out: &mut impl Write,
+    ed_state: &crate::editor_state::EditorState,
+    completion: &crate::completion::Completion,
+    x: usize,
+    cursor_row: usize,
+) -> io::Result<()> {
+    let rows = ed_state.term_info.rows as usize;
+    let height = completion.candidates.len().min(POPUP_HEIGHT);
+    let first_row = if cursor_row + 1 + height < rows {
+        cursor_row + 1
+    } else {
+        cursor_row.saturating_sub(height)
+    };
+    let scroll = completion
+        .selected
+        .map_or(0, |selected| (selected + 1).saturating_sub(height));
+    let width = completion
+        .candidates
+        .iter()
+        .map(|candidate| candidate.text.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min((ed_state.term_info.cols as usize).saturating_sub(x + 2));
+    let visible = completion.candidates.iter().enumerate().skip(scroll).take(height);
+    for (row, (i, candidate)) in visible.enumerate() {
+        let text = candidate.text.chars().take(width).collect::<String>();
+        let item = format!(" {text:<width$} ");
+        queue!(out, cursor::MoveTo(x as u16, (first_row + row) as u16))?;
+        if completion.selected == Some(i) {
+            queue!(out, style::PrintStyledContent(item.black().on_white()))?;
+        } else {
+            queue!(out, style::PrintStyledContent(item.white().on_dark_grey()))?;
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn render(ed_state: &crate::editor_state::EditorState) -> io::Result<()> {
     let mut stdout = io::stdout();
 
     let buffer = ed_state.get_buffer();
-    let mut buff_iter = buffer.content.iter();
+    let tab_size = ed_state.editor_globals.tab_size.max(1);
+
+    // the cursor is only an offset into the buffer, its screen position is computed here
+    let (cursor_y, cursor_column) = buffer.cursor_position();
+    let cursor_line = (buffer.line_offset(cursor_y)..buffer.line_end(buffer.cursor()))
+        .filter_map(|offset| buffer.char_at(offset))
+        .collect::<Vec<_>>();
+    let cursor_x = display_column(&cursor_line, cursor_column, tab_size);
 
-    let top_y = std::cmp::max(
-        0,
-        ed_state.cursor.y as isize - ed_state.term_info.rows as isize / 2,
-    ) as usize;
-    let bottom_y = (ed_state.cursor.y + ed_state.term_info.rows as usize / 2)
+    let (top_y, top_offset) = top_line(ed_state);
+    let bottom_y = (cursor_y + ed_state.term_info.rows as usize / 2)
         .checked_sub(ed_state.editor_globals.bottom_rows_skipped)
         .unwrap_or_else(|| crate::panic("bottom_y underflow: resized window too small to render"));
 
@@ -25,32 +168,39 @@ pub(crate) fn render(ed_state: &crate::editor_state::EditorState) -> io::Result<
         cursor::MoveTo(0, 0)
     )?;
 
-    let mut n_lines = 0;
-    for &c in buff_iter.by_ref() {
-        if n_lines == top_y {
-            break;
-        }
-        if c == '\n' {
-            n_lines += 1;
-        }
-    }
-
-    let top_offset = std::cmp::max(
-        0,
-        ed_state.term_info.rows as isize / 2 - ed_state.cursor.y as isize,
-    ) as usize;
-
+    let mut n_lines = top_y;
     queue!(stdout, cursor::MoveToNextLine(top_offset as u16))?;
-    // n_lines += top_offset;
 
-    for &c in buff_iter.by_ref() {
+    let mut width = 0;
+    let first_offset = buffer.line_offset(top_y);
+    let selected = crate::visual::highlighted_ranges(ed_state);
+    for (offset, &c) in buffer.content.iter().enumerate().skip(first_offset) {
+        let highlighted = is_highlighted(ed_state, &selected, offset);
         if c == '\n' {
+            // cursors at the end of a line still need to show up
+            if highlighted {
+                queue!(stdout, style::PrintStyledContent(' '.reverse()))?;
+            }
             n_lines += 1;
+            width = 0;
             queue!(stdout, cursor::MoveToNextLine(1))?;
             if n_lines == bottom_y {
                 break;
             }
+        } else if c == '\t' {
+            let next_width = advance(width, c, tab_size);
+            let spaces = " ".repeat(next_width - width);
+            if highlighted {
+                queue!(stdout, style::PrintStyledContent(spaces.reverse()))?;
+            } else {
+                queue!(stdout, style::Print(spaces))?;
+            }
+            width = next_width;
+        } else if highlighted {
+            width = advance(width, c, tab_size);
+            queue!(stdout, style::PrintStyledContent(c.white().reverse()))?;
         } else {
+            width = advance(width, c, tab_size);
             queue!(stdout, style::PrintStyledContent(c.white()))?;
         }
     }
@@ -60,15 +210,53 @@ pub(crate) fn render(ed_state: &crate::editor_state::EditorState) -> io::Result<
             (bottom_y - n_lines) as u16 + ed_state.editor_globals.bottom_rows_skipped as u16
         )
     )?;
-    for c in ed_state.command_line.buffer.chars() {
-        if c != '\n' {
+    if let crate::editor_state::EditorMode::Command = ed_state.mode {
+        queue!(stdout, style::PrintStyledContent(':'.white()))?;
+        for c in ed_state.command_line.input.chars() {
             queue!(stdout, style::PrintStyledContent(c.white()))?;
         }
+        queue!(
+            stdout,
+            cursor::MoveTo(
+                1 + ed_state.command_line.input.chars().count() as u16,
+                ed_state.term_info.rows.saturating_sub(1)
+            )
+        )?;
+    } else {
+        let recording_message;
+        let message = match &ed_state.macro_recording {
+            Some(recording) if ed_state.command_line.buffer.is_empty() => {
+                recording_message = format!("recording @{}", recording.register);
+                &recording_message
+            }
+            _ => &ed_state.command_line.buffer,
+        };
+        // messages spanning multiple lines (eg the `:map` listing) grow upwards from the bottom
+        let message_lines = message.lines().collect::<Vec<_>>();
+        let first_row = ed_state
+            .term_info
+            .rows
+            .saturating_sub(message_lines.len() as u16);
+        for (i, line) in message_lines.iter().enumerate() {
+            queue!(
+                stdout,
+                cursor::MoveTo(0, first_row + i as u16),
+                terminal::Clear(terminal::ClearType::CurrentLine),
+                style::PrintStyledContent(line.white())
+            )?;
+        }
+        let cursor_row = cursor_y - top_y + top_offset;
+        if let Some(completion) = crate::completion::active(ed_state) {
+            let line_start = buffer.line_start(buffer.cursor());
+            let anchor_column = completion.anchor.saturating_sub(line_start);
+            let anchor_x = display_column(&cursor_line, anchor_column, tab_size);
+            draw_popup(&mut stdout, ed_state, completion, anchor_x, cursor_row)?;
+        }
+        queue!(stdout, cursor::MoveTo(cursor_x as u16, cursor_row as u16))?;
     }
-    queue!(stdout, cursor::MoveTo(ed_state.cursor.x as u16, ed_state.cursor.y as u16))?;
 
     // NOTE: no need to check if the bottom_y is out of range (cursor at bottom of file) because
-    // the buff_iter.by_ref for loop writing the characters will simply stop iterating and write
+    // the for loop writing the characters will simply stop iterating and write
     // nothing to the screen below the bottom_y. This is only needed for the top_y.
     stdout.flush()?;
     Ok(())
@@ -0,0 +1,127 @@
+use crate::editor_state::{EditorMode, EditorState, EditorStateUpdate};
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::rc::Rc;
+
+/// Types a key that is not bound to anything in replace mode: characters overwrite the ones under
+/// the cursors, Backspace moves back and puts back what was overwritten
+pub(crate) fn type_key(atom: crate::motion_interpreter::MotionAtom, ed_state: &mut EditorState) {
+    let text = match atom.code {
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "\n".to_string(),
+        KeyCode::Tab => {
+            let buffer = ed_state.get_buffer();
+            let globals = &ed_state.editor_globals;
+            let edit = crate::indent::tab_edit(buffer, globals, buffer.cursor());
+            edit.text.iter().collect()
+        }
+        KeyCode::Backspace => return backspace(ed_state),
+        _ => return,
+    };
+    overwrite(ed_state, &text);
+}
+
+/// Overwrites the characters under the cursors with `text`
+pub(crate) fn overwrite(ed_state: &mut EditorState, text: &str) {
+    let buffer = &mut ed_state.buffers[ed_state.buffer_idx];
+    let EditorMode::Replace { replaced } = &mut ed_state.mode else {
+        return;
+    };
+    // cursors were added or merged, what they overwrote before can not be told apart anymore
+    if replaced.len() != buffer.selections.len() {
+        *replaced = vec![Vec::new(); buffer.selections.len()];
+    }
+    for c in text.chars() {
+        let overwritten = buffer.overwrite_at_cursors(c);
+        for (stack, old) in replaced.iter_mut().zip(overwritten) {
+            stack.push(old);
+        }
+    }
+    ed_state.registers.inserted(text);
+}
+
+fn backspace(ed_state: &mut EditorState) {
+    let buffer = &mut ed_state.buffers[ed_state.buffer_idx];
+    let EditorMode::Replace { replaced } = &mut ed_state.mode else {
+        return;
+    };
+    if replaced.len() != buffer.selections.len() {
+        *replaced = vec![Vec::new(); buffer.selections.len()];
+    }
+    let restore = replaced.iter_mut().map(|stack| stack.pop()).collect::<Vec<_>>();
+    buffer.restore_before_cursors(&restore);
+    ed_state.registers.insert_deleted(1);
+}
+
+/// The motion of the keys `r{char}` in normal or visual mode. `r` takes any character, so the
+/// characters are not bound after it (only <Esc> is, which cancels).
+pub(crate) fn replacement_motion(
+    mode: &EditorMode,
+    keys: &[crate::motion_interpreter::MotionAtom],
+) -> Option<crate::motion_interpreter::MotionFunction> {
+    let [prefix, key] = keys else {
+        return None;
+    };
+    if prefix.code != KeyCode::Char('r') || !prefix.modifiers.is_empty() {
+        return None;
+    }
+    let visual = match mode {
+        EditorMode::Normal => false,
+        EditorMode::Visual(_) => true,
+        _ => return None,
+    };
+    let replacement = match key.code {
+        KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => c,
+        KeyCode::Tab if key.modifiers.is_empty() => '\t',
+        KeyCode::Enter if key.modifiers.is_empty() && !visual => '\n',
+        _ => return None,
+    };
+    Some(crate::motion_interpreter::MotionFunction(Rc::new(
+        move |ed_state| match visual {
+            true => crate::visual::replace_selected(ed_state, replacement),
+            false => replace_chars(ed_state, replacement),
+        },
+    )))
+}
+
+/// `r{char}` replaces `count` characters from every cursor on with `c`, `r<CR>` replaces them with
+/// a single line break. Nothing changes if a line has fewer characters left.
+pub(crate) fn replace_chars(ed_state: &EditorState, c: char) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let count = ed_state.count_or_one();
+    let ranges = buffer
+        .selections
+        .iter()
+        .map(|selection| selection.head..selection.head + count)
+        .collect::<Vec<_>>();
+    if ranges
+        .iter()
+        .any(|range| range.end > buffer.line_end(range.start))
+    {
+        return EditorStateUpdate::None;
+    }
+    let text = if c == '\n' {
+        "\n".to_string()
+    } else {
+        c.to_string().repeat(count)
+    };
+    let mut transaction = crate::transaction::Transaction::new();
+    for range in ranges.iter().rev() {
+        transaction.replace(ed_state.buffer_idx, range.clone(), &text);
+    }
+    // the cursors end up on the last replaced character, or at the start of the split off line
+    let mut selections = buffer.selections.clone();
+    selections.map(|selection| {
+        let (offset, column) = if c == '\n' {
+            (selection.head + count, 0)
+        } else {
+            let offset = selection.head + count - 1;
+            (offset, offset - buffer.line_start(offset))
+        };
+        crate::selections::Selection::cursor(
+            transaction.shifted(ed_state.buffer_idx, offset),
+            column,
+        )
+    });
+    transaction.selections(selections);
+    EditorStateUpdate::Transaction(transaction)
+}
@@ -0,0 +1,186 @@
+use crate::editor_state::EditorStateUpdate;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+/// A motion function that does not finish right away. Once it completes, its update is applied
+/// to the editor state like the update of a synchronous motion function.
+pub(crate) type Task = Pin<Box<dyn Future<Output = EditorStateUpdate>>>;
+
+struct TaskWaker {
+    woken: AtomicBool,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+    }
+}
+
+struct ScheduledTask {
+    task: Task,
+    waker: Arc<TaskWaker>,
+}
+
+/// Drives tasks spawned by motion functions. It is polled by the event loop between input events,
+/// so tasks never block the input, they only make progress when they are woken up.
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    tasks: Vec<ScheduledTask>,
+}
+
+impl Scheduler {
+    pub(crate) fn spawn(&mut self, task: Task) {
+        self.tasks.push(ScheduledTask {
+            task,
+            // every task is polled at least once
+            waker: Arc::new(TaskWaker {
+                woken: AtomicBool::new(true),
+            }),
+        });
+    }
+
+    pub(crate) fn is_idle(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Polls every task that was woken up since the last call and returns the updates of the ones
+    /// that finished (in the order they were spawned)
+    pub(crate) fn poll(&mut self) -> Vec<EditorStateUpdate> {
+        let mut updates = Vec::new();
+        self.tasks.retain_mut(|scheduled_task| {
+            if !scheduled_task.waker.woken.swap(false, Ordering::SeqCst) {
+                return true;
+            }
+            let waker = Waker::from(Arc::clone(&scheduled_task.waker));
+            let mut context = Context::from_waker(&waker);
+            match scheduled_task.task.as_mut().poll(&mut context) {
+                Poll::Ready(update) => {
+                    updates.push(update);
+                    false
+                }
+                Poll::Pending => true,
+            }
+        });
+        updates
+    }
+}
+
+struct BlockingTaskState<T> {
+    result: Option<std::thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// A future resolving to the result of a closure running on its own thread, or to the panic of
+/// the closure
+pub(crate) struct BlockingTask<T> {
+    state: Arc<Mutex<BlockingTaskState<T>>>,
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = std::thread::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self
+            .state
+            .lock()
+            .expect("Fatal: blocking task state poisoned");
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Runs `f` on a background thread, so heavy work (eg a project wide grep or a formatter run)
+/// can be awaited inside a task without freezing the editor
+pub(crate) fn spawn_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> BlockingTask<T> {
+    let state = Arc::new(Mutex::new(BlockingTaskState {
+        result: None,
+        waker: None,
+    }));
+    let thread_state = Arc::clone(&state);
+    std::thread::spawn(move || {
+        // a panic still finishes the task, the awaiting task would wait forever otherwise
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+        let mut state = thread_state
+            .lock()
+            .expect("Fatal: blocking task state poisoned");
+        state.result = Some(result);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    });
+    BlockingTask { state }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_idx(update: &EditorStateUpdate) -> Option<usize> {
+        match update {
+            EditorStateUpdate::BufferIdx(idx) => Some(*idx),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_ready_task_finishes_on_first_poll() {
+        let mut scheduler = Scheduler::default();
+        scheduler.spawn(Box::pin(async { EditorStateUpdate::BufferIdx(3) }));
+        let updates = scheduler.poll();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(buffer_idx(&updates[0]), Some(3));
+        assert!(scheduler.is_idle());
+    }
+    #[test]
+    fn test_blocking_task_finishes_later() {
+        let mut scheduler = Scheduler::default();
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        scheduler.spawn(Box::pin(async move {
+            let idx = spawn_blocking(move || {
+                receiver.recv().unwrap();
+                7
+            })
+            .await
+            .unwrap();
+            EditorStateUpdate::BufferIdx(idx)
+        }));
+        assert!(scheduler.poll().is_empty());
+        // not woken up, so polling again does nothing
+        assert!(scheduler.poll().is_empty());
+        sender.send(()).unwrap();
+        let mut updates = Vec::new();
+        while updates.is_empty() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            updates = scheduler.poll();
+        }
+        assert_eq!(buffer_idx(&updates[0]), Some(7));
+    }
+    #[test]
+    fn test_panicking_blocking_task_finishes() {
+        let mut scheduler = Scheduler::default();
+        scheduler.spawn(Box::pin(async {
+            let result = spawn_blocking(|| -> usize { panic!("task failed") }).await;
+            EditorStateUpdate::BufferIdx(result.map_or(0, |idx| idx + 1))
+        }));
+        let mut updates = Vec::new();
+        while updates.is_empty() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            updates = scheduler.poll();
+        }
+        assert_eq!(buffer_idx(&updates[0]), Some(0));
+    }
+}
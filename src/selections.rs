@@ -0,0 +1,324 @@
+use std::ops::Range;
+
+/// One cursor of a buffer. Outside of visual mode `anchor` and `head` are the same, in visual mode
+/// the selection reaches from `anchor` to `head` (both included).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Selection {
+    pub(crate) anchor: usize,
+    /// offset of the character the cursor is on
+    pub(crate) head: usize,
+    /// the column vertical motions try to get back to after passing shorter lines (`usize::MAX`
+    /// sticks to the end of the line after `$`)
+    pub(crate) desired_column: usize,
+}
+
+impl Selection {
+    pub(crate) fn cursor(offset: usize, desired_column: usize) -> Self {
+        Self {
+            anchor: offset,
+            head: offset,
+            desired_column,
+        }
+    }
+
+    pub(crate) fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    /// The last selected offset (included)
+    pub(crate) fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+
+    pub(crate) fn range(&self) -> Range<usize> {
+        self.start()..self.end() + 1
+    }
+
+    pub(crate) fn collapse(self) -> Self {
+        Self {
+            anchor: self.head,
+            ..self
+        }
+    }
+
+    fn shift(self, range: &Range<usize>, inserted: usize) -> Self {
+        Self {
+            anchor: crate::transaction::shift_offset(self.anchor, range, inserted),
+            head: crate::transaction::shift_offset(self.head, range, inserted),
+            ..self
+        }
+    }
+}
+
+/// The cursors of a buffer, sorted by position and merged when they overlap. The primary one is
+/// the cursor the screen follows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Selections {
+    selections: Vec<Selection>,
+    primary: usize,
+}
+
+impl Default for Selections {
+    fn default() -> Self {
+        Self::single(Selection::cursor(0, 0))
+    }
+}
+
+impl Selections {
+    pub(crate) fn new(selections: Vec<Selection>, primary: usize) -> Self {
+        assert!(primary < selections.len(), "primary selection out of range");
+        let mut selections = Self {
+            selections,
+            primary,
+        };
+        selections.normalize();
+        selections
+    }
+
+    pub(crate) fn single(selection: Selection) -> Self {
+        Self {
+            selections: vec![selection],
+            primary: 0,
+        }
+    }
+
+    pub(crate) fn primary(&self) -> Selection {
+        self.selections[self.primary]
+    }
+
+    pub(crate) fn primary_index(&self) -> usize {
+        self.primary
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.selections.len()
+    }
+
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, Selection> {
+        self.selections.iter()
+    }
+
+    /// Adds a selection and makes it the primary one
+    pub(crate) fn push(&mut self, selection: Selection) {
+        self.selections.push(selection);
+        self.primary = self.selections.len() - 1;
+        self.normalize();
+    }
+
+    /// Changes every selection, the ones that overlap afterwards are merged
+    pub(crate) fn map(&mut self, f: impl FnMut(Selection) -> Selection) {
+        self.selections = self.selections.iter().copied().map(f).collect();
+        self.normalize();
+    }
+
+    /// Moves every selection along with the text after `range` was replaced with `inserted`
+    /// characters
+    pub(crate) fn shift(&mut self, range: &Range<usize>, inserted: usize) {
+        self.map(|selection| selection.shift(range, inserted));
+    }
+
+    fn normalize(&mut self) {
+        let primary = self.selections[self.primary];
+        let mut selections = std::mem::take(&mut self.selections);
+        selections.sort_by_key(|selection| (selection.start(), selection.end()));
+        for selection in selections {
+            match self.selections.last_mut() {
+                Some(last) if selection.start() <= last.end() => {
+                    let (start, end) = (last.start(), last.end().max(selection.end()));
+                    let forward = last.head >= last.anchor || selection.head >= selection.anchor;
+                    let desired_column = if selection == primary {
+                        selection.desired_column
+                    } else {
+                        last.desired_column
+                    };
+                    let (anchor, head) = if forward { (start, end) } else { (end, start) };
+                    *last = Selection {
+                        anchor,
+                        head,
+                        desired_column,
+                    };
+                }
+                _ => self.selections.push(selection),
+            }
+        }
+        // the primary selection may have been merged into another one
+        self.primary = self
+            .selections
+            .iter()
+            .position(|selection| selection.range().contains(&primary.head))
+            .unwrap_or(0);
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Range of the word under `offset`, if there is one
+fn word_at(buffer: &crate::editor_buffer::Buffer, offset: usize) -> Option<Range<usize>> {
+    if !buffer.char_at(offset).is_some_and(is_word_char) {
+        return None;
+    }
+    let mut start = offset;
+    while start > 0 && buffer.char_at(start - 1).is_some_and(is_word_char) {
+        start -= 1;
+    }
+    let mut end = offset;
+    while buffer.char_at(end).is_some_and(is_word_char) {
+        end += 1;
+    }
+    Some(start..end)
+}
+
+/// Adds a cursor at the next match of the word under the primary cursor (or of the selected text
+/// in visual mode), wrapping around the end of the buffer. The new cursor becomes the primary one,
+/// so repeating it walks through the matches.
+pub(crate) fn add_cursor_at_next_match(
+    ed_state: &crate::editor_state::EditorState,
+) -> crate::editor_state::EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let primary = buffer.selections.primary();
+    let visual = ed_state.mode.kind() == crate::editor_state::ModeKind::Visual;
+    let mut transaction = crate::transaction::Transaction::new();
+    let (pattern, whole_word) = if visual {
+        (primary.range(), false)
+    } else {
+        match word_at(buffer, primary.head) {
+            Some(word) => (word, true),
+            None => {
+                transaction.message("No word under cursor");
+                return crate::editor_state::EditorStateUpdate::Transaction(transaction);
+            }
+        }
+    };
+    let len = buffer.len();
+    let pattern_start = pattern.start;
+    let pattern = buffer.content.slice(pattern.start..pattern.end.min(len));
+    let is_match = |start: usize| {
+        !pattern.is_empty()
+            && start + pattern.len() <= len
+            && (0..pattern.len()).all(|i| buffer.char_at(start + i) == Some(pattern[i]))
+            && !(whole_word
+                && (start > 0 && buffer.char_at(start - 1).is_some_and(is_word_char)
+                    || buffer.char_at(start + pattern.len()).is_some_and(is_word_char)))
+    };
+    let taken = |start: usize| {
+        buffer
+            .selections
+            .iter()
+            .any(|s| s.start() < start + pattern.len() && s.end() >= start)
+    };
+    let found = (1..=len)
+        .map(|i| (primary.end() + i) % len)
+        .find(|&start| is_match(start) && !taken(start));
+    let mut selections = buffer.selections.clone();
+    match found {
+        Some(start) if visual => selections.push(Selection {
+            anchor: start,
+            head: start + pattern.len() - 1,
+            ..primary
+        }),
+        // keep the cursor at the same position inside the word
+        Some(start) => selections.push(Selection::cursor(
+            start + primary.head - pattern_start,
+            primary.desired_column,
+        )),
+        None => {
+            transaction.message("No more matches");
+            return crate::editor_state::EditorStateUpdate::Transaction(transaction);
+        }
+    }
+    transaction.selections(selections);
+    crate::editor_state::EditorStateUpdate::Transaction(transaction)
+}
+
+/// Leaves visual mode with a cursor on every line the selections touch, in the column of their
+/// heads
+pub(crate) fn split_into_lines(
+    ed_state: &crate::editor_state::EditorState,
+) -> crate::editor_state::EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let primary = buffer.selections.primary();
+    let mut cursors = Vec::new();
+    let mut primary_idx = 0;
+    for selection in buffer.selections.iter() {
+        let (start_line, _) = buffer.position(selection.start());
+        let (end_line, _) = buffer.position(selection.end());
+        let (head_line, column) = buffer.position(selection.head);
+        for line in start_line..=end_line {
+            if *selection == primary && line == head_line {
+                primary_idx = cursors.len();
+            }
+            let offset = crate::text_motions::clamp_to_line(buffer, buffer.offset(line, column));
+            cursors.push(Selection::cursor(offset, column));
+        }
+    }
+    let mut transaction = crate::transaction::Transaction::new();
+    transaction
+        .selections(Selections::new(cursors, primary_idx))
+        .mode(crate::editor_state::EditorMode::Normal);
+    crate::editor_state::EditorStateUpdate::Transaction(transaction)
+}
+
+/// Drops every cursor but the primary one
+pub(crate) fn keep_primary(
+    ed_state: &crate::editor_state::EditorState,
+) -> crate::editor_state::EditorStateUpdate {
+    let primary = ed_state.get_buffer().selections.primary();
+    let mut transaction = crate::transaction::Transaction::new();
+    transaction.selections(Selections::single(primary.collapse()));
+    crate::editor_state::EditorStateUpdate::Transaction(transaction)
+}
+
+/// Leaves visual mode, the selections shrink back to their heads
+pub(crate) fn leave_visual(
+    ed_state: &crate::editor_state::EditorState,
+) -> crate::editor_state::EditorStateUpdate {
+    let mut selections = ed_state.get_buffer().selections.clone();
+    selections.map(Selection::collapse);
+    let mut transaction = crate::transaction::Transaction::new();
+    transaction
+        .selections(selections)
+        .mode(crate::editor_state::EditorMode::Normal);
+    crate::editor_state::EditorStateUpdate::Transaction(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlapping_selections_are_merged() {
+        let selections = Selections::new(
+            vec![
+                Selection::cursor(9, 0),
+                Selection {
+                    anchor: 2,
+                    head: 5,
+                    desired_column: 0,
+                },
+                Selection::cursor(4, 0),
+                Selection::cursor(9, 0),
+            ],
+            2,
+        );
+        assert_eq!(
+            selections.iter().map(Selection::range).collect::<Vec<_>>(),
+            vec![2..6, 9..10]
+        );
+        assert_eq!(selections.primary_index(), 0);
+    }
+    #[test]
+    fn test_selections_follow_edits() {
+        let mut selections = Selections::new(
+            vec![Selection::cursor(1, 0), Selection::cursor(5, 0)],
+            0,
+        );
+        selections.shift(&(3..3), 3);
+        assert_eq!(selections.iter().map(|s| s.head).collect::<Vec<_>>(), vec![1, 8]);
+        // deleting the text between the cursors makes them collapse into one
+        selections.shift(&(1..8), 0);
+        assert_eq!(selections.len(), 1);
+        assert_eq!(selections.primary().head, 1);
+    }
+}
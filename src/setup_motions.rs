@@ -1,5 +1,47 @@
 use std::rc::Rc;
 
+/// Binds `keys` (in key notation) to a motion function, for motions spanning multiple keys
+fn insert_motion(
+    motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    keys: &str,
+    motion_function: crate::motion_interpreter::MotionFunction,
+) {
+    let mut motion_tree =
+        crate::motion_interpreter::MotionTree::Tree(std::mem::take(motion_tree_map));
+    motion_tree
+        .insert(
+            crate::motion_interpreter::MotionComponentBuffer(
+                crate::key_notation::parse(keys).expect("Fatal: invalid builtin motion keys"),
+            ),
+            motion_function,
+        )
+        .expect("Fatal: conflicting builtin motions");
+    if let crate::motion_interpreter::MotionTree::Tree(tree) = motion_tree {
+        *motion_tree_map = tree;
+    }
+}
+
+/// The names of all registers, for `"{name}` and <C-r>{name}
+fn register_names() -> impl Iterator<Item = char> {
+    ('a'..='z')
+        .chain('A'..='Z')
+        .chain('0'..='9')
+        .chain("\"-_".chars())
+        .chain(crate::clipboard::CLIPBOARD_REGISTERS.chars())
+        .chain(crate::registers::READ_ONLY_REGISTERS.chars())
+}
+
+/// h/j/k/l, w/b/e/W/B/E, 0/^/$, gg/G, shared by normal and visual mode
+pub(crate) fn setup_cursor_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
+    for text_motion in crate::text_motions::TEXT_MOTIONS {
+        insert_motion(
+            motion_tree_map,
+            text_motion.keys,
+            crate::text_motions::cursor_motion(text_motion),
+        );
+    }
+}
+
 pub(crate) fn setup_normal_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
     motion_tree_map.insert(
         crate::motion_interpreter::MotionAtom {
@@ -7,7 +49,15 @@ pub(crate) fn setup_normal_motions(motion_tree_map: &mut crate::motion_interpret
             modifiers: crossterm::event::KeyModifiers::NONE,
         },
         crate::motion_interpreter::MotionTree::Atom(crate::motion_interpreter::MotionFunction(
-            Rc::new(|_| {
+            Rc::new(|ed_state| {
+                // after <C-o>, escape just goes back to insert mode (once the command is done)
+                if ed_state.insert_after_command.is_some() {
+                    return crate::editor_state::EditorStateUpdate::None;
+                }
+                // with multiple cursors, the first escape only gets rid of the extra ones
+                if ed_state.get_buffer().selections.len() > 1 {
+                    return crate::selections::keep_primary(ed_state);
+                }
                 // println!("bye bye");
                 crate::quit()
             }),
@@ -29,20 +79,69 @@ pub(crate) fn setup_normal_motions(motion_tree_map: &mut crate::motion_interpret
     );
     motion_tree_map.insert(
         crate::motion_interpreter::MotionAtom {
-            code: crossterm::event::KeyCode::Char('v'),
+            code: crossterm::event::KeyCode::Char(':'),
             modifiers: crossterm::event::KeyModifiers::NONE,
         },
         crate::motion_interpreter::MotionTree::Atom(crate::motion_interpreter::MotionFunction(
-            Rc::new(|ed_state| {
-                // println!("visual mode");
+            Rc::new(|_| {
                 crate::editor_state::EditorStateUpdate::Mode(
-                    crate::editor_state::EditorMode::Visual {
-                        cursor_start: ed_state.cursor,
-                    }
+                    crate::editor_state::EditorMode::Command,
                 )
             }),
         )),
     );
+    motion_tree_map.insert(
+        crate::motion_interpreter::MotionAtom {
+            code: crossterm::event::KeyCode::Char('.'),
+            modifiers: crossterm::event::KeyModifiers::NONE,
+        },
+        crate::motion_interpreter::MotionTree::Atom(crate::motion_interpreter::MotionFunction(
+            Rc::new(|ed_state| crate::editor_state::EditorStateUpdate::DotRepeat(ed_state.count)),
+        )),
+    );
+    for register in crate::macros::MACRO_REGISTERS.chars() {
+        let register_atom = crate::motion_interpreter::MotionAtom {
+            code: crossterm::event::KeyCode::Char(register),
+            modifiers: crossterm::event::KeyModifiers::NONE,
+        };
+        let q = crate::motion_interpreter::MotionAtom {
+            code: crossterm::event::KeyCode::Char('q'),
+            modifiers: crossterm::event::KeyModifiers::NONE,
+        };
+        motion_tree_map
+            .entry(q)
+            .or_default()
+            .insert(
+                crate::motion_interpreter::MotionComponentBuffer(vec![register_atom]),
+                crate::motion_interpreter::MotionFunction(Rc::new(move |_| {
+                    crate::editor_state::EditorStateUpdate::StartMacroRecording(register)
+                })),
+            )
+            .expect("Fatal: could not set up macro recording motions");
+    }
+    for register in crate::macros::MACRO_REGISTERS.chars().chain(std::iter::once('@')) {
+        let register_atom = crate::motion_interpreter::MotionAtom {
+            code: crossterm::event::KeyCode::Char(register),
+            modifiers: crossterm::event::KeyModifiers::NONE,
+        };
+        let at = crate::motion_interpreter::MotionAtom {
+            code: crossterm::event::KeyCode::Char('@'),
+            modifiers: crossterm::event::KeyModifiers::NONE,
+        };
+        motion_tree_map
+            .entry(at)
+            .or_default()
+            .insert(
+                crate::motion_interpreter::MotionComponentBuffer(vec![register_atom]),
+                crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                    crate::editor_state::EditorStateUpdate::PlayMacro {
+                        register,
+                        count: ed_state.count_or_one(),
+                    }
+                })),
+            )
+            .expect("Fatal: could not set up macro playback motions");
+    }
 }
 
 pub(crate) fn setup_insert_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
@@ -60,44 +159,520 @@ pub(crate) fn setup_insert_motions(motion_tree_map: &mut crate::motion_interpret
             }),
         )),
     );
+    setup_completion_motions(motion_tree_map);
+    setup_insert_editing_motions(motion_tree_map);
+}
+
+/// <C-w>/<C-u> delete the word or the line in front of the cursor, <C-r>{register} inserts a
+/// register, <C-o> runs one normal mode command, <C-t>/<C-d> indent and dedent the line and <C-v>
+/// inserts the next key literally (or a character by its code, like <C-v>u20ac)
+fn setup_insert_editing_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
+    type Motion = fn(&crate::editor_state::EditorState) -> crate::editor_state::EditorStateUpdate;
+    let motions: [(&str, Motion); 7] = [
+        ("<C-w>", crate::insert_keys::delete_word),
+        ("<C-u>", crate::insert_keys::delete_line),
+        ("<C-t>", |ed_state| crate::indent::shift_cursor_lines(ed_state, true)),
+        ("<C-d>", |ed_state| crate::indent::shift_cursor_lines(ed_state, false)),
+        ("<C-o>", |_| crate::editor_state::EditorStateUpdate::InsertCommand),
+        ("<C-v>", |_| crate::editor_state::EditorStateUpdate::InsertLiteral),
+        // <C-q> is the same, for terminals that use <C-v> for pasting
+        ("<C-q>", |_| crate::editor_state::EditorStateUpdate::InsertLiteral),
+    ];
+    for (keys, motion) in motions {
+        insert_motion(
+            motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(motion)),
+        );
+    }
+    let atom = |c, modifiers| crate::motion_interpreter::MotionAtom {
+        code: crossterm::event::KeyCode::Char(c),
+        modifiers,
+    };
+    for name in register_names() {
+        motion_tree_map
+            .entry(atom('r', crossterm::event::KeyModifiers::CONTROL))
+            .or_default()
+            .insert(
+                crate::motion_interpreter::MotionComponentBuffer(vec![atom(
+                    name,
+                    crossterm::event::KeyModifiers::NONE,
+                )]),
+                crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                    crate::insert_keys::insert_register(ed_state, name)
+                })),
+            )
+            .expect("Fatal: could not set up register motions");
+    }
+}
+
+/// <C-n>/<C-p> complete from the sources of the `complete` option, <C-x> followed by <C-n>/<C-p>,
+/// <C-l>, <C-f> or <C-k> from keywords, lines, file paths or the dictionary. <C-y> keeps the
+/// candidate and <C-e> goes back to the typed text.
+fn setup_completion_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
+    use crate::completion::{CompletionCommand, Source};
+    let selects = [
+        ("<C-n>", true, None),
+        ("<C-p>", false, None),
+        ("<C-x><C-n>", true, Some(Source::Keyword)),
+        ("<C-x><C-p>", false, Some(Source::Keyword)),
+        ("<C-x><C-l>", true, Some(Source::Line)),
+        ("<C-x><C-f>", true, Some(Source::File)),
+        ("<C-x><C-k>", true, Some(Source::Dictionary)),
+    ];
+    let commands = selects
+        .into_iter()
+        .map(|(keys, forward, source)| (keys, CompletionCommand::Select { forward, source }))
+        .chain([
+            ("<C-y>", CompletionCommand::Accept),
+            ("<C-e>", CompletionCommand::Cancel),
+        ]);
+    for (keys, command) in commands {
+        insert_motion(
+            motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |_| {
+                crate::editor_state::EditorStateUpdate::Complete(command)
+            })),
+        );
+    }
+}
+
+/// `R` enters replace mode, which Esc leaves again, the insert mode editing and completion keys
+/// work there too. `r{char}` replaces characters under the cursor (`r<CR>` splits the line).
+pub(crate) fn setup_replace_motions(
+    normal_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    replace_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+) {
+    insert_motion(
+        normal_motion_tree_map,
+        "R",
+        crate::motion_interpreter::MotionFunction(Rc::new(|_| {
+            crate::editor_state::EditorStateUpdate::Mode(
+                crate::editor_state::EditorMode::new_replace(),
+            )
+        })),
+    );
+    insert_motion(
+        replace_motion_tree_map,
+        "<Esc>",
+        crate::motion_interpreter::MotionFunction(Rc::new(|_| {
+            crate::editor_state::EditorStateUpdate::Mode(crate::editor_state::EditorMode::Normal)
+        })),
+    );
+    setup_completion_motions(replace_motion_tree_map);
+    setup_insert_editing_motions(replace_motion_tree_map);
+    // the character after `r` is handled by `replace::replacement_motion`
+    insert_motion(
+        normal_motion_tree_map,
+        "r<Esc>",
+        crate::motion_interpreter::MotionFunction(Rc::new(|_| {
+            crate::editor_state::EditorStateUpdate::None
+        })),
+    );
+}
+
+/// <C-o>/<C-i> step through the jumplist, `g;`/`g,` through the changelist (<Tab> is the same key
+/// as <C-i> in most terminals)
+pub(crate) fn setup_jump_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
+    for (keys, direction) in [("<C-o>", -1), ("<C-i>", 1), ("<Tab>", 1)] {
+        insert_motion(
+            motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                crate::editor_state::EditorStateUpdate::StepJumplist(
+                    direction * ed_state.count_or_one() as isize,
+                )
+            })),
+        );
+    }
+    for (keys, direction) in [("g;", -1), ("g,", 1)] {
+        insert_motion(
+            motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                crate::editor_state::EditorStateUpdate::StepChangelist(
+                    direction * ed_state.count_or_one() as isize,
+                )
+            })),
+        );
+    }
+}
+
+/// `m{a-zA-Z}` sets a mark, `'{mark}` jumps to its line and `` `{mark} `` to its exact position
+pub(crate) fn setup_mark_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
+    for mark in ('a'..='z').chain('A'..='Z') {
+        insert_motion(
+            motion_tree_map,
+            &format!("m{mark}"),
+            crate::motion_interpreter::MotionFunction(Rc::new(move |_| {
+                crate::editor_state::EditorStateUpdate::SetMark(mark)
+            })),
+        );
+    }
+    for (prefix, linewise) in [('\'', true), ('`', false)] {
+        for mark in ('a'..='z').chain('A'..='Z').chain("'`.<>^".chars()) {
+            let key = match mark {
+                '<' => "<lt>".to_string(),
+                mark => mark.to_string(),
+            };
+            insert_motion(
+                motion_tree_map,
+                &format!("{prefix}{key}"),
+                crate::motion_interpreter::MotionFunction(Rc::new(move |_| {
+                    crate::editor_state::EditorStateUpdate::JumpToMark { mark, linewise }
+                })),
+            );
+        }
+    }
+}
+
+/// <C-n> adds a cursor at the next match in normal and visual mode, <A-s> in visual mode puts a
+/// cursor on every selected line
+pub(crate) fn setup_multi_cursor_motions(
+    normal_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    visual_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+) {
+    for motion_tree_map in [normal_motion_tree_map, &mut *visual_motion_tree_map] {
+        insert_motion(
+            motion_tree_map,
+            "<C-n>",
+            crate::motion_interpreter::MotionFunction(Rc::new(
+                crate::selections::add_cursor_at_next_match,
+            )),
+        );
+    }
+    insert_motion(
+        visual_motion_tree_map,
+        "<A-s>",
+        crate::motion_interpreter::MotionFunction(Rc::new(crate::selections::split_into_lines)),
+    );
 }
 
 pub(crate) fn setup_visual_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
     motion_tree_map.insert(
         crate::motion_interpreter::MotionAtom {
-            code: crossterm::event::KeyCode::Char('v'),
+            code: crossterm::event::KeyCode::Esc,
             modifiers: crossterm::event::KeyModifiers::NONE,
         },
         crate::motion_interpreter::MotionTree::Atom(crate::motion_interpreter::MotionFunction(
-            Rc::new(|_| {
-                // println!("normal mode");
-                crate::editor_state::EditorStateUpdate::Mode(
-                    crate::editor_state::EditorMode::Normal,
-                )
-            }),
+            Rc::new(crate::selections::leave_visual),
         )),
     );
     motion_tree_map.insert(
         crate::motion_interpreter::MotionAtom {
-            code: crossterm::event::KeyCode::Esc,
+            code: crossterm::event::KeyCode::Char(':'),
             modifiers: crossterm::event::KeyModifiers::NONE,
         },
         crate::motion_interpreter::MotionTree::Atom(crate::motion_interpreter::MotionFunction(
             Rc::new(|_| {
                 crate::editor_state::EditorStateUpdate::Mode(
-                    crate::editor_state::EditorMode::Normal,
+                    crate::editor_state::EditorMode::Command,
                 )
             }),
         )),
     );
 }
 
+/// `v`/`V`/<C-v> enter (or switch between) the kinds of visual mode and `gv` selects the last
+/// visual area again. In visual mode `o` goes to the other end of the selections and c, >, <, ~,
+/// u, U, J, r{char}, I and A work on the selected text.
+pub(crate) fn setup_visual_operator_motions(
+    normal_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    visual_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+) {
+    use crate::visual::{CaseChange, VisualKind};
+    for motion_tree_map in [&mut *normal_motion_tree_map, &mut *visual_motion_tree_map] {
+        for (keys, kind) in [
+            ("v", VisualKind::Charwise),
+            ("V", VisualKind::Linewise),
+            ("<C-v>", VisualKind::Blockwise),
+        ] {
+            insert_motion(
+                motion_tree_map,
+                keys,
+                crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                    crate::visual::toggle(ed_state, kind)
+                })),
+            );
+        }
+    }
+    insert_motion(
+        normal_motion_tree_map,
+        "gv",
+        crate::motion_interpreter::MotionFunction(Rc::new(crate::visual::reselect)),
+    );
+    insert_motion(
+        visual_motion_tree_map,
+        "o",
+        crate::motion_interpreter::MotionFunction(Rc::new(crate::visual::swap_ends)),
+    );
+    insert_motion(
+        visual_motion_tree_map,
+        "c",
+        crate::motion_interpreter::MotionFunction(Rc::new(crate::visual::change)),
+    );
+    insert_motion(
+        visual_motion_tree_map,
+        "J",
+        crate::motion_interpreter::MotionFunction(Rc::new(crate::visual::join_lines)),
+    );
+    for (keys, right) in [(">", true), ("<lt>", false)] {
+        insert_motion(
+            visual_motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                crate::visual::shift_lines(ed_state, right)
+            })),
+        );
+    }
+    for (keys, case) in [
+        ("~", CaseChange::Toggle),
+        ("u", CaseChange::Lower),
+        ("U", CaseChange::Upper),
+    ] {
+        insert_motion(
+            visual_motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                crate::visual::change_case(ed_state, case)
+            })),
+        );
+    }
+    for (keys, append) in [("I", false), ("A", true)] {
+        insert_motion(
+            visual_motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                crate::visual::insert(ed_state, append)
+            })),
+        );
+    }
+    // the character after `r` is handled by `replace::replacement_motion`
+    insert_motion(
+        visual_motion_tree_map,
+        "r<Esc>",
+        crate::motion_interpreter::MotionFunction(Rc::new(|_| {
+            crate::editor_state::EditorStateUpdate::None
+        })),
+    );
+}
+
+/// `u`/<C-r> walk up and down the undo tree, `g-`/`g+` through its states in the order they were
+/// made
+pub(crate) fn setup_undo_motions(motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap) {
+    for keys in ["u", "<C-r>", "g-", "g+"] {
+        insert_motion(
+            motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                use crate::undo::{TimeStep, UndoCommand};
+                let count = ed_state.count_or_one();
+                crate::editor_state::EditorStateUpdate::Undo(match keys {
+                    "u" => UndoCommand::Undo(count),
+                    "<C-r>" => UndoCommand::Redo(count),
+                    "g-" => UndoCommand::Earlier(TimeStep::Changes(count)),
+                    _ => UndoCommand::Later(TimeStep::Changes(count)),
+                })
+            })),
+        );
+    }
+}
+
+/// `"{name}` selects the register for the next command, `y{motion}`/`d{motion}` (`yy`/`dd` for
+/// lines), `x`, `D`, `Y` and `p`/`P` in normal mode and `y`/`d`/`x` in visual mode yank, delete and
+/// put
+pub(crate) fn setup_register_motions(
+    normal_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    visual_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+) {
+    let atom = |c| crate::motion_interpreter::MotionAtom {
+        code: crossterm::event::KeyCode::Char(c),
+        modifiers: crossterm::event::KeyModifiers::NONE,
+    };
+    for name in register_names() {
+        for motion_tree_map in [&mut *normal_motion_tree_map, &mut *visual_motion_tree_map] {
+            motion_tree_map
+                .entry(atom('"'))
+                .or_default()
+                .insert(
+                    crate::motion_interpreter::MotionComponentBuffer(vec![atom(name)]),
+                    crate::motion_interpreter::MotionFunction(Rc::new(move |_| {
+                        crate::editor_state::EditorStateUpdate::SelectRegister(name)
+                    })),
+                )
+                .expect("Fatal: could not set up register motions");
+        }
+    }
+    for (operator, delete) in [("y", false), ("d", true)] {
+        for text_motion in crate::text_motions::TEXT_MOTIONS {
+            insert_motion(
+                normal_motion_tree_map,
+                &format!("{operator}{}", text_motion.keys),
+                crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                    crate::registers::operator(ed_state, text_motion, delete)
+                })),
+            );
+        }
+    }
+    for keys in ["yy", "Y"] {
+        insert_motion(
+            normal_motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(crate::registers::yank_lines)),
+        );
+    }
+    insert_motion(
+        normal_motion_tree_map,
+        "dd",
+        crate::motion_interpreter::MotionFunction(Rc::new(crate::registers::delete_lines)),
+    );
+    insert_motion(
+        normal_motion_tree_map,
+        "x",
+        crate::motion_interpreter::MotionFunction(Rc::new(crate::registers::delete_chars)),
+    );
+    let line_end = crate::text_motions::TEXT_MOTIONS
+        .iter()
+        .find(|text_motion| text_motion.keys == "$")
+        .expect("Fatal: $ is a text motion");
+    insert_motion(
+        normal_motion_tree_map,
+        "D",
+        crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+            crate::registers::operator(ed_state, line_end, true)
+        })),
+    );
+    for (keys, before) in [("p", false), ("P", true)] {
+        insert_motion(
+            normal_motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                crate::registers::put(ed_state, before)
+            })),
+        );
+    }
+    for (keys, delete) in [("y", false), ("d", true), ("x", true)] {
+        insert_motion(
+            visual_motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                crate::registers::yank_selections(ed_state, delete)
+            })),
+        );
+    }
+}
+
+/// `>`, `<` and `=` followed by a text motion (or doubled for `count` lines) shift and reindent
+/// lines, `=` reindents the selected lines in visual mode
+pub(crate) fn setup_indent_motions(
+    normal_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    visual_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+) {
+    // `None` reindents, otherwise the lines are shifted right or left
+    for (operator, shift) in [(">", Some(true)), ("<lt>", Some(false)), ("=", None)] {
+        let motions = crate::text_motions::TEXT_MOTIONS
+            .iter()
+            .map(|text_motion| (text_motion.keys, Some(text_motion)))
+            .chain(std::iter::once((operator, None)));
+        for (keys, text_motion) in motions {
+            insert_motion(
+                normal_motion_tree_map,
+                &format!("{operator}{keys}"),
+                crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                    let spans = crate::indent::operator_lines(ed_state, text_motion);
+                    match shift {
+                        Some(right) => crate::indent::shift(ed_state, &spans, right, 1),
+                        None => crate::indent::reindent(ed_state, &spans),
+                    }
+                })),
+            );
+        }
+    }
+    insert_motion(
+        visual_motion_tree_map,
+        "=",
+        crate::motion_interpreter::MotionFunction(Rc::new(|ed_state| {
+            let spans = crate::visual::selected_lines(ed_state.get_buffer());
+            crate::indent::reindent(ed_state, &spans)
+        })),
+    );
+}
+
+/// `gc{motion}`, `gcc` and visual `gc` comment out lines or uncomment them
+pub(crate) fn setup_comment_motions(
+    normal_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    visual_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+) {
+    let motions = crate::text_motions::TEXT_MOTIONS
+        .iter()
+        .map(|text_motion| (text_motion.keys, Some(text_motion)))
+        .chain(std::iter::once(("c", None)));
+    for (keys, text_motion) in motions {
+        insert_motion(
+            normal_motion_tree_map,
+            &format!("gc{keys}"),
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                let spans = crate::indent::operator_lines(ed_state, text_motion);
+                crate::comment::toggle(ed_state, &spans)
+            })),
+        );
+    }
+    insert_motion(
+        visual_motion_tree_map,
+        "gc",
+        crate::motion_interpreter::MotionFunction(Rc::new(|ed_state| {
+            let spans = crate::visual::selected_lines(ed_state.get_buffer());
+            crate::comment::toggle(ed_state, &spans)
+        })),
+    );
+}
+
+/// <C-a> and <C-x> add the count to a number or subtract it, in visual mode `g<C-a>` and `g<C-x>`
+/// do it more on every line
+pub(crate) fn setup_increment_motions(
+    normal_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    visual_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+) {
+    for (keys, sign) in [("<C-a>", 1), ("<C-x>", -1)] {
+        insert_motion(
+            normal_motion_tree_map,
+            keys,
+            crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                crate::increment::increment(ed_state, sign)
+            })),
+        );
+        for progressive in [false, true] {
+            insert_motion(
+                visual_motion_tree_map,
+                &format!("{}{keys}", if progressive { "g" } else { "" }),
+                crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+                    crate::increment::increment_selected(ed_state, sign, progressive)
+                })),
+            );
+        }
+    }
+}
+
 pub(crate) fn setup_motions(
     normal_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
     insert_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
     visual_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
+    replace_motion_tree_map: &mut crate::motion_interpreter::MotionTreeMap,
 ) {
     setup_normal_motions(normal_motion_tree_map);
+    setup_cursor_motions(normal_motion_tree_map);
     setup_insert_motions(insert_motion_tree_map);
     setup_visual_motions(visual_motion_tree_map);
+    setup_cursor_motions(visual_motion_tree_map);
+    setup_multi_cursor_motions(normal_motion_tree_map, visual_motion_tree_map);
+    setup_jump_motions(normal_motion_tree_map);
+    setup_mark_motions(normal_motion_tree_map);
+    setup_undo_motions(normal_motion_tree_map);
+    setup_register_motions(normal_motion_tree_map, visual_motion_tree_map);
+    setup_visual_operator_motions(normal_motion_tree_map, visual_motion_tree_map);
+    setup_replace_motions(normal_motion_tree_map, replace_motion_tree_map);
+    setup_indent_motions(normal_motion_tree_map, visual_motion_tree_map);
+    setup_comment_motions(normal_motion_tree_map, visual_motion_tree_map);
+    setup_increment_motions(normal_motion_tree_map, visual_motion_tree_map);
 }
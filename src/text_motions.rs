@@ -0,0 +1,434 @@
+use crate::editor_buffer::Buffer;
+use std::rc::Rc;
+
+/// How a motion selects text when it is used as the range of an operator (like in vim)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MotionKind {
+    /// the character the motion ends on is not included
+    Exclusive,
+    /// the character the motion ends on is included
+    Inclusive,
+    /// every line the motion touches is included completely
+    Linewise,
+}
+
+/// How a motion changes the column vertical motions try to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DesiredColumn {
+    /// keep it (vertical motions)
+    Keep,
+    /// stick to the end of the line (`$`)
+    End,
+    /// the column the motion ends on
+    Set,
+}
+
+/// Computes where a motion starting at `offset` ends. `desired_column` is the column vertical
+/// motions aim for and `count` the count typed in front of the motion (if any).
+pub(crate) type MotionFn =
+    fn(buffer: &Buffer, offset: usize, desired_column: usize, count: Option<usize>) -> usize;
+
+pub(crate) struct TextMotion {
+    pub(crate) keys: &'static str,
+    pub(crate) kind: MotionKind,
+    pub(crate) desired_column: DesiredColumn,
+    /// whether the position before the motion is remembered in the jumplist
+    pub(crate) jump: bool,
+    pub(crate) function: MotionFn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Punctuation,
+    Word,
+}
+
+fn char_class(c: char, big_word: bool) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if big_word || c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+fn class_at(buffer: &Buffer, offset: usize, big_word: bool) -> CharClass {
+    buffer
+        .char_at(offset)
+        .map_or(CharClass::Blank, |c| char_class(c, big_word))
+}
+
+/// Offset of the last character of the line containing `offset` (the line start for empty lines)
+pub(crate) fn last_char_of_line(buffer: &Buffer, offset: usize) -> usize {
+    let start = buffer.line_start(offset);
+    let end = buffer.line_end(offset);
+    end.saturating_sub(1).max(start)
+}
+
+/// Offset of the first non-blank character of the line containing `offset`
+pub(crate) fn first_non_blank(buffer: &Buffer, offset: usize) -> usize {
+    let mut offset = buffer.line_start(offset);
+    while matches!(buffer.char_at(offset), Some(' ' | '\t')) {
+        offset += 1;
+    }
+    offset
+}
+
+/// Moves an offset that is on a line break (or past the end) of a non-empty line back onto the
+/// last character of the line, where the cursor has to be in normal mode
+pub(crate) fn clamp_to_line(buffer: &Buffer, offset: usize) -> usize {
+    offset.min(last_char_of_line(buffer, offset))
+}
+
+fn left(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    offset
+        .saturating_sub(count.unwrap_or(1))
+        .max(buffer.line_start(offset))
+}
+
+fn right(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    (offset + count.unwrap_or(1)).min(last_char_of_line(buffer, offset))
+}
+
+fn down(buffer: &Buffer, offset: usize, desired_column: usize, count: Option<usize>) -> usize {
+    let (line, _) = buffer.position(offset);
+    let line = (line + count.unwrap_or(1)).min(buffer.line_count() - 1);
+    buffer.offset(line, desired_column)
+}
+
+fn up(buffer: &Buffer, offset: usize, desired_column: usize, count: Option<usize>) -> usize {
+    let (line, _) = buffer.position(offset);
+    buffer.offset(line.saturating_sub(count.unwrap_or(1)), desired_column)
+}
+
+fn line_start(buffer: &Buffer, offset: usize, _: usize, _: Option<usize>) -> usize {
+    buffer.line_start(offset)
+}
+
+fn line_first_non_blank(buffer: &Buffer, offset: usize, _: usize, _: Option<usize>) -> usize {
+    first_non_blank(buffer, offset)
+}
+
+fn line_end(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    // `3$` goes to the end of the line two lines down
+    let offset = down(buffer, offset, 0, Some(count.unwrap_or(1) - 1));
+    last_char_of_line(buffer, offset)
+}
+
+fn first_line(buffer: &Buffer, _: usize, _: usize, count: Option<usize>) -> usize {
+    let line = count.unwrap_or(1).saturating_sub(1);
+    first_non_blank(buffer, buffer.line_offset(line))
+}
+
+fn last_line(buffer: &Buffer, _: usize, _: usize, count: Option<usize>) -> usize {
+    let line = match count {
+        Some(count) => count.saturating_sub(1),
+        None => buffer.line_count() - 1,
+    };
+    first_non_blank(buffer, buffer.line_offset(line))
+}
+
+fn next_word_start(buffer: &Buffer, offset: usize, big_word: bool) -> usize {
+    let len = buffer.len();
+    let mut offset = offset;
+    let class = class_at(buffer, offset, big_word);
+    if class != CharClass::Blank {
+        while offset < len && class_at(buffer, offset, big_word) == class {
+            offset += 1;
+        }
+    }
+    // skip blanks, an empty line counts as a word of its own though
+    while offset < len {
+        match buffer.char_at(offset) {
+            Some('\n') => {
+                offset += 1;
+                if buffer.char_at(offset) == Some('\n') {
+                    return offset;
+                }
+            }
+            Some(c) if c.is_whitespace() => offset += 1,
+            _ => break,
+        }
+    }
+    offset
+}
+
+fn next_word_end(buffer: &Buffer, offset: usize, big_word: bool) -> usize {
+    let len = buffer.len();
+    let mut offset = offset + 1;
+    while offset < len && class_at(buffer, offset, big_word) == CharClass::Blank {
+        offset += 1;
+    }
+    if offset >= len {
+        return len.saturating_sub(1);
+    }
+    let class = class_at(buffer, offset, big_word);
+    while offset + 1 < len && class_at(buffer, offset + 1, big_word) == class {
+        offset += 1;
+    }
+    offset
+}
+
+fn prev_word_start(buffer: &Buffer, offset: usize, big_word: bool) -> usize {
+    if offset == 0 {
+        return 0;
+    }
+    let mut offset = offset - 1;
+    while offset > 0 && class_at(buffer, offset, big_word) == CharClass::Blank {
+        // an empty line counts as a word of its own
+        if buffer.char_at(offset) == Some('\n') && buffer.char_at(offset - 1) == Some('\n') {
+            return offset;
+        }
+        offset -= 1;
+    }
+    let class = class_at(buffer, offset, big_word);
+    while offset > 0 && class_at(buffer, offset - 1, big_word) == class {
+        offset -= 1;
+    }
+    offset
+}
+
+fn repeat(
+    buffer: &Buffer,
+    offset: usize,
+    count: Option<usize>,
+    big_word: bool,
+    step: fn(&Buffer, usize, bool) -> usize,
+) -> usize {
+    (0..count.unwrap_or(1)).fold(offset, |offset, _| step(buffer, offset, big_word))
+}
+
+fn word_forward(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    repeat(buffer, offset, count, false, next_word_start)
+}
+
+fn big_word_forward(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    repeat(buffer, offset, count, true, next_word_start)
+}
+
+fn word_end(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    repeat(buffer, offset, count, false, next_word_end)
+}
+
+fn big_word_end(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    repeat(buffer, offset, count, true, next_word_end)
+}
+
+fn word_backward(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    repeat(buffer, offset, count, false, prev_word_start)
+}
+
+fn big_word_backward(buffer: &Buffer, offset: usize, _: usize, count: Option<usize>) -> usize {
+    repeat(buffer, offset, count, true, prev_word_start)
+}
+
+pub(crate) const TEXT_MOTIONS: &[TextMotion] = &[
+    TextMotion {
+        keys: "h",
+        kind: MotionKind::Exclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: left,
+    },
+    TextMotion {
+        keys: "l",
+        kind: MotionKind::Exclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: right,
+    },
+    TextMotion {
+        keys: "j",
+        kind: MotionKind::Linewise,
+        desired_column: DesiredColumn::Keep,
+        jump: false,
+        function: down,
+    },
+    TextMotion {
+        keys: "k",
+        kind: MotionKind::Linewise,
+        desired_column: DesiredColumn::Keep,
+        jump: false,
+        function: up,
+    },
+    TextMotion {
+        keys: "w",
+        kind: MotionKind::Exclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: word_forward,
+    },
+    TextMotion {
+        keys: "W",
+        kind: MotionKind::Exclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: big_word_forward,
+    },
+    TextMotion {
+        keys: "b",
+        kind: MotionKind::Exclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: word_backward,
+    },
+    TextMotion {
+        keys: "B",
+        kind: MotionKind::Exclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: big_word_backward,
+    },
+    TextMotion {
+        keys: "e",
+        kind: MotionKind::Inclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: word_end,
+    },
+    TextMotion {
+        keys: "E",
+        kind: MotionKind::Inclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: big_word_end,
+    },
+    TextMotion {
+        keys: "0",
+        kind: MotionKind::Exclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: line_start,
+    },
+    TextMotion {
+        keys: "^",
+        kind: MotionKind::Exclusive,
+        desired_column: DesiredColumn::Set,
+        jump: false,
+        function: line_first_non_blank,
+    },
+    TextMotion {
+        keys: "$",
+        kind: MotionKind::Inclusive,
+        desired_column: DesiredColumn::End,
+        jump: false,
+        function: line_end,
+    },
+    TextMotion {
+        keys: "gg",
+        kind: MotionKind::Linewise,
+        desired_column: DesiredColumn::Set,
+        jump: true,
+        function: first_line,
+    },
+    TextMotion {
+        keys: "G",
+        kind: MotionKind::Linewise,
+        desired_column: DesiredColumn::Set,
+        jump: true,
+        function: last_line,
+    },
+];
+
+/// Creates the motion function moving the cursor with `text_motion`
+pub(crate) fn cursor_motion(
+    text_motion: &'static TextMotion,
+) -> crate::motion_interpreter::MotionFunction {
+    crate::motion_interpreter::MotionFunction(Rc::new(move |ed_state| {
+        let buffer = ed_state.get_buffer();
+        // in visual mode the selections grow, otherwise the cursors just move
+        let visual = ed_state.mode.kind() == crate::editor_state::ModeKind::Visual;
+        let mut selections = buffer.selections.clone();
+        selections.map(|selection| {
+            let target = (text_motion.function)(
+                buffer,
+                selection.head,
+                selection.desired_column,
+                ed_state.count,
+            );
+            let target = clamp_to_line(buffer, target);
+            let desired_column = match text_motion.desired_column {
+                DesiredColumn::Keep => selection.desired_column,
+                DesiredColumn::End => usize::MAX,
+                DesiredColumn::Set => buffer.position(target).1,
+            };
+            crate::selections::Selection {
+                anchor: if visual { selection.anchor } else { target },
+                head: target,
+                desired_column,
+            }
+        });
+        let mut transaction = crate::transaction::Transaction::new();
+        if text_motion.jump {
+            transaction.record_jump();
+        }
+        transaction.selections(selections);
+        crate::editor_state::EditorStateUpdate::Transaction(transaction)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(keys: &str, text: &str, offset: usize, count: Option<usize>) -> usize {
+        let buffer = Buffer::from_text(text);
+        let text_motion = TEXT_MOTIONS
+            .iter()
+            .find(|text_motion| text_motion.keys == keys)
+            .unwrap();
+        clamp_to_line(
+            &buffer,
+            (text_motion.function)(&buffer, offset, buffer.position(offset).1, count),
+        )
+    }
+
+    #[test]
+    fn test_horizontal_motions_stay_on_line() {
+        let text = "ab\ncdef\n";
+        assert_eq!(run("h", text, 3, None), 3);
+        assert_eq!(run("h", text, 5, Some(9)), 3);
+        assert_eq!(run("l", text, 3, Some(9)), 6);
+        assert_eq!(run("l", text, 1, None), 1);
+        assert_eq!(run("$", text, 3, None), 6);
+        assert_eq!(run("0", text, 5, None), 3);
+        assert_eq!(run("^", "  x\n", 0, None), 2);
+    }
+    #[test]
+    fn test_vertical_motions_keep_desired_column() {
+        let buffer = Buffer::from_text("abcdef\nab\n\nabcdef");
+        assert_eq!(down(&buffer, 4, 4, None), 9);
+        assert_eq!(clamp_to_line(&buffer, down(&buffer, 4, 4, None)), 8);
+        assert_eq!(down(&buffer, 4, 4, Some(2)), 10);
+        assert_eq!(down(&buffer, 4, 4, Some(3)), 15);
+        assert_eq!(up(&buffer, 15, usize::MAX, Some(3)), 6);
+        assert_eq!(down(&buffer, 4, 4, Some(100)), 15);
+    }
+    #[test]
+    fn test_word_motions() {
+        let text = "foo.bar  baz\n\nqux";
+        assert_eq!(run("w", text, 0, None), 3);
+        assert_eq!(run("w", text, 3, None), 4);
+        assert_eq!(run("w", text, 4, None), 9);
+        assert_eq!(run("w", text, 9, None), 13);
+        assert_eq!(run("W", text, 0, None), 9);
+        assert_eq!(run("w", text, 0, Some(4)), 13);
+        assert_eq!(run("e", text, 0, None), 2);
+        assert_eq!(run("e", text, 2, None), 3);
+        assert_eq!(run("E", text, 0, None), 6);
+        assert_eq!(run("b", text, 9, None), 4);
+        assert_eq!(run("B", text, 9, None), 0);
+        assert_eq!(run("b", text, 14, None), 13);
+        assert_eq!(run("b", text, 13, None), 9);
+    }
+    #[test]
+    fn test_line_motions() {
+        let text = "a\n  b\nc";
+        assert_eq!(run("gg", text, 6, None), 0);
+        assert_eq!(run("gg", text, 0, Some(2)), 4);
+        assert_eq!(run("G", text, 0, None), 6);
+        assert_eq!(run("G", text, 0, Some(2)), 4);
+    }
+}
@@ -0,0 +1,409 @@
+use crate::editor_state::{EditorMode, EditorState};
+use std::fmt::Write;
+use std::ops::Range;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum TransactionError {
+    #[error("No buffer with index {0}")]
+    BufferIndexOutOfRangeError(usize),
+    #[error("Range {range:?} is out of bounds for buffer {buffer_idx} of length {len}")]
+    RangeOutOfBoundsError {
+        buffer_idx: usize,
+        range: Range<usize>,
+        len: usize,
+    },
+    #[error("{0}")]
+    OptionError(#[from] crate::options::OptionError),
+    #[error("{0}")]
+    RegisterError(#[from] crate::registers::RegisterError),
+}
+
+/// Replaces `range` in buffer `buffer_idx` with `text`. Inserting and deleting are replacements
+/// of an empty range and with an empty text respectively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Edit {
+    pub(crate) buffer_idx: usize,
+    pub(crate) range: Range<usize>,
+    pub(crate) text: Vec<char>,
+}
+
+impl Edit {
+    pub(crate) fn insert(buffer_idx: usize, at: usize, text: &str) -> Self {
+        Self {
+            buffer_idx,
+            range: at..at,
+            text: text.chars().collect(),
+        }
+    }
+
+    pub(crate) fn delete(buffer_idx: usize, range: Range<usize>) -> Self {
+        Self {
+            buffer_idx,
+            range,
+            text: Vec::new(),
+        }
+    }
+
+    pub(crate) fn replace(buffer_idx: usize, range: Range<usize>, text: &str) -> Self {
+        Self {
+            buffer_idx,
+            range,
+            text: text.chars().collect(),
+        }
+    }
+
+    fn apply(self, buffers: &mut [crate::editor_buffer::Buffer]) {
+        buffers[self.buffer_idx].replace(self.range, &self.text);
+    }
+}
+
+/// Where a position in the text ends up after `range` was replaced with `inserted` characters:
+/// positions behind the range move with the text, positions inside a deleted range collapse to
+/// its start
+pub(crate) fn shift_offset(offset: usize, range: &Range<usize>, inserted: usize) -> usize {
+    if offset >= range.end {
+        offset - range.len() + inserted
+    } else if offset > range.start {
+        range.start + (offset - range.start).min(inserted)
+    } else {
+        offset
+    }
+}
+
+pub(crate) enum Operation {
+    Edit(Edit),
+    /// replaces the cursors of the current buffer
+    Selections(crate::selections::Selections),
+    /// enters visual mode with the selection going from `start` to `end` (where the cursor ends up)
+    #[allow(dead_code)]
+    Selection {
+        start: usize,
+        end: usize,
+    },
+    /// remembers the cursor position in the jumplist, put it in front of the cursor operation of
+    /// a jump
+    RecordJump,
+    /// stores yanked (or `deleted`) text in register `name` (`None` for the unnamed register) with
+    /// the rules of `Registers::yank` and `Registers::delete`
+    Yank {
+        name: Option<char>,
+        register: crate::registers::Register,
+        deleted: bool,
+    },
+    Mode(EditorMode),
+    Message(String),
+    Option(crate::options::EditorOption),
+}
+
+/// A batch of changes to the editor state that is applied all at once or not at all. All edits of
+/// a transaction end up in a single undo step per buffer.
+#[derive(Default)]
+pub(crate) struct Transaction {
+    pub(crate) operations: Vec<Operation>,
+}
+
+impl Transaction {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn edit(&mut self, edit: Edit) -> &mut Self {
+        self.operations.push(Operation::Edit(edit));
+        self
+    }
+
+    pub(crate) fn insert(&mut self, buffer_idx: usize, at: usize, text: &str) -> &mut Self {
+        self.edit(Edit::insert(buffer_idx, at, text))
+    }
+
+    pub(crate) fn delete(&mut self, buffer_idx: usize, range: Range<usize>) -> &mut Self {
+        self.edit(Edit::delete(buffer_idx, range))
+    }
+
+    pub(crate) fn replace(
+        &mut self,
+        buffer_idx: usize,
+        range: Range<usize>,
+        text: &str,
+    ) -> &mut Self {
+        self.edit(Edit::replace(buffer_idx, range, text))
+    }
+
+    /// Where `offset` in buffer `buffer_idx` ends up after the edits added so far, for placing the
+    /// cursors of a transaction in offsets from before its edits
+    pub(crate) fn shifted(&self, buffer_idx: usize, offset: usize) -> usize {
+        self.operations
+            .iter()
+            .fold(offset, |offset, operation| match operation {
+                Operation::Edit(edit) if edit.buffer_idx == buffer_idx => {
+                    shift_offset(offset, &edit.range, edit.text.len())
+                }
+                _ => offset,
+            })
+    }
+
+    /// Moves the cursor to `offset`, dropping any other cursors
+    pub(crate) fn cursor(&mut self, offset: usize, desired_column: usize) -> &mut Self {
+        self.selections(crate::selections::Selections::single(
+            crate::selections::Selection::cursor(offset, desired_column),
+        ))
+    }
+
+    pub(crate) fn selections(&mut self, selections: crate::selections::Selections) -> &mut Self {
+        self.operations.push(Operation::Selections(selections));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn selection(&mut self, start: usize, end: usize) -> &mut Self {
+        self.operations.push(Operation::Selection { start, end });
+        self
+    }
+
+    pub(crate) fn record_jump(&mut self) -> &mut Self {
+        self.operations.push(Operation::RecordJump);
+        self
+    }
+
+    pub(crate) fn yank(
+        &mut self,
+        name: Option<char>,
+        register: crate::registers::Register,
+    ) -> &mut Self {
+        self.operations.push(Operation::Yank {
+            name,
+            register,
+            deleted: false,
+        });
+        self
+    }
+
+    /// Like `yank`, for text that is deleted by the transaction
+    pub(crate) fn delete_into(
+        &mut self,
+        name: Option<char>,
+        register: crate::registers::Register,
+    ) -> &mut Self {
+        self.operations.push(Operation::Yank {
+            name,
+            register,
+            deleted: true,
+        });
+        self
+    }
+
+    pub(crate) fn mode(&mut self, mode: EditorMode) -> &mut Self {
+        self.operations.push(Operation::Mode(mode));
+        self
+    }
+
+    pub(crate) fn message(&mut self, message: impl Into<String>) -> &mut Self {
+        self.operations.push(Operation::Message(message.into()));
+        self
+    }
+
+    pub(crate) fn option(&mut self, option: crate::options::EditorOption) -> &mut Self {
+        self.operations.push(Operation::Option(option));
+        self
+    }
+
+    pub(crate) fn set_option(
+        &mut self,
+        name: &str,
+        value: &str,
+    ) -> Result<&mut Self, TransactionError> {
+        let option = crate::options::parse(name, value)?;
+        Ok(self.option(option))
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Checks every edit against the buffer lengths as they will be at the point the edit is
+    /// applied, so applying can not fail halfway through
+    fn validate(&self, ed_state: &EditorState) -> Result<(), TransactionError> {
+        let mut lens = ed_state
+            .buffers
+            .iter()
+            .map(|buffer| buffer.content.len())
+            .collect::<Vec<_>>();
+        for operation in &self.operations {
+            if let Operation::Yank { name, .. } = operation {
+                crate::registers::check_writable(*name)?;
+            }
+            if let Operation::Edit(edit) = operation {
+                let len = lens
+                    .get_mut(edit.buffer_idx)
+                    .ok_or(TransactionError::BufferIndexOutOfRangeError(edit.buffer_idx))?;
+                if edit.range.start > edit.range.end || edit.range.end > *len {
+                    return Err(TransactionError::RangeOutOfBoundsError {
+                        buffer_idx: edit.buffer_idx,
+                        range: edit.range.clone(),
+                        len: *len,
+                    });
+                }
+                *len = *len - edit.range.len() + edit.text.len();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn apply(self, ed_state: &mut EditorState) -> Result<(), TransactionError> {
+        self.validate(ed_state)?;
+        let mut changed = vec![false; ed_state.buffers.len()];
+        for operation in self.operations {
+            match operation {
+                Operation::Edit(edit) => {
+                    let buffer_idx = edit.buffer_idx;
+                    let buffer = &mut ed_state.buffers[buffer_idx];
+                    crate::jumplist::record_change(buffer, edit.range.start);
+                    edit.apply(&mut ed_state.buffers);
+                    changed[buffer_idx] = true;
+                }
+                Operation::Selections(mut selections) => {
+                    let buffer = ed_state.get_buffer_mut();
+                    let len = buffer.len();
+                    selections.map(|selection| crate::selections::Selection {
+                        anchor: selection.anchor.min(len),
+                        head: selection.head.min(len),
+                        ..selection
+                    });
+                    buffer.selections = selections;
+                }
+                Operation::Selection { start, end } => {
+                    let buffer = ed_state.get_buffer_mut();
+                    let (start, end) = (start.min(buffer.len()), end.min(buffer.len()));
+                    buffer.selections = crate::selections::Selections::single(
+                        crate::selections::Selection {
+                            anchor: start,
+                            head: end,
+                            desired_column: buffer.position(end).1,
+                        },
+                    );
+                    ed_state.mode = EditorMode::new_visual();
+                }
+                Operation::RecordJump => crate::jumplist::record_jump(ed_state),
+                Operation::Yank {
+                    name,
+                    register,
+                    deleted,
+                } => {
+                    let text = register.text.clone();
+                    if deleted {
+                        ed_state.registers.delete(name, register);
+                    } else {
+                        ed_state.registers.yank(name, register);
+                    }
+                    if let Some(name @ ('+' | '*')) = name {
+                        crate::clipboard::copy(ed_state, name, &text);
+                    }
+                }
+                Operation::Mode(mode) => ed_state.mode = mode,
+                Operation::Message(message) => {
+                    writeln!(&mut ed_state.command_line, "{}", message)
+                        .expect("Fatal: Could not write to command line buffer");
+                }
+                Operation::Option(option) => {
+                    crate::options::apply(&mut ed_state.editor_globals, option)
+                }
+            }
+        }
+        for (buffer, changed) in ed_state.buffers.iter_mut().zip(changed) {
+            if changed {
+                buffer.change_tick += 1;
+                buffer.finish_undo_step();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_ed_state() -> EditorState {
+        EditorState::new_normal(
+            crate::editor_state::TermInfo { rows: 24, cols: 80 },
+            crate::editor_state::EditorGlobals::default(),
+        )
+    }
+
+    fn buffer_text(ed_state: &EditorState) -> String {
+        ed_state.get_buffer().content.iter().collect()
+    }
+
+    #[test]
+    fn test_apply_edits_in_order() {
+        let mut ed_state = new_ed_state();
+        let mut transaction = Transaction::new();
+        transaction
+            .insert(0, 0, "hello world")
+            .replace(0, 0..5, "goodbye")
+            .delete(0, 7..13)
+            .insert(0, 7, "!")
+            .mode(EditorMode::Insert);
+        transaction.apply(&mut ed_state).unwrap();
+        assert_eq!(buffer_text(&ed_state), "goodbye!");
+        assert!(matches!(ed_state.mode, EditorMode::Insert));
+    }
+    #[test]
+    fn test_invalid_transaction_changes_nothing() {
+        let mut ed_state = new_ed_state();
+        let mut transaction = Transaction::new();
+        transaction
+            .insert(0, 0, "abc")
+            .mode(EditorMode::Insert)
+            .delete(0, 2..4);
+        assert!(matches!(
+            transaction.apply(&mut ed_state),
+            Err(TransactionError::RangeOutOfBoundsError { len: 3, .. })
+        ));
+        assert_eq!(buffer_text(&ed_state), "");
+        assert!(matches!(ed_state.mode, EditorMode::Normal));
+        assert_eq!(ed_state.get_buffer().undo.newest(), 0);
+    }
+    #[test]
+    fn test_records_one_undo_step() {
+        let mut ed_state = new_ed_state();
+        let mut transaction = Transaction::new();
+        transaction.insert(0, 0, "one two");
+        transaction.apply(&mut ed_state).unwrap();
+        let mut transaction = Transaction::new();
+        transaction.replace(0, 0..3, "1").insert(0, 1, "+");
+        transaction.apply(&mut ed_state).unwrap();
+        assert_eq!(buffer_text(&ed_state), "1+ two");
+        assert_eq!(ed_state.get_buffer().undo.newest(), 2);
+
+        let buffer = ed_state.get_buffer_mut();
+        crate::undo::run(buffer, crate::undo::UndoCommand::Undo(1)).unwrap();
+        assert_eq!(buffer_text(&ed_state), "one two");
+    }
+    #[test]
+    fn test_cursor_follows_edits() {
+        let mut ed_state = new_ed_state();
+        let mut transaction = Transaction::new();
+        transaction.insert(0, 0, "hello world").cursor(6, 6);
+        transaction.apply(&mut ed_state).unwrap();
+        let mut transaction = Transaction::new();
+        transaction.replace(0, 0..5, "hi").delete(0, 3..5);
+        transaction.apply(&mut ed_state).unwrap();
+        assert_eq!(buffer_text(&ed_state), "hi rld");
+        assert_eq!(ed_state.get_buffer().cursor(), 3);
+    }
+    #[test]
+    fn test_option_and_message() {
+        let mut ed_state = new_ed_state();
+        let mut transaction = Transaction::new();
+        assert!(transaction.set_option("nosuchoption", "1").is_err());
+        transaction
+            .set_option("tabsize", "2")
+            .unwrap()
+            .message("tabs are two spaces now");
+        transaction.apply(&mut ed_state).unwrap();
+        assert_eq!(ed_state.editor_globals.tab_size, 2);
+        assert_eq!(ed_state.command_line.buffer, "tabs are two spaces now\n");
+    }
+}
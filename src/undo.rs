@@ -0,0 +1,456 @@
+use crate::editor_buffer::Buffer;
+use crate::selections::Selections;
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum UndoError {
+    #[error("Already at oldest change")]
+    OldestChangeError,
+    #[error("Already at newest change")]
+    NewestChangeError,
+    #[error("Invalid argument: {0}")]
+    InvalidStepError(String),
+}
+
+/// `range` (in the text as it was before the edit) was replaced: `old` is the text that was there
+/// and `new` the text that replaced it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct UndoEdit {
+    pub(crate) range: Range<usize>,
+    pub(crate) old: Vec<char>,
+    pub(crate) new: Vec<char>,
+}
+
+/// A state of the buffer, reached from its parent state with `edits`
+#[derive(Debug, Clone)]
+pub(crate) struct UndoNode {
+    pub(crate) parent: Option<usize>,
+    /// the child redo goes to, the one that was created or visited last
+    pub(crate) redo_child: Option<usize>,
+    pub(crate) edits: Vec<UndoEdit>,
+    pub(crate) cursor_before: Selections,
+    pub(crate) cursor_after: Selections,
+    pub(crate) time: SystemTime,
+}
+
+/// How far `:earlier`/`:later` go
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TimeStep {
+    Changes(usize),
+    Duration(Duration),
+    Writes(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UndoCommand {
+    /// `u`, goes up the tree
+    Undo(usize),
+    /// <C-r>, goes down the tree along the branch that was used last
+    Redo(usize),
+    /// `g-` and `:earlier`, go back in time across branches
+    Earlier(TimeStep),
+    /// `g+` and `:later`
+    Later(TimeStep),
+}
+
+/// The undo history of a buffer. Every node is a state of the text, its index is its sequence
+/// number (so the nodes are in the order they were created) and node 0 is the text before any
+/// change. Making a change after undoing starts a new branch, so nothing is ever lost.
+#[derive(Debug, Clone)]
+pub(crate) struct UndoTree {
+    pub(crate) nodes: Vec<UndoNode>,
+    pub(crate) current: usize,
+    /// sequence numbers of the states the buffer was written in, in the order of the writes
+    pub(crate) writes: Vec<usize>,
+    /// edits that are not part of a node yet and the cursors from before the first one
+    pending: Option<(Vec<UndoEdit>, Selections)>,
+    /// while an insert session runs, all its edits go into one node
+    grouping: bool,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self {
+            nodes: vec![UndoNode {
+                parent: None,
+                redo_child: None,
+                edits: Vec::new(),
+                cursor_before: Selections::default(),
+                cursor_after: Selections::default(),
+                time: SystemTime::now(),
+            }],
+            current: 0,
+            writes: Vec::new(),
+            pending: None,
+            grouping: false,
+        }
+    }
+}
+
+impl UndoTree {
+    /// Remembers an edit for the next undo step, `selections` are the cursors before the edit
+    pub(crate) fn record(&mut self, edit: UndoEdit, selections: &Selections) {
+        self.pending
+            .get_or_insert_with(|| (Vec::new(), selections.clone()))
+            .0
+            .push(edit);
+    }
+
+    /// Turns the recorded edits into an undo step (unless an insert session is grouping them),
+    /// `selections` are the cursors after the edits
+    pub(crate) fn commit(&mut self, selections: &Selections) {
+        if self.grouping {
+            return;
+        }
+        let Some((edits, cursor_before)) = self.pending.take() else {
+            return;
+        };
+        self.nodes.push(UndoNode {
+            parent: Some(self.current),
+            redo_child: None,
+            edits,
+            cursor_before,
+            cursor_after: selections.clone(),
+            time: SystemTime::now(),
+        });
+        let seq = self.nodes.len() - 1;
+        self.nodes[self.current].redo_child = Some(seq);
+        self.current = seq;
+    }
+
+    /// Starts collecting the edits of an insert session into one step. With `reopen_last` the step
+    /// that was just made (by the command that started the session, like `cw`) is continued.
+    pub(crate) fn start_group(&mut self, reopen_last: bool) {
+        if reopen_last && self.pending.is_none() && self.current == self.nodes.len() - 1 {
+            if let Some(parent) = self.nodes[self.current].parent {
+                let node = self.nodes.pop().expect("the current node exists");
+                self.nodes[parent].redo_child = None;
+                self.current = parent;
+                self.pending = Some((node.edits, node.cursor_before));
+            }
+        }
+        self.grouping = true;
+    }
+
+    pub(crate) fn end_group(&mut self, selections: &Selections) {
+        self.grouping = false;
+        self.commit(selections);
+    }
+
+    /// Remembers that the buffer was written in its current state
+    pub(crate) fn mark_written(&mut self) {
+        self.writes.retain(|&seq| seq != self.current);
+        self.writes.push(self.current);
+    }
+
+    /// The sequence number of the newest state
+    pub(crate) fn newest(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// How many changes lead from the original text to `seq`
+    pub(crate) fn depth(&self, seq: usize) -> usize {
+        std::iter::successors(Some(seq), |&seq| self.nodes[seq].parent).count() - 1
+    }
+
+    /// The nodes to undo (in order) and the ones to redo (in order) to get from the current state
+    /// to `target`
+    fn path_to(&self, target: usize) -> (Vec<usize>, Vec<usize>) {
+        let ancestors =
+            std::iter::successors(Some(self.current), |&seq| self.nodes[seq].parent)
+                .collect::<Vec<_>>();
+        let mut redo = Vec::new();
+        let mut seq = target;
+        while !ancestors.contains(&seq) {
+            redo.push(seq);
+            seq = self.nodes[seq]
+                .parent
+                .expect("the root is an ancestor of every node");
+        }
+        redo.reverse();
+        let undo = ancestors.into_iter().take_while(|&ancestor| ancestor != seq).collect();
+        (undo, redo)
+    }
+
+    /// The sequence number `step` leads to, going back in time if `earlier`
+    fn step_target(&self, step: TimeStep, earlier: bool) -> usize {
+        let current = self.current;
+        match step {
+            TimeStep::Changes(count) if earlier => current.saturating_sub(count),
+            TimeStep::Changes(count) => current.saturating_add(count).min(self.newest()),
+            TimeStep::Duration(duration) => {
+                let time = self.nodes[current].time;
+                let target_time = if earlier {
+                    time.checked_sub(duration).unwrap_or(SystemTime::UNIX_EPOCH)
+                } else {
+                    // past the end of time, so every state is older
+                    match time.checked_add(duration) {
+                        Some(target_time) => target_time,
+                        None => return self.newest(),
+                    }
+                };
+                // the newest state that already existed at that time
+                let target = self
+                    .nodes
+                    .iter()
+                    .rposition(|node| node.time <= target_time)
+                    .unwrap_or(0);
+                if earlier {
+                    target.min(current.saturating_sub(1))
+                } else {
+                    target.max(current + 1).min(self.newest())
+                }
+            }
+            TimeStep::Writes(count) if earlier => {
+                // with changes since the last write the first step goes back to that write
+                let older = self.writes.iter().filter(|&&seq| seq < current).count();
+                match older.checked_sub(count) {
+                    Some(idx) => self.writes[idx],
+                    None => 0,
+                }
+            }
+            TimeStep::Writes(count) => {
+                let newer = self
+                    .writes
+                    .iter()
+                    .filter(|&&seq| seq > current)
+                    .copied()
+                    .collect::<Vec<_>>();
+                newer
+                    .get(count.saturating_sub(1))
+                    .copied()
+                    .unwrap_or(self.newest())
+            }
+        }
+    }
+}
+
+/// Parses the argument of `:earlier`/`:later`: a count of changes, a duration like `10s`, `5m`,
+/// `1h` or `2d`, or a count of file writes like `1f`
+pub(crate) fn parse_time_step(arg: &str) -> Result<TimeStep, UndoError> {
+    let arg = arg.trim();
+    if arg.is_empty() {
+        return Ok(TimeStep::Changes(1));
+    }
+    let invalid = || UndoError::InvalidStepError(arg.to_string());
+    let (number, unit) = match arg.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => arg.split_at(idx),
+        None => (arg, ""),
+    };
+    let count = number.parse::<u64>().map_err(|_| invalid())?;
+    let seconds = |factor: u64| match count.checked_mul(factor) {
+        Some(seconds) => Ok(TimeStep::Duration(Duration::from_secs(seconds))),
+        None => Err(invalid()),
+    };
+    match unit {
+        "" => Ok(TimeStep::Changes(count as usize)),
+        "s" => seconds(1),
+        "m" => seconds(60),
+        "h" => seconds(60 * 60),
+        "d" => seconds(24 * 60 * 60),
+        "f" => Ok(TimeStep::Writes(count as usize)),
+        _ => Err(invalid()),
+    }
+}
+
+fn undo_node(buffer: &mut Buffer, seq: usize) {
+    for edit in buffer.undo.nodes[seq].edits.clone().into_iter().rev() {
+        let range = edit.range.start..edit.range.start + edit.new.len();
+        buffer.replace_unrecorded(range, &edit.old);
+    }
+    let parent = buffer.undo.nodes[seq].parent.expect("the root is never undone");
+    buffer.undo.nodes[parent].redo_child = Some(seq);
+    buffer.undo.current = parent;
+}
+
+fn redo_node(buffer: &mut Buffer, seq: usize) {
+    for edit in buffer.undo.nodes[seq].edits.clone() {
+        buffer.replace_unrecorded(edit.range, &edit.new);
+    }
+    buffer.undo.current = seq;
+}
+
+/// Brings the buffer into state `target` and puts the cursors where they were at that change
+fn go_to(buffer: &mut Buffer, target: usize) {
+    let (undo, redo) = buffer.undo.path_to(target);
+    let mut selections = None;
+    for &seq in &undo {
+        undo_node(buffer, seq);
+        selections = Some(buffer.undo.nodes[seq].cursor_before.clone());
+    }
+    for &seq in &redo {
+        redo_node(buffer, seq);
+        selections = Some(buffer.undo.nodes[seq].cursor_after.clone());
+    }
+    buffer.change_tick += 1;
+    if let Some(mut selections) = selections {
+        let len = buffer.len();
+        selections.map(|selection| crate::selections::Selection {
+            anchor: selection.anchor.min(len),
+            head: selection.head.min(len),
+            ..selection
+        });
+        buffer.selections = selections;
+    }
+}
+
+pub(crate) fn run(buffer: &mut Buffer, command: UndoCommand) -> Result<(), UndoError> {
+    // anything that was not made into a step yet is undone first, also the edits of an insert
+    // session that is still going on (its later edits are a new step)
+    let grouping = buffer.undo.grouping;
+    buffer.undo.end_group(&buffer.selections);
+    let result = go_to_command(buffer, command);
+    if grouping {
+        buffer.undo.start_group(false);
+    }
+    result
+}
+
+fn go_to_command(buffer: &mut Buffer, command: UndoCommand) -> Result<(), UndoError> {
+    let tree = &buffer.undo;
+    let target = match command {
+        UndoCommand::Undo(_) | UndoCommand::Earlier(_) if tree.current == 0 => {
+            return Err(UndoError::OldestChangeError)
+        }
+        UndoCommand::Redo(_) if tree.nodes[tree.current].redo_child.is_none() => {
+            return Err(UndoError::NewestChangeError)
+        }
+        UndoCommand::Later(_) if tree.current == tree.newest() => {
+            return Err(UndoError::NewestChangeError)
+        }
+        UndoCommand::Undo(count) => (0..count).fold(tree.current, |seq, _| {
+            tree.nodes[seq].parent.unwrap_or(seq)
+        }),
+        UndoCommand::Redo(count) => (0..count).fold(tree.current, |seq, _| {
+            tree.nodes[seq].redo_child.unwrap_or(seq)
+        }),
+        UndoCommand::Earlier(step) => tree.step_target(step, true),
+        UndoCommand::Later(step) => tree.step_target(step, false),
+    };
+    go_to(buffer, target);
+    Ok(())
+}
+
+fn time_ago(time: SystemTime) -> String {
+    let seconds = time.elapsed().unwrap_or_default().as_secs();
+    match seconds {
+        0..=99 => format!("{} seconds ago", seconds),
+        100..=5999 => format!("{} minutes ago", seconds / 60),
+        6000..=172799 => format!("{} hours ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+/// `:undolist`, one line for every leaf of the tree
+pub(crate) fn list(buffer: &Buffer) -> String {
+    let tree = &buffer.undo;
+    let leaves = (1..tree.nodes.len())
+        .filter(|&seq| !tree.nodes.iter().any(|node| node.parent == Some(seq)))
+        .collect::<Vec<_>>();
+    if leaves.is_empty() {
+        return "Nothing to undo".to_string();
+    }
+    let mut lines = vec!["number changes  when               saved".to_string()];
+    for seq in leaves {
+        let saved = match tree.writes.iter().position(|&written| written == seq) {
+            Some(write) => format!("{}", write + 1),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "{:>6} {:>7}  {:<18} {}",
+            seq,
+            tree.depth(seq),
+            time_ago(tree.nodes[seq].time),
+            saved
+        ));
+    }
+    lines
+        .iter()
+        .map(|line| line.trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(buffer: &Buffer) -> String {
+        buffer.content.iter().collect()
+    }
+
+    fn change(buffer: &mut Buffer, at: usize, text: &str) {
+        buffer.replace(at..at, &text.chars().collect::<Vec<_>>());
+        buffer.finish_undo_step();
+    }
+
+    #[test]
+    fn test_undo_closes_insert_group() {
+        let mut buffer = Buffer::new();
+        change(&mut buffer, 0, "a");
+        buffer.undo.start_group(false);
+        change(&mut buffer, 1, "b");
+        run(&mut buffer, UndoCommand::Undo(1)).unwrap();
+        assert_eq!(text(&buffer), "a");
+        change(&mut buffer, 1, "c");
+        buffer.undo.end_group(&buffer.selections);
+        run(&mut buffer, UndoCommand::Undo(1)).unwrap();
+        assert_eq!(text(&buffer), "a");
+    }
+    #[test]
+    fn test_branches_are_kept() {
+        let mut buffer = Buffer::new();
+        change(&mut buffer, 0, "a");
+        change(&mut buffer, 1, "b");
+        run(&mut buffer, UndoCommand::Undo(1)).unwrap();
+        change(&mut buffer, 1, "c");
+        assert_eq!(text(&buffer), "ac");
+        // redo follows the new branch, g- goes back to the old one
+        assert!(run(&mut buffer, UndoCommand::Redo(1)).is_err());
+        run(&mut buffer, UndoCommand::Earlier(TimeStep::Changes(1))).unwrap();
+        assert_eq!(text(&buffer), "ab");
+        run(&mut buffer, UndoCommand::Undo(5)).unwrap();
+        assert_eq!(text(&buffer), "");
+        assert!(run(&mut buffer, UndoCommand::Undo(1)).is_err());
+        run(&mut buffer, UndoCommand::Redo(2)).unwrap();
+        assert_eq!(text(&buffer), "ab");
+        run(&mut buffer, UndoCommand::Later(TimeStep::Changes(1))).unwrap();
+        assert_eq!(text(&buffer), "ac");
+    }
+    #[test]
+    fn test_earlier_writes_and_durations() {
+        let mut buffer = Buffer::new();
+        change(&mut buffer, 0, "a");
+        buffer.undo.mark_written();
+        change(&mut buffer, 1, "b");
+        change(&mut buffer, 2, "c");
+        run(&mut buffer, UndoCommand::Earlier(TimeStep::Writes(1))).unwrap();
+        assert_eq!(text(&buffer), "a");
+        run(&mut buffer, UndoCommand::Earlier(TimeStep::Writes(1))).unwrap();
+        assert_eq!(text(&buffer), "");
+        run(&mut buffer, UndoCommand::Later(TimeStep::Writes(1))).unwrap();
+        assert_eq!(text(&buffer), "a");
+        // all changes were made just now, so going a minute back reaches the original text
+        let minute = parse_time_step("1m").unwrap();
+        run(&mut buffer, UndoCommand::Later(minute)).unwrap();
+        assert_eq!(text(&buffer), "abc");
+        run(&mut buffer, UndoCommand::Earlier(minute)).unwrap();
+        assert_eq!(text(&buffer), "");
+        assert!(parse_time_step("5x").is_err());
+    }
+    #[test]
+    fn test_huge_time_steps() {
+        let mut buffer = Buffer::new();
+        change(&mut buffer, 0, "a");
+        change(&mut buffer, 1, "b");
+        assert!(parse_time_step("300000000000000d").is_err());
+        run(&mut buffer, UndoCommand::Earlier(TimeStep::Changes(2))).unwrap();
+        let forever = parse_time_step("10000000000000000000s").unwrap();
+        run(&mut buffer, UndoCommand::Later(forever)).unwrap();
+        assert_eq!(text(&buffer), "ab");
+        run(&mut buffer, UndoCommand::Earlier(TimeStep::Changes(2))).unwrap();
+        run(&mut buffer, UndoCommand::Later(TimeStep::Changes(usize::MAX))).unwrap();
+        assert_eq!(text(&buffer), "ab");
+    }
+}
@@ -0,0 +1,405 @@
+use crate::editor_buffer::Buffer;
+use crate::selections::{Selection, Selections};
+use crate::undo::{UndoEdit, UndoNode, UndoTree};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Bumped whenever the format changes, files of other versions are not read
+const VERSION: usize = 1;
+const MAGIC: &str = "miv-undo";
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum UndoFileError {
+    #[error("Undo file version {0} is not supported")]
+    VersionError(usize),
+    #[error("Undo file is corrupt")]
+    CorruptUndoFileError,
+    #[error("Undo history is too large to save ({0} KiB)")]
+    TooLargeError(usize),
+    #[error("{0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// FNV-1a, the hash has to stay the same across builds so the std hashers can not be used
+fn content_hash(buffer: &Buffer) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    let mut bytes = [0; 4];
+    for c in buffer.content.iter() {
+        for &byte in c.encode_utf8(&mut bytes).as_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// Where the undo file of `path` goes: `.name.un~` next to it with an `undodir` of `.`, otherwise
+/// the full path with `/` replaced by `%` in `undodir` (like vim). `None` if undo files are off.
+pub(crate) fn undo_file_path(path: &Path, undo_dir: &str) -> Option<PathBuf> {
+    if undo_dir.is_empty() {
+        return None;
+    }
+    if undo_dir == "." {
+        let name = path.file_name()?.to_string_lossy();
+        return Some(path.with_file_name(format!(".{}.un~", name)));
+    }
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = path.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "%");
+    Some(Path::new(undo_dir).join(name))
+}
+
+fn write_text(out: &mut String, text: &[char]) {
+    write!(out, " {}:", text.len()).expect("writing to a string can not fail");
+    out.extend(text);
+}
+
+fn write_option(out: &mut String, value: Option<usize>) {
+    match value {
+        Some(value) => write!(out, " {}", value),
+        None => write!(out, " -"),
+    }
+    .expect("writing to a string can not fail");
+}
+
+fn write_selections(out: &mut String, selections: &Selections) {
+    write!(out, "\n{} {}", selections.len(), selections.primary_index())
+        .expect("writing to a string can not fail");
+    for selection in selections.iter() {
+        write!(
+            out,
+            " {} {} {}",
+            selection.anchor, selection.head, selection.desired_column
+        )
+        .expect("writing to a string can not fail");
+    }
+}
+
+/// Serialises the undo tree of `buffer`. The format is text: a header line with the version
+/// followed by numbers and texts (as `length:chars`, so they can hold any character) separated by
+/// whitespace.
+pub(crate) fn serialize(buffer: &Buffer) -> String {
+    let tree = &buffer.undo;
+    let mut out = format!("{} {}\n{:016x}", MAGIC, VERSION, content_hash(buffer));
+    write!(out, "\n{} {}", tree.current, tree.writes.len())
+        .expect("writing to a string can not fail");
+    for seq in &tree.writes {
+        write!(out, " {}", seq).expect("writing to a string can not fail");
+    }
+    write!(out, "\n{}", tree.nodes.len()).expect("writing to a string can not fail");
+    for node in &tree.nodes {
+        let time = node
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        out.push('\n');
+        write_option(&mut out, node.parent);
+        write_option(&mut out, node.redo_child);
+        write!(out, " {} {}", time.as_secs(), time.subsec_nanos())
+            .expect("writing to a string can not fail");
+        write_selections(&mut out, &node.cursor_before);
+        write_selections(&mut out, &node.cursor_after);
+        write!(out, "\n{}", node.edits.len()).expect("writing to a string can not fail");
+        for edit in &node.edits {
+            write!(out, "\n{} {}", edit.range.start, edit.range.end)
+                .expect("writing to a string can not fail");
+            write_text(&mut out, &edit.old);
+            write_text(&mut out, &edit.new);
+        }
+    }
+    out.push('\n');
+    out
+}
+
+/// Reads the tokens written by `serialize`
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Reader<'_> {
+    fn word(&mut self) -> Result<String, UndoFileError> {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut word = String::new();
+        while let Some(c) = self.chars.next_if(|c| !c.is_whitespace() && *c != ':') {
+            word.push(c);
+        }
+        if word.is_empty() {
+            return Err(UndoFileError::CorruptUndoFileError);
+        }
+        Ok(word)
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, UndoFileError> {
+        self.word()?
+            .parse()
+            .map_err(|_| UndoFileError::CorruptUndoFileError)
+    }
+
+    fn option(&mut self) -> Result<Option<usize>, UndoFileError> {
+        match self.word()?.as_str() {
+            "-" => Ok(None),
+            word => Ok(Some(
+                word.parse().map_err(|_| UndoFileError::CorruptUndoFileError)?,
+            )),
+        }
+    }
+
+    fn text(&mut self) -> Result<Vec<char>, UndoFileError> {
+        let len = self.number::<usize>()?;
+        if self.chars.next() != Some(':') {
+            return Err(UndoFileError::CorruptUndoFileError);
+        }
+        let text = self.chars.by_ref().take(len).collect::<Vec<_>>();
+        if text.len() != len {
+            return Err(UndoFileError::CorruptUndoFileError);
+        }
+        Ok(text)
+    }
+
+    fn selections(&mut self) -> Result<Selections, UndoFileError> {
+        let len = self.number::<usize>()?;
+        let primary = self.number::<usize>()?;
+        let selections = (0..len)
+            .map(|_| {
+                Ok(Selection {
+                    anchor: self.number()?,
+                    head: self.number()?,
+                    desired_column: self.number()?,
+                })
+            })
+            .collect::<Result<Vec<_>, UndoFileError>>()?;
+        if primary >= selections.len() {
+            return Err(UndoFileError::CorruptUndoFileError);
+        }
+        Ok(Selections::new(selections, primary))
+    }
+}
+
+/// Undoes the edits of `node` in `text`, false if they do not fit it
+fn undo_edits(text: &mut Vec<char>, node: &UndoNode) -> bool {
+    for edit in node.edits.iter().rev() {
+        let range = edit.range.start..edit.range.start + edit.new.len();
+        if text.get(range.clone()) != Some(&edit.new[..]) {
+            return false;
+        }
+        text.splice(range, edit.old.iter().copied());
+    }
+    true
+}
+
+/// Redoes the edits of `node` in `text`, false if they do not fit it
+fn redo_edits(text: &mut Vec<char>, node: &UndoNode) -> bool {
+    for edit in &node.edits {
+        if text.get(edit.range.clone()) != Some(&edit.old[..]) {
+            return false;
+        }
+        text.splice(edit.range.clone(), edit.new.iter().copied());
+    }
+    true
+}
+
+/// Whether the edits of every node fit the text of its parent, so undo and redo can not run out
+/// of the text. The tree is walked from `text`, the text of the state `current`.
+fn edits_fit(nodes: &[UndoNode], current: usize, mut text: Vec<char>) -> bool {
+    let mut seq = current;
+    while let Some(parent) = nodes[seq].parent {
+        if !undo_edits(&mut text, &nodes[seq]) {
+            return false;
+        }
+        seq = parent;
+    }
+    let mut children = vec![Vec::new(); nodes.len()];
+    for (seq, node) in nodes.iter().enumerate() {
+        if let Some(parent) = node.parent {
+            children[parent].push(seq);
+        }
+    }
+    // (node, whether its children are done), the text is the one of the node on top
+    let mut stack = children[0].iter().map(|&seq| (seq, false)).collect::<Vec<_>>();
+    while let Some((seq, done)) = stack.pop() {
+        if done {
+            undo_edits(&mut text, &nodes[seq]);
+            continue;
+        }
+        if !redo_edits(&mut text, &nodes[seq]) {
+            return false;
+        }
+        stack.push((seq, true));
+        stack.extend(children[seq].iter().map(|&child| (child, false)));
+    }
+    true
+}
+
+/// Parses an undo file, `None` if it belongs to a different version of the text than `buffer`
+pub(crate) fn deserialize(buffer: &Buffer, text: &str) -> Result<Option<UndoTree>, UndoFileError> {
+    let mut reader = Reader {
+        chars: text.chars().peekable(),
+    };
+    if reader.word()? != MAGIC {
+        return Err(UndoFileError::CorruptUndoFileError);
+    }
+    let version = reader.number()?;
+    if version != VERSION {
+        return Err(UndoFileError::VersionError(version));
+    }
+    let hash = u64::from_str_radix(&reader.word()?, 16)
+        .map_err(|_| UndoFileError::CorruptUndoFileError)?;
+    if hash != content_hash(buffer) {
+        return Ok(None);
+    }
+    let current = reader.number::<usize>()?;
+    let n_writes = reader.number::<usize>()?;
+    let writes = (0..n_writes)
+        .map(|_| reader.number())
+        .collect::<Result<Vec<usize>, _>>()?;
+    let n_nodes = reader.number::<usize>()?;
+    let mut nodes = Vec::new();
+    for seq in 0..n_nodes {
+        let parent = reader.option()?;
+        let redo_child = reader.option()?;
+        // parents come before their children, so the tree can not have cycles
+        if parent.is_some_and(|parent| parent >= seq) || (seq == 0) != parent.is_none() {
+            return Err(UndoFileError::CorruptUndoFileError);
+        }
+        let (secs, nanos) = (reader.number()?, reader.number::<u32>()?);
+        // more nanoseconds would carry over into the seconds (and overflow them)
+        if nanos >= 1_000_000_000 {
+            return Err(UndoFileError::CorruptUndoFileError);
+        }
+        let time = SystemTime::UNIX_EPOCH
+            .checked_add(Duration::new(secs, nanos))
+            .ok_or(UndoFileError::CorruptUndoFileError)?;
+        let cursor_before = reader.selections()?;
+        let cursor_after = reader.selections()?;
+        let n_edits = reader.number::<usize>()?;
+        let edits = (0..n_edits)
+            .map(|_| {
+                let range = reader.number()?..reader.number()?;
+                Ok(UndoEdit {
+                    range,
+                    old: reader.text()?,
+                    new: reader.text()?,
+                })
+            })
+            .collect::<Result<Vec<_>, UndoFileError>>()?;
+        nodes.push(UndoNode {
+            parent,
+            redo_child,
+            edits,
+            cursor_before,
+            cursor_after,
+            time,
+        });
+    }
+    let in_range = |seq: &usize| *seq < nodes.len();
+    let is_child = |(seq, node): (usize, &UndoNode)| {
+        node.redo_child
+            .is_none_or(|child| nodes.get(child).is_some_and(|child| child.parent == Some(seq)))
+    };
+    if nodes.is_empty()
+        || !in_range(&current)
+        || !writes.iter().all(in_range)
+        || !nodes.iter().enumerate().all(is_child)
+        || !edits_fit(&nodes, current, buffer.content.iter().copied().collect())
+    {
+        return Err(UndoFileError::CorruptUndoFileError);
+    }
+    let mut tree = UndoTree::default();
+    tree.nodes = nodes;
+    tree.current = current;
+    tree.writes = writes;
+    Ok(Some(tree))
+}
+
+/// Writes the undo tree of `buffer` to the undo file of `path`, after the buffer was written there
+pub(crate) fn save(
+    buffer: &Buffer,
+    path: &Path,
+    editor_globals: &crate::editor_state::EditorGlobals,
+) -> Result<(), UndoFileError> {
+    let Some(undo_path) = undo_file_path(path, &editor_globals.undo_dir) else {
+        return Ok(());
+    };
+    let text = serialize(buffer);
+    let size = text.len().div_ceil(1024);
+    if size > editor_globals.max_undo_size {
+        // an old undo file would not match the text anymore anyway
+        let _ = std::fs::remove_file(&undo_path);
+        return Err(UndoFileError::TooLargeError(size));
+    }
+    std::fs::write(undo_path, text)?;
+    Ok(())
+}
+
+/// Restores the undo tree of a buffer that was just loaded from `path`. Returns whether there was
+/// an undo file for exactly this text.
+pub(crate) fn load(
+    buffer: &mut Buffer,
+    path: &Path,
+    editor_globals: &crate::editor_state::EditorGlobals,
+) -> Result<bool, UndoFileError> {
+    let Some(undo_path) = undo_file_path(path, &editor_globals.undo_dir) else {
+        return Ok(false);
+    };
+    let size = match std::fs::metadata(&undo_path) {
+        Ok(metadata) => metadata.len().div_ceil(1024) as usize,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error.into()),
+    };
+    if size > editor_globals.max_undo_size {
+        return Err(UndoFileError::TooLargeError(size));
+    }
+    let text = std::fs::read_to_string(undo_path)?;
+    match deserialize(buffer, &text)? {
+        Some(tree) => {
+            buffer.undo = tree;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut buffer = Buffer::new();
+        buffer.replace(0..0, &"one\ntwo".chars().collect::<Vec<_>>());
+        buffer.finish_undo_step();
+        buffer.replace(3..4, &[' ', ':']);
+        buffer.finish_undo_step();
+        buffer.undo.mark_written();
+        let text = serialize(&buffer);
+        let mut loaded = Buffer::from_text("one :two");
+        loaded.undo = deserialize(&loaded, &text).unwrap().unwrap();
+        crate::undo::run(&mut loaded, crate::undo::UndoCommand::Undo(1)).unwrap();
+        assert_eq!(loaded.content.iter().collect::<String>(), "one\ntwo");
+        crate::undo::run(&mut loaded, crate::undo::UndoCommand::Undo(1)).unwrap();
+        assert_eq!(loaded.content.iter().collect::<String>(), "");
+        // the file was changed behind our back, so the history does not fit it
+        assert!(deserialize(&Buffer::from_text("one two"), &text).unwrap().is_none());
+        let written = Buffer::from_text("one :two");
+        let newer = text.replacen("miv-undo 1", "miv-undo 2", 1);
+        assert!(matches!(
+            deserialize(&written, &newer),
+            Err(UndoFileError::VersionError(2))
+        ));
+        assert!(deserialize(&written, &text[..text.len() - 8]).is_err());
+        // edits that do not fit the text, or a redo child that is not a child
+        let bad_range = text.replacen("\n3 4 1:\n", "\n3 9 1:\n", 1);
+        assert_ne!(bad_range, text);
+        assert!(deserialize(&written, &bad_range).is_err());
+        let bad_child = text.replacen("\n - 1 ", "\n - 2 ", 1);
+        assert_ne!(bad_child, text);
+        assert!(deserialize(&written, &bad_child).is_err());
+        // a time whose nanoseconds would carry over into (and overflow) the seconds
+        let time_line = text.lines().find(|line| line.starts_with(" - 1 ")).unwrap();
+        let bad_time = text.replacen(time_line, " - 1 18446744073709551615 1000000000", 1);
+        assert!(matches!(
+            deserialize(&written, &bad_time),
+            Err(UndoFileError::CorruptUndoFileError)
+        ));
+    }
+}
@@ -0,0 +1,474 @@
+use crate::editor_buffer::Buffer;
+use crate::editor_state::{EditorMode, EditorState, EditorStateUpdate};
+use crate::selections::{Selection, Selections};
+use std::ops::{Range, RangeInclusive};
+
+/// What the selections of visual mode cover: the characters from anchor to head (`v`), the whole
+/// lines they touch (`V`) or the block between their columns (<C-v>)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum VisualKind {
+    #[default]
+    Charwise,
+    Linewise,
+    Blockwise,
+}
+
+/// What `gv` needs besides the `'<` and `'>` marks to select the last visual area again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LastVisual {
+    pub(crate) kind: VisualKind,
+    /// the head was at `'<`, the selection was made backwards
+    pub(crate) backwards: bool,
+    pub(crate) desired_column: usize,
+}
+
+/// The kind of visual mode the editor is in (charwise outside of visual mode)
+pub(crate) fn kind(ed_state: &EditorState) -> VisualKind {
+    match ed_state.mode {
+        EditorMode::Visual(kind) => kind,
+        _ => VisualKind::Charwise,
+    }
+}
+
+/// The lines a selection touches
+pub(crate) fn lines(buffer: &Buffer, selection: &Selection) -> RangeInclusive<usize> {
+    buffer.position(selection.start()).0..=buffer.position(selection.end()).0
+}
+
+/// The columns a block selection covers. The end is exclusive, `usize::MAX` after `$` (the block
+/// then reaches to the end of every line).
+pub(crate) fn block_columns(buffer: &Buffer, selection: &Selection) -> Range<usize> {
+    let (_, anchor_column) = buffer.position(selection.anchor);
+    let (_, head_column) = buffer.position(selection.head);
+    let end = if selection.desired_column == usize::MAX {
+        usize::MAX
+    } else {
+        anchor_column.max(head_column) + 1
+    };
+    anchor_column.min(head_column)..end
+}
+
+/// The text a block selection covers, one range per line (empty on lines that are too short to
+/// reach the block)
+pub(crate) fn block_ranges(buffer: &Buffer, selection: &Selection) -> Vec<Range<usize>> {
+    let columns = block_columns(buffer, selection);
+    lines(buffer, selection)
+        .map(|line| {
+            let start = buffer.line_offset(line);
+            let end = buffer.line_end(start);
+            (start + columns.start).min(end)..start.saturating_add(columns.end).min(end)
+        })
+        .collect()
+}
+
+/// The lines touched by the selections as (first, last), lines shared by several selections are
+/// merged into one span
+pub(crate) fn selected_lines(buffer: &Buffer) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for selection in buffer.selections.iter() {
+        let lines = lines(buffer, selection);
+        match spans.last_mut() {
+            Some(last) if *lines.start() <= last.1 => last.1 = last.1.max(*lines.end()),
+            _ => spans.push((*lines.start(), *lines.end())),
+        }
+    }
+    spans
+}
+
+/// The text the operators of visual mode work on, in ascending order: the characters of every
+/// selection, the selected lines (without the line break of their last lines) or the block of
+/// the primary selection
+pub(crate) fn selected_ranges(ed_state: &EditorState) -> Vec<Range<usize>> {
+    let buffer = ed_state.get_buffer();
+    match kind(ed_state) {
+        VisualKind::Charwise => buffer
+            .selections
+            .iter()
+            .map(|selection| selection.start()..(selection.end() + 1).min(buffer.len()))
+            .collect(),
+        VisualKind::Linewise => selected_lines(buffer)
+            .into_iter()
+            .map(|(first, last)| {
+                buffer.line_offset(first)..buffer.line_end(buffer.line_offset(last))
+            })
+            .collect(),
+        VisualKind::Blockwise => block_ranges(buffer, &buffer.selections.primary()),
+    }
+}
+
+/// What is drawn highlighted in visual mode, selected lines include their line breaks
+pub(crate) fn highlighted_ranges(ed_state: &EditorState) -> Vec<Range<usize>> {
+    let EditorMode::Visual(kind) = ed_state.mode else {
+        return Vec::new();
+    };
+    let buffer = ed_state.get_buffer();
+    let mut ranges = selected_ranges(ed_state);
+    if kind == VisualKind::Linewise {
+        for range in &mut ranges {
+            range.end = (range.end + 1).min(buffer.len());
+        }
+    }
+    ranges
+}
+
+/// Where the cursor goes when an operator is done with `selection`: its start, or the top left
+/// corner of a block
+pub(crate) fn start(buffer: &Buffer, selection: &Selection, kind: VisualKind) -> usize {
+    match kind {
+        VisualKind::Blockwise => {
+            let first_line = buffer.position(selection.start()).0;
+            buffer.offset(first_line, block_columns(buffer, selection).start)
+        }
+        _ => selection.start(),
+    }
+}
+
+/// Leaves visual mode with the cursors at the starts of the selections (moved along with the edits
+/// of `transaction` in front of them)
+pub(crate) fn back_to_normal(
+    ed_state: &EditorState,
+    transaction: &mut crate::transaction::Transaction,
+) {
+    let buffer = ed_state.get_buffer();
+    let kind = kind(ed_state);
+    let mut selections = buffer.selections.clone();
+    if kind == VisualKind::Blockwise {
+        selections = Selections::single(selections.primary());
+    }
+    selections.map(|selection| {
+        let offset = crate::text_motions::clamp_to_line(buffer, start(buffer, &selection, kind));
+        let column = buffer.position(offset).1;
+        Selection::cursor(transaction.shifted(ed_state.buffer_idx, offset), column)
+    });
+    transaction.selections(selections).mode(EditorMode::Normal);
+}
+
+/// `v`, `V` and <C-v>: enters visual mode of `kind`, switches to it from another kind of visual
+/// mode or leaves visual mode if it already is of that kind
+pub(crate) fn toggle(ed_state: &EditorState, kind: VisualKind) -> EditorStateUpdate {
+    match ed_state.mode {
+        EditorMode::Visual(current) if current == kind => crate::selections::leave_visual(ed_state),
+        _ if kind == VisualKind::Blockwise => {
+            // blocks of several cursors would run into each other
+            let primary = ed_state.get_buffer().selections.primary();
+            let mut transaction = crate::transaction::Transaction::new();
+            transaction
+                .selections(Selections::single(primary))
+                .mode(EditorMode::Visual(kind));
+            EditorStateUpdate::Transaction(transaction)
+        }
+        _ => EditorStateUpdate::Mode(EditorMode::Visual(kind)),
+    }
+}
+
+/// `o` moves the cursors to the other ends of the selections
+pub(crate) fn swap_ends(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let kind = kind(ed_state);
+    let mut selections = buffer.selections.clone();
+    selections.map(|selection| {
+        // a block stays stretched to the line ends after `$`
+        let desired_column = match kind {
+            VisualKind::Blockwise if selection.desired_column == usize::MAX => usize::MAX,
+            _ => buffer.position(selection.anchor).1,
+        };
+        Selection {
+            anchor: selection.head,
+            head: selection.anchor,
+            desired_column,
+        }
+    });
+    let mut transaction = crate::transaction::Transaction::new();
+    transaction.selections(selections);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// `gv` selects the last visual area of the buffer again, with the kind of visual mode it had
+pub(crate) fn reselect(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let mark = |mark| {
+        buffer
+            .marks
+            .get(&mark)
+            .and_then(|&anchor| buffer.anchors.get(anchor))
+    };
+    let mut transaction = crate::transaction::Transaction::new();
+    let (Some(last), Some(start), Some(end)) = (buffer.last_visual, mark('<'), mark('>')) else {
+        transaction.message("No previous visual selection");
+        return EditorStateUpdate::Transaction(transaction);
+    };
+    let (anchor, head) = if last.backwards {
+        (end, start)
+    } else {
+        (start, end)
+    };
+    transaction
+        .selections(Selections::single(Selection {
+            anchor,
+            head,
+            desired_column: last.desired_column,
+        }))
+        .mode(EditorMode::Visual(last.kind));
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// Visual `c`: deletes the selected text (leaving the selected lines empty) into the register and
+/// inserts in its place. In a block the text typed goes into every line that reaches the block.
+pub(crate) fn change(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let kind = kind(ed_state);
+    let (register, _) = crate::registers::selected_text(ed_state);
+    let ranges = selected_ranges(ed_state);
+    let mut transaction = crate::transaction::Transaction::new();
+    transaction.delete_into(ed_state.selected_register, register);
+    for range in ranges.iter().rev() {
+        transaction.delete(ed_state.buffer_idx, range.clone());
+    }
+    let mut cursors = ranges.iter().map(|range| range.start).collect::<Vec<_>>();
+    if kind == VisualKind::Blockwise {
+        let left = block_columns(buffer, &buffer.selections.primary()).start;
+        cursors.retain(|&offset| offset - buffer.line_start(offset) == left);
+        if cursors.is_empty() {
+            cursors.push(ranges[0].start);
+        }
+    }
+    let cursors = cursors
+        .into_iter()
+        .map(|offset| {
+            Selection::cursor(
+                transaction.shifted(ed_state.buffer_idx, offset),
+                offset - buffer.line_start(offset),
+            )
+        })
+        .collect::<Vec<_>>();
+    let primary = buffer.selections.primary_index().min(cursors.len() - 1);
+    transaction
+        .selections(Selections::new(cursors, primary))
+        .mode(EditorMode::Insert);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// Visual `I` and `A`: inserts in front of (behind, if `append`) the selections. In a block every
+/// line gets a cursor at the left (right) edge, so the text typed goes into all of them: lines
+/// that are too short are skipped by `I` and padded with spaces by `A`.
+pub(crate) fn insert(ed_state: &EditorState, append: bool) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let mut transaction = crate::transaction::Transaction::new();
+    let mut cursors = Vec::new();
+    match kind(ed_state) {
+        VisualKind::Blockwise => {
+            let selection = buffer.selections.primary();
+            let columns = block_columns(buffer, &selection);
+            // from the bottom up, so the padding does not move the lines in front
+            for line in lines(buffer, &selection).rev() {
+                let start = buffer.line_offset(line);
+                let len = buffer.line_end(start) - start;
+                if append {
+                    // after `$` the text goes to the end of every line
+                    if columns.end != usize::MAX && len < columns.end {
+                        transaction.insert(
+                            ed_state.buffer_idx,
+                            start + len,
+                            &" ".repeat(columns.end - len),
+                        );
+                    }
+                    cursors.push(start + len.min(columns.end));
+                } else if len >= columns.start {
+                    cursors.push(start + columns.start);
+                }
+            }
+            if cursors.is_empty() {
+                cursors.push(buffer.offset(*lines(buffer, &selection).start(), columns.start));
+            }
+            // the top line gets the primary cursor
+            cursors.reverse();
+        }
+        VisualKind::Linewise => {
+            for &(first, last) in &selected_lines(buffer) {
+                cursors.push(if append {
+                    buffer.line_end(buffer.line_offset(last))
+                } else {
+                    crate::text_motions::first_non_blank(buffer, buffer.line_offset(first))
+                });
+            }
+        }
+        VisualKind::Charwise => {
+            for selection in buffer.selections.iter() {
+                cursors.push(if append {
+                    (selection.end() + 1).min(buffer.line_end(selection.end()))
+                } else {
+                    selection.start()
+                });
+            }
+        }
+    }
+    let cursors = cursors
+        .into_iter()
+        .map(|offset| {
+            let offset = transaction.shifted(ed_state.buffer_idx, offset);
+            Selection::cursor(offset, offset - buffer.line_start(offset))
+        })
+        .collect::<Vec<_>>();
+    let primary = buffer.selections.primary_index().min(cursors.len() - 1);
+    transaction
+        .selections(Selections::new(cursors, primary))
+        .mode(EditorMode::Insert);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+/// Visual `>` and `<`: shifts the selected lines right (or left) by `count` levels of
+/// `shiftwidth`
+pub(crate) fn shift_lines(ed_state: &EditorState, right: bool) -> EditorStateUpdate {
+    let spans = selected_lines(ed_state.get_buffer());
+    crate::indent::shift(ed_state, &spans, right, ed_state.count_or_one())
+}
+
+/// Replaces every selected character (but the line breaks) with what `f` makes of it, then goes
+/// back to normal mode
+fn map_selected(ed_state: &EditorState, f: impl Fn(char) -> char) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let mut transaction = crate::transaction::Transaction::new();
+    for range in selected_ranges(ed_state).into_iter().rev() {
+        let old = buffer.content.slice(range.clone());
+        let new = old
+            .iter()
+            .map(|&c| if c == '\n' { c } else { f(c) })
+            .collect::<Vec<_>>();
+        if new != old {
+            transaction.replace(ed_state.buffer_idx, range, &new.iter().collect::<String>());
+        }
+    }
+    back_to_normal(ed_state, &mut transaction);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CaseChange {
+    Toggle,
+    Lower,
+    Upper,
+}
+
+/// `c` in the other case, characters whose other case is more than one character (like `ß`) are
+/// kept so the text keeps its length
+fn convert_case(c: char, case: CaseChange) -> char {
+    let upper = match case {
+        CaseChange::Toggle => c.is_lowercase(),
+        CaseChange::Lower => false,
+        CaseChange::Upper => true,
+    };
+    let converted = if upper {
+        c.to_uppercase().collect::<Vec<_>>()
+    } else {
+        c.to_lowercase().collect()
+    };
+    match converted[..] {
+        [converted] => converted,
+        _ => c,
+    }
+}
+
+/// Visual `~`, `u` and `U`
+pub(crate) fn change_case(ed_state: &EditorState, case: CaseChange) -> EditorStateUpdate {
+    map_selected(ed_state, |c| convert_case(c, case))
+}
+
+/// Visual `r{char}`
+pub(crate) fn replace_selected(ed_state: &EditorState, replacement: char) -> EditorStateUpdate {
+    map_selected(ed_state, |_| replacement)
+}
+
+/// Visual `J`: joins the selected lines (a single line with the one below it). The leading
+/// whitespace of the joined lines becomes a single space, none is added in front of an empty line
+/// or a `)` or behind whitespace.
+pub(crate) fn join_lines(ed_state: &EditorState) -> EditorStateUpdate {
+    let buffer = ed_state.get_buffer();
+    let spans = selected_lines(buffer);
+    let mut transaction = crate::transaction::Transaction::new();
+    let mut cursors = Vec::new();
+    for &(first, last) in spans.iter().rev() {
+        let last = last.max(first + 1).min(buffer.line_count() - 1);
+        let mut cursor = None;
+        for line in (first..last).rev() {
+            let start = buffer.line_offset(line);
+            let end = buffer.line_end(start);
+            let blanks = buffer
+                .content
+                .iter()
+                .skip(end + 1)
+                .take_while(|&&c| c == ' ' || c == '\t')
+                .count();
+            let next = buffer.char_at(end + 1 + blanks);
+            let no_space = end == start
+                || matches!(next, None | Some('\n' | ')'))
+                || matches!(buffer.char_at(end - 1), Some(' ' | '\t'));
+            let space = if no_space { "" } else { " " };
+            transaction.replace(ed_state.buffer_idx, end..end + 1 + blanks, space);
+            // the cursor goes where the last lines were joined
+            if cursor.is_none() {
+                let at_line_end = no_space && matches!(next, None | Some('\n')) && end > start;
+                cursor = Some(end - at_line_end as usize);
+            }
+        }
+        cursors.extend(cursor.map(|cursor| (cursor, buffer.line_offset(first))));
+    }
+    let mut cursors = cursors
+        .into_iter()
+        .map(|(offset, line_start)| {
+            let offset = transaction.shifted(ed_state.buffer_idx, offset);
+            let line_start = transaction.shifted(ed_state.buffer_idx, line_start);
+            Selection::cursor(offset, offset - line_start)
+        })
+        .collect::<Vec<_>>();
+    if cursors.is_empty() {
+        // only the last line was selected, there is nothing to join it with
+        back_to_normal(ed_state, &mut transaction);
+        return EditorStateUpdate::Transaction(transaction);
+    }
+    cursors.reverse();
+    transaction
+        .selections(Selections::new(cursors, 0))
+        .mode(EditorMode::Normal);
+    EditorStateUpdate::Transaction(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_case() {
+        assert_eq!(convert_case('a', CaseChange::Toggle), 'A');
+        assert_eq!(convert_case('B', CaseChange::Toggle), 'b');
+        assert_eq!(convert_case('1', CaseChange::Toggle), '1');
+        assert_eq!(convert_case('A', CaseChange::Lower), 'a');
+        assert_eq!(convert_case('a', CaseChange::Lower), 'a');
+        assert_eq!(convert_case('é', CaseChange::Upper), 'É');
+        // the uppercase of `ß` is `SS`, which would change the length of the text
+        assert_eq!(convert_case('ß', CaseChange::Upper), 'ß');
+        assert_eq!(convert_case('ß', CaseChange::Toggle), 'ß');
+    }
+    #[test]
+    fn test_block_ranges() {
+        let buffer = Buffer::from_text("abcd\nx\nefgh");
+        let selection = Selection {
+            anchor: 1,
+            head: 9,
+            desired_column: 2,
+        };
+        // the short line in between is not reached by the block
+        assert_eq!(block_ranges(&buffer, &selection), vec![1..3, 6..6, 8..10]);
+        let selection = Selection {
+            desired_column: usize::MAX,
+            ..selection
+        };
+        assert_eq!(block_ranges(&buffer, &selection), vec![1..4, 6..6, 8..11]);
+        // the columns are the same no matter which end is the head
+        let selection = Selection {
+            anchor: 9,
+            head: 1,
+            desired_column: 1,
+        };
+        assert_eq!(block_ranges(&buffer, &selection), vec![1..3, 6..6, 8..10]);
+    }
+}